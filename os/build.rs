@@ -1,13 +1,18 @@
-use std::fs::{read_dir, File};
+use std::fs::{read_dir, read_to_string, write, File};
 use std::io::{Result, Write};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-changed={}", KERNEL_SYMS_PATH);
     insert_app_data().unwrap();
+    insert_kernel_symbols().unwrap();
 }
 
 static TARGET_PATH: &str = "../user/target/riscv64gc-unknown-none-elf/release/";
+/// 由Makefile在第一次链接后用nm导出的内核代码段符号
+static KERNEL_SYMS_PATH: &str = "target/riscv64gc-unknown-none-elf/release/os.syms";
+static KSYMS_ASM: &str = "src/ksyms.S";
 
 fn insert_app_data() -> Result<()> {
     let mut f = File::create("src/link_app.S").unwrap();
@@ -65,3 +70,59 @@ app_{0}_end:"#,
     }
     Ok(())
 }
+
+/// 把nm导出的代码段符号按地址排序后生成内核符号表，供backtrace符号化。
+/// 第一次构建时还没有符号文件，生成一张空表。
+/// 内容不变时不改写ksyms.S，以免重新编译内核
+fn insert_kernel_symbols() -> Result<()> {
+    let mut symbols: Vec<(usize, String)> = read_to_string(KERNEL_SYMS_PATH)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            // 每行为“地址 类型 名字”，demangle后的名字中可能含有空格
+            let mut fields = line.splitn(3, ' ');
+            let addr = usize::from_str_radix(fields.next()?, 16).ok()?;
+            let kind = fields.next()?;
+            let name = fields.next()?;
+            if !matches!(kind, "t" | "T") || name.starts_with(".L") || name.starts_with('$') {
+                return None;
+            }
+            Some((addr, strip_hash(name).to_string()))
+        })
+        .collect();
+    symbols.sort();
+    symbols.dedup_by_key(|(addr, _)| *addr);
+
+    let mut asm = String::new();
+    asm.push_str(&format!(
+        r#"
+    .section .ksyms, "a"
+    .align 3
+    .global _ksyms
+_ksyms:
+    .quad {}
+"#,
+        symbols.len()
+    ));
+    for (idx, (addr, _)) in symbols.iter().enumerate() {
+        asm.push_str(&format!("    .quad {:#x}, ksym_{}\n", addr, idx));
+    }
+    for (idx, (_, name)) in symbols.iter().enumerate() {
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        asm.push_str(&format!("ksym_{}:\n    .string \"{}\"\n", idx, name));
+    }
+    if read_to_string(KSYMS_ASM).ok().as_deref() != Some(asm.as_str()) {
+        write(KSYMS_ASM, asm)?;
+    }
+    Ok(())
+}
+
+/// 去掉Rust符号末尾形如::h0123456789abcdef的哈希
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => name,
+    }
+}
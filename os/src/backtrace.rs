@@ -0,0 +1,156 @@
+//! 基于帧指针的栈回溯。内核与用户程序都以-Cforce-frame-pointers=yes编译，
+//! 每个栈帧中fp-8处保存返回地址，fp-16处保存调用者的fp。
+//! 内核地址用构建时从内核ELF导出的符号表（见build.rs）符号化
+use crate::config::{
+    kernel_stack_position, BOOT_STACK_SIZE, KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE,
+};
+use crate::mm::UserPtr;
+use core::arch::asm;
+use core::fmt;
+use core::slice;
+use core::str;
+
+/// 最多打印的栈帧数，防止损坏的栈导致无限循环
+const MAX_FRAMES: usize = 32;
+
+/// 符号表中的一项，按地址升序排列，name是以0结尾的字符串
+#[repr(C)]
+struct KernelSymbol {
+    addr: usize,
+    name: *const u8,
+}
+
+fn kernel_symbols() -> &'static [KernelSymbol] {
+    extern "C" {
+        fn _ksyms();
+    }
+    // 第一个usize是符号数，其后紧跟着各项
+    unsafe {
+        let base = _ksyms as usize as *const usize;
+        slice::from_raw_parts(base.add(1) as *const KernelSymbol, *base)
+    }
+}
+
+/// 查找包含addr的内核函数，返回函数名与addr在其中的偏移
+fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    extern "C" {
+        fn etext();
+    }
+    if addr >= etext as usize {
+        return None;
+    }
+    let symbols = kernel_symbols();
+    let idx = symbols.partition_point(|symbol| symbol.addr <= addr);
+    let symbol = symbols.get(idx.checked_sub(1)?)?;
+    let name = unsafe {
+        let mut len = 0;
+        while *symbol.name.add(len) != 0 {
+            len += 1;
+        }
+        str::from_utf8_unchecked(slice::from_raw_parts(symbol.name, len))
+    };
+    Some((name, addr - symbol.addr))
+}
+
+/// 以“地址 函数名+偏移”的形式显示一个内核代码地址，找不到符号时只显示地址
+pub struct Symbol(pub usize);
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match lookup(self.0) {
+            Some((name, offset)) => write!(f, "{:#x} {}+{:#x}", self.0, name, offset),
+            None => write!(f, "{:#x}", self.0),
+        }
+    }
+}
+
+/// 包含addr的启动栈或内核栈的[bottom, top]，不在任何栈上时返回None
+fn stack_bounds(addr: usize) -> Option<(usize, usize)> {
+    extern "C" {
+        fn boot_stack();
+        fn boot_stack_top();
+    }
+    let (boot_bottom, boot_top) = (boot_stack as usize, boot_stack_top as usize);
+    if addr > boot_bottom && addr <= boot_top {
+        let bottom = boot_bottom + (addr - boot_bottom - 1) / BOOT_STACK_SIZE * BOOT_STACK_SIZE;
+        return Some((bottom, bottom + BOOT_STACK_SIZE));
+    }
+    if addr >= TRAMPOLINE {
+        return None;
+    }
+    // 各内核栈之间隔着一个保护页
+    let slot = (TRAMPOLINE - addr) / (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let (bottom, top) = kernel_stack_position(slot);
+    if addr > bottom && addr <= top {
+        Some((bottom, top))
+    } else {
+        None
+    }
+}
+
+/// 从帧指针fp开始沿调用链向高地址回溯，对每个返回地址调用f。
+/// read读取栈上的一个usize，无法读取时返回None。
+/// 遇到读不出、未对齐或不递增的帧指针就停止，因此不会陷入循环
+fn walk(fp: usize, read: impl Fn(usize) -> Option<usize>, mut f: impl FnMut(usize, usize)) {
+    let mut fp = fp;
+    for depth in 1..MAX_FRAMES {
+        if fp % 8 != 0 || fp < 16 {
+            break;
+        }
+        let (ra, prev_fp) = match (read(fp - 8), read(fp - 16)) {
+            (Some(ra), Some(prev_fp)) => (ra, prev_fp),
+            _ => break,
+        };
+        if ra == 0 {
+            break;
+        }
+        f(depth, ra);
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+}
+
+/// 打印内核调用链，只读取fp所在的那一个内核栈，因此不会访问未映射的地址
+fn print_kernel_frames(fp: usize) {
+    let (bottom, top) = match stack_bounds(fp) {
+        Some(bounds) => bounds,
+        None => {
+            println!("  (frame pointer {:#x} is not on a kernel stack)", fp);
+            return;
+        }
+    };
+    let read = |addr: usize| {
+        if addr >= bottom && addr + 8 <= top {
+            Some(unsafe { *(addr as *const usize) })
+        } else {
+            None
+        }
+    };
+    walk(fp, read, |depth, ra| {
+        println!("  #{:<2} {}", depth, Symbol(ra));
+    });
+}
+
+/// 打印调用本函数处的内核调用链，用于panic
+#[inline(never)]
+pub fn print_kernel_backtrace() {
+    let fp: usize;
+    unsafe {
+        asm!("mv {}, s0", out(reg) fp);
+    }
+    println!("[kernel] backtrace:");
+    print_kernel_frames(fp);
+}
+
+/// 打印用户程序的调用链：出错的pc以及fp开始的各个返回地址。
+/// 通过token对应的页表读取用户栈，只读取用户可读的页
+pub fn print_user_backtrace(token: usize, pc: usize, fp: usize) {
+    let read = |addr: usize| UserPtr::new(token, addr as *const usize).read().ok();
+    println!("[kernel] user backtrace:");
+    println!("  #0  {:#x}", pc);
+    walk(fp, read, |depth, ra| {
+        println!("  #{:<2} {:#x}", depth, ra);
+    });
+}
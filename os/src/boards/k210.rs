@@ -1 +1,17 @@
+use crate::drivers::chardev::SbiConsole;
+use crate::fdt::Device;
+
 pub const CLOCK_FREQ: usize = 403000000 / 62;
+
+// 以下为没有设备树时使用的默认值
+pub const MEMORY: (usize, usize) = (0x8000_0000, 0x8080_0000);
+/// K210上没有NS16550A，控制台仍然通过SBI访问
+pub const UART: Option<Device> = None;
+pub const PLIC: Option<Device> = None;
+pub const RTC: Option<Device> = None;
+
+pub type CharDeviceImpl = SbiConsole;
+
+pub fn console() -> CharDeviceImpl {
+    SbiConsole::new()
+}
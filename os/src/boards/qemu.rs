@@ -1 +1,29 @@
+use crate::drivers::chardev::NS16550a;
+use crate::fdt::{machine, Device};
+
 pub const CLOCK_FREQ: usize = 12500000;
+
+// 以下为没有设备树时使用的默认值
+pub const MEMORY: (usize, usize) = (0x8000_0000, 0x8080_0000);
+pub const UART: Option<Device> = Some(Device {
+    base: 0x1000_0000,
+    size: 0x100,
+    irq: 10,
+});
+pub const PLIC: Option<Device> = Some(Device {
+    base: 0x0c00_0000,
+    size: 0x40_0000,
+    irq: 0,
+});
+pub const RTC: Option<Device> = Some(Device {
+    base: 0x0010_1000,
+    size: 0x1000,
+    irq: 11,
+});
+
+pub type CharDeviceImpl = NS16550a;
+
+/// 创建控制台设备，使用设备树中的第一个NS16550A串口
+pub fn console() -> CharDeviceImpl {
+    NS16550a::new(machine().uart.expect("no ns16550a uart found").base)
+}
@@ -0,0 +1,103 @@
+//! 内核命令行，来自设备树/chosen节点的bootargs，在QEMU中由-append给出。
+//! 各项以空格分隔，形如key=value或单独的开关，不认识的项给出警告后忽略：
+//! init=程序名、loglevel=级别、sched=fifo|rr、quiet、tests=测例1,测例2、strace=程序1,程序2
+use crate::console::{parse_log_level, set_console_level};
+use crate::fdt::machine;
+use crate::loader::get_app_data_by_name;
+use crate::sync::{LockClass, UPSafeCell};
+use lazy_static::*;
+use log::LevelFilter;
+
+/// 用户任务的调度策略，两者都按FIFO顺序取出就绪任务
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// 时钟中断时切换到下一个就绪任务
+    RoundRobin,
+    /// 任务一直运行到它阻塞、让出处理器或退出，不会因时钟中断被切换
+    Fifo,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Cmdline {
+    /// 第一个用户进程
+    pub init: &'static str,
+    /// 覆盖构建时由LOG指定的日志级别
+    pub loglevel: Option<LevelFilter>,
+    pub sched: SchedPolicy,
+    /// 串口上只显示WARN及以上的日志，其余的仍写入日志缓冲区
+    pub quiet: bool,
+    /// 以逗号分隔的测例名，usertests只运行这些测例，为空时运行全部
+    pub tests: &'static str,
+    /// 以逗号分隔的程序名，启动或exec这些程序时把系统调用跟踪打印到串口
+    pub strace: &'static str,
+}
+
+impl Cmdline {
+    fn default() -> Self {
+        Self {
+            init: "initproc",
+            loglevel: None,
+            sched: SchedPolicy::RoundRobin,
+            quiet: false,
+            tests: "",
+            strace: "",
+        }
+    }
+    fn parse(bootargs: &'static str) -> Self {
+        let mut cmdline = Self::default();
+        for arg in bootargs.split_whitespace() {
+            match arg.split_once('=') {
+                Some(("init", name)) => cmdline.init = name,
+                Some(("loglevel", level)) => match parse_log_level(level) {
+                    Some(level) => cmdline.loglevel = Some(level),
+                    None => warn!("cmdline: invalid loglevel {}", level),
+                },
+                Some(("sched", "rr")) => cmdline.sched = SchedPolicy::RoundRobin,
+                Some(("sched", "fifo")) => cmdline.sched = SchedPolicy::Fifo,
+                Some(("tests", tests)) => cmdline.tests = tests,
+                Some(("strace", names)) => cmdline.strace = names,
+                None if arg == "quiet" => cmdline.quiet = true,
+                _ => warn!("cmdline: unknown parameter {}", arg),
+            }
+        }
+        cmdline
+    }
+    /// 程序name是否在strace=中列出
+    pub fn traced(&self, name: &str) -> bool {
+        self.strace.split(',').any(|traced| traced == name)
+    }
+}
+
+lazy_static! {
+    static ref CMDLINE: UPSafeCell<Cmdline> =
+        unsafe { UPSafeCell::new_with_class(Cmdline::default(), LockClass::Other) };
+}
+
+/// 返回解析得到的命令行选项
+pub fn cmdline() -> Cmdline {
+    *CMDLINE.exclusive_access()
+}
+
+/// 解析设备树给出的命令行并应用其中的日志选项，须在fdt::init与console::init之后调用
+pub fn init() {
+    let bootargs = machine().bootargs;
+    let cmdline = Cmdline::parse(bootargs);
+    if let Some(level) = cmdline.loglevel {
+        log::set_max_level(level);
+    }
+    if cmdline.quiet {
+        set_console_level(LevelFilter::Warn);
+    }
+    info!("command line: {}", bootargs);
+    for test in cmdline.tests.split(',').filter(|test| !test.is_empty()) {
+        if get_app_data_by_name(test).is_none() {
+            warn!("cmdline: no test named {}", test);
+        }
+    }
+    for name in cmdline.strace.split(',').filter(|name| !name.is_empty()) {
+        if get_app_data_by_name(name).is_none() {
+            warn!("cmdline: no program named {} to trace", name);
+        }
+    }
+    *CMDLINE.exclusive_access() = cmdline;
+}
@@ -1,16 +1,23 @@
-pub use crate::board::CLOCK_FREQ;
-
 pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
-pub const MEMORY_END: usize = 0x80800000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
+/// 支持的最大hart数，编号不小于它的hart不会被启动，须与entry.asm中的MAX_HARTS一致
+pub const MAX_HARTS: usize = 8;
+/// 每个hart的启动栈大小，须与entry.asm中的BOOT_STACK_SIZE一致
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
 
 /// Trampoline页面起始地址，最高的一个页面
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 /// TrapContext页面起始地址，次高的一个页面
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// 未指定挂接地址时，共享内存段从这个用户虚拟地址开始向上寻找空闲位置
+pub const USER_SHM_BASE: usize = 0x10_0000_0000;
+/// 未指定MAP_FIXED时，mmap从这个用户虚拟地址开始向上寻找空闲位置
+pub const USER_MMAP_BASE: usize = 0x20_0000_0000;
+/// SV39下用户可以自行选择的虚拟地址上限，更高的地址符号扩展后属于高半部分
+pub const USER_SPACE_END: usize = 1 << 38;
 
 /// Return (bottom, top) of a kernel stack in kernel space.
 /// 返回应用的**内核栈**在内核地址空间中的位置
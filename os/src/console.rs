@@ -1,19 +1,27 @@
-use crate::sbi::console_putchar;
+use crate::drivers::chardev::{CharDevice, UART};
+use crate::smp::hart_id;
+use crate::sync::{IrqSafeMutex, LockClass};
+use crate::timer::get_time_ns;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record};
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+        for ch in s.bytes() {
+            UART.write(ch);
         }
         Ok(())
     }
 }
 
+/// 先将格式化的结果写入串口的发送缓冲区，再一次性交给硬件
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
+    UART.flush();
 }
 
 #[macro_export]
@@ -29,3 +37,168 @@ macro_rules! println {
         $crate::console::print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
     }
 }
+
+/// 日志环形缓冲区的大小
+const LOG_BUF_SIZE: usize = 16 * 1024;
+
+/// 保存最近的日志，写满后覆盖最旧的内容，用户程序通过syslog系统调用读取
+struct LogBuffer {
+    buf: [u8; LOG_BUF_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LOG_BUF_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn push(&mut self, byte: u8) {
+        self.buf[(self.head + self.len) % LOG_BUF_SIZE] = byte;
+        if self.len == LOG_BUF_SIZE {
+            self.head = (self.head + 1) % LOG_BUF_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+}
+
+static LOG_BUFFER: IrqSafeMutex<LogBuffer> =
+    IrqSafeMutex::new_with_class(LogBuffer::new(), LockClass::Log);
+
+/// 按时间顺序取出缓冲区中最后的至多len个字节
+pub fn read_log(len: usize) -> Vec<u8> {
+    let buffer = LOG_BUFFER.lock();
+    let len = len.min(buffer.len);
+    let start = buffer.head + buffer.len - len;
+    (start..start + len)
+        .map(|idx| buffer.buf[idx % LOG_BUF_SIZE])
+        .collect()
+}
+
+/// 按时间顺序取出缓冲区中最旧的至多len个字节，并只从缓冲区中移除这些字节，
+/// 读取与移除在同一次加锁内完成，期间写入的日志不会丢失
+pub fn take_log(len: usize) -> Vec<u8> {
+    let mut buffer = LOG_BUFFER.lock();
+    let len = len.min(buffer.len);
+    let start = buffer.head;
+    let log = (start..start + len)
+        .map(|idx| buffer.buf[idx % LOG_BUF_SIZE])
+        .collect();
+    buffer.head = (start + len) % LOG_BUF_SIZE;
+    buffer.len -= len;
+    log
+}
+
+/// 清空日志缓冲区
+pub fn clear_log() {
+    let mut buffer = LOG_BUFFER.lock();
+    buffer.head = 0;
+    buffer.len = 0;
+}
+
+/// 日志缓冲区的容量
+pub fn log_buffer_size() -> usize {
+    LOG_BUF_SIZE
+}
+
+/// 串口上显示的最低日志级别，更低级别的日志只写入缓冲区
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Trace as usize);
+
+/// 设置串口上显示的最低日志级别，用于命令行中的quiet
+pub fn set_console_level(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// 把一条日志写入缓冲区，console为真时同时写入串口
+struct LogWriter<'a> {
+    buffer: &'a mut LogBuffer,
+    console: bool,
+}
+
+impl Write for LogWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.bytes() {
+            self.buffer.push(ch);
+            if self.console {
+                UART.write(ch);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// log门面的后端，每条日志带有开机以来的时间与所在的hart，在串口上按级别着色
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let color = match record.level() {
+            Level::Error => 31,
+            Level::Warn => 93,
+            Level::Info => 34,
+            Level::Debug => 32,
+            Level::Trace => 90,
+        };
+        let console = record.level() as usize <= CONSOLE_LEVEL.load(Ordering::Relaxed);
+        let ns = get_time_ns();
+        let mut buffer = LOG_BUFFER.lock();
+        if console {
+            Stdout.write_fmt(format_args!("\x1b[{}m", color)).unwrap();
+        }
+        LogWriter {
+            buffer: &mut buffer,
+            console,
+        }
+        .write_fmt(format_args!(
+            "[{:>5}.{:06}][hart {}] {:<5} {}\n",
+            ns / 1_000_000_000,
+            ns % 1_000_000_000 / 1000,
+            hart_id(),
+            record.level(),
+            record.args()
+        ))
+        .unwrap();
+        if console {
+            Stdout.write_str("\x1b[0m").unwrap();
+            UART.flush();
+        }
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+/// 解析error、warn、info、debug、trace或off形式的日志级别，不区分大小写
+pub fn parse_log_level(name: &str) -> Option<LevelFilter> {
+    [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ]
+    .into_iter()
+    .find(|level| level.as_str().eq_ignore_ascii_case(name))
+}
+
+/// 注册日志后端，须在串口初始化之后调用。
+/// 日志级别由构建时的环境变量LOG指定，默认为INFO，命令行中的loglevel可以再覆盖它
+pub fn init() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(
+        option_env!("LOG")
+            .and_then(parse_log_level)
+            .unwrap_or(LevelFilter::Info),
+    );
+}
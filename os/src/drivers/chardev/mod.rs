@@ -0,0 +1,38 @@
+//! 字符设备，目前只有作为控制台使用的串口
+#[cfg(not(feature = "board_k210"))]
+mod ns16550a;
+#[cfg(feature = "board_k210")]
+mod sbi;
+
+use crate::board::CharDeviceImpl;
+use crate::sync::WaitQueue;
+use lazy_static::*;
+
+#[cfg(not(feature = "board_k210"))]
+pub use ns16550a::NS16550a;
+#[cfg(feature = "board_k210")]
+pub use sbi::SbiConsole;
+
+pub trait CharDevice {
+    /// 初始化设备，开启接收中断
+    fn init(&self);
+    /// 读取一个字节，没有输入时阻塞当前任务
+    fn read(&self) -> u8;
+    /// 非阻塞地读取一个字节
+    fn try_read(&self) -> Option<u8>;
+    /// 是否有尚未读取的输入
+    fn has_input(&self) -> bool;
+    /// 将一个字节放入发送缓冲区
+    fn write(&self, ch: u8);
+    /// 等待发送缓冲区中的数据全部交给硬件
+    fn flush(&self);
+    /// 处理设备中断：取出硬件中收到的数据并唤醒等待输入的任务
+    fn handle_irq(&self);
+    /// 有新的输入时会被唤醒的等待队列，不支持时返回None
+    fn wait_queue(&self) -> Option<&WaitQueue>;
+}
+
+lazy_static! {
+    /// 控制台串口，构造时不分配堆内存，在堆初始化之前也可以输出
+    pub static ref UART: CharDeviceImpl = crate::board::console();
+}
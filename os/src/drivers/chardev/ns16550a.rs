@@ -0,0 +1,172 @@
+//! QEMU virt平台上的NS16550A串口驱动
+use super::CharDevice;
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+
+/// 寄存器相对于基地址的偏移
+const RBR: usize = 0; // 接收缓冲（读）
+const THR: usize = 0; // 发送保持（写）
+const IER: usize = 1; // 中断使能
+const FCR: usize = 2; // FIFO控制（写）
+const LCR: usize = 3; // 线路控制
+const MCR: usize = 4; // Modem控制
+const LSR: usize = 5; // 线路状态
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+/// 使能并清空收发FIFO
+const FCR_ENABLE_AND_CLEAR: u8 = 0b111;
+/// 8位数据位，1位停止位，无校验
+const LCR_8N1: u8 = 0b11;
+/// DTR | RTS | OUT2，OUT2使中断信号能够送出芯片
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+/// 发送FIFO的深度，THR为空时可以连续写入这么多字节
+const TX_FIFO_SIZE: usize = 16;
+
+const RX_BUF_SIZE: usize = 256;
+const TX_BUF_SIZE: usize = 1024;
+
+/// 定长的环形缓冲区，满时丢弃新写入的数据
+struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn push(&mut self, ch: u8) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.buf[(self.head + self.len) % N] = ch;
+        self.len += 1;
+        true
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let ch = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(ch)
+    }
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+}
+
+struct NS16550aInner {
+    rx: RingBuffer<RX_BUF_SIZE>,
+    tx: RingBuffer<TX_BUF_SIZE>,
+}
+
+pub struct NS16550a {
+    base_addr: usize,
+    inner: UPSafeCell<NS16550aInner>,
+    /// 等待输入的任务
+    wait_queue: WaitQueue,
+}
+
+impl NS16550a {
+    pub fn new(base_addr: usize) -> Self {
+        Self {
+            base_addr,
+            inner: unsafe {
+                UPSafeCell::new_with_class(
+                    NS16550aInner {
+                        rx: RingBuffer::new(),
+                        tx: RingBuffer::new(),
+                    },
+                    LockClass::Uart,
+                )
+            },
+            wait_queue: WaitQueue::new(),
+        }
+    }
+    fn read_reg(&self, offset: usize) -> u8 {
+        unsafe { ((self.base_addr + offset) as *const u8).read_volatile() }
+    }
+    fn write_reg(&self, offset: usize, value: u8) {
+        unsafe { ((self.base_addr + offset) as *mut u8).write_volatile(value) }
+    }
+    /// 将硬件接收FIFO中的数据全部搬到rx缓冲区，返回是否收到了数据
+    fn receive(&self, inner: &mut NS16550aInner) -> bool {
+        let mut received = false;
+        while self.read_reg(LSR) & LSR_DATA_READY != 0 {
+            inner.rx.push(self.read_reg(RBR));
+            received = true;
+        }
+        received
+    }
+    /// 在THR为空时将tx缓冲区中的数据写入发送FIFO，返回tx缓冲区是否已经清空
+    fn transmit(&self, inner: &mut NS16550aInner) -> bool {
+        if self.read_reg(LSR) & LSR_THR_EMPTY != 0 {
+            for _ in 0..TX_FIFO_SIZE {
+                match inner.tx.pop() {
+                    Some(ch) => self.write_reg(THR, ch),
+                    None => break,
+                }
+            }
+        }
+        inner.tx.is_empty()
+    }
+}
+
+impl CharDevice for NS16550a {
+    fn init(&self) {
+        self.write_reg(IER, 0);
+        self.write_reg(FCR, FCR_ENABLE_AND_CLEAR);
+        self.write_reg(LCR, LCR_8N1);
+        self.write_reg(MCR, MCR_DTR_RTS_OUT2);
+        self.write_reg(IER, IER_RX_AVAILABLE);
+    }
+    fn read(&self) -> u8 {
+        loop {
+            if let Some(ch) = self.try_read() {
+                return ch;
+            }
+            self.wait_queue.wait(None);
+        }
+    }
+    fn try_read(&self) -> Option<u8> {
+        let mut inner = self.inner.exclusive_access();
+        self.receive(&mut inner);
+        inner.rx.pop()
+    }
+    fn has_input(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        self.receive(&mut inner);
+        !inner.rx.is_empty()
+    }
+    fn write(&self, ch: u8) {
+        let mut inner = self.inner.exclusive_access();
+        while inner.tx.is_full() {
+            self.transmit(&mut inner);
+        }
+        inner.tx.push(ch);
+    }
+    fn flush(&self) {
+        let mut inner = self.inner.exclusive_access();
+        while !self.transmit(&mut inner) {}
+    }
+    fn handle_irq(&self) {
+        let received = self.receive(&mut self.inner.exclusive_access());
+        if received {
+            self.wait_queue.wake_all();
+        }
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
+}
@@ -0,0 +1,57 @@
+use super::CharDevice;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::task::suspend_current_and_run_next;
+
+/// 通过SBI访问的控制台，输入只能轮询
+pub struct SbiConsole {
+    /// has_input时从SBI预先取出、尚未被读走的字符
+    pending: UPSafeCell<Option<u8>>,
+}
+
+impl SbiConsole {
+    pub fn new() -> Self {
+        Self {
+            pending: unsafe { UPSafeCell::new_with_class(None, LockClass::Uart) },
+        }
+    }
+}
+
+impl CharDevice for SbiConsole {
+    fn init(&self) {}
+    fn read(&self) -> u8 {
+        loop {
+            match self.try_read() {
+                Some(ch) => return ch,
+                None => suspend_current_and_run_next(),
+            }
+        }
+    }
+    fn try_read(&self) -> Option<u8> {
+        if let Some(ch) = self.pending.exclusive_access().take() {
+            return Some(ch);
+        }
+        match console_getchar() {
+            0 | usize::MAX => None,
+            c => Some(c as u8),
+        }
+    }
+    fn has_input(&self) -> bool {
+        let mut pending = self.pending.exclusive_access();
+        if pending.is_none() {
+            match console_getchar() {
+                0 | usize::MAX => {}
+                c => *pending = Some(c as u8),
+            }
+        }
+        pending.is_some()
+    }
+    fn write(&self, ch: u8) {
+        console_putchar(ch as usize);
+    }
+    fn flush(&self) {}
+    fn handle_irq(&self) {}
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        None
+    }
+}
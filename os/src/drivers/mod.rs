@@ -0,0 +1,63 @@
+//! 设备驱动
+pub mod chardev;
+pub mod plic;
+pub mod rtc;
+pub mod virtio;
+
+use crate::fdt::machine;
+use chardev::{CharDevice, UART};
+use plic::register_irq_handler;
+use virtio::{
+    MmioTransport, VirtIOGpu, VirtIOInput, VirtIONet, VirtIORng, DEVICE_ID_GPU, DEVICE_ID_INPUT,
+    DEVICE_ID_NET, DEVICE_ID_RNG,
+};
+
+/// 初始化中断控制器，探测virtio设备并注册各设备的中断处理函数。
+/// 没有PLIC时仍然探测全部设备，只是不注册中断，设备只能轮询
+pub fn init() {
+    let machine = machine();
+    let has_plic = machine.plic.is_some();
+    if has_plic {
+        plic::init();
+    }
+    let register_irq = |irq: usize, handler: fn()| {
+        if has_plic {
+            register_irq_handler(irq, handler);
+        }
+    };
+    if let Some(uart) = machine.uart {
+        register_irq(uart.irq, || UART.handle_irq());
+    }
+    for device in machine.virtio.iter().flatten() {
+        let transport = match MmioTransport::probe(device) {
+            Some(transport) => transport,
+            None => continue,
+        };
+        let irq = transport.irq;
+        match transport.device_id {
+            DEVICE_ID_NET => match VirtIONet::new(transport) {
+                Ok(net) => {
+                    crate::net::add_virtio(net);
+                    register_irq(irq, crate::net::handle_irq);
+                }
+                Err(err) => error!("virtio-net at {:#x}: {:?}", device.base, err),
+            },
+            DEVICE_ID_RNG => match VirtIORng::new(transport) {
+                Ok(rng) => crate::random::add_entropy_source(rng),
+                Err(err) => error!("virtio-rng at {:#x}: {:?}", device.base, err),
+            },
+            DEVICE_ID_GPU => match VirtIOGpu::new(transport) {
+                Ok(gpu) => crate::fs::add_framebuffer(gpu),
+                Err(err) => error!("virtio-gpu at {:#x}: {:?}", device.base, err),
+            },
+            DEVICE_ID_INPUT => match VirtIOInput::new(transport) {
+                Ok(input) => {
+                    crate::fs::add_input_device(input);
+                    register_irq(irq, crate::fs::handle_input_irq);
+                }
+                Err(err) => error!("virtio-input at {:#x}: {:?}", device.base, err),
+            },
+            id => warn!("unsupported virtio device {} at {:#x}", id, device.base),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! 平台级中断控制器（PLIC）驱动，以及外部中断号到设备处理函数的分发
+use crate::fdt::machine;
+use crate::smp::hart_id;
+use crate::sync::{LockClass, UPSafeCell};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// PLIC支持的中断源数目上限，0号中断源保留不用
+const IRQ_MAX: usize = 128;
+
+/// 中断目标所在的特权级，PLIC的中断目标（context）由hart与特权级共同确定
+#[derive(Copy, Clone)]
+pub enum PrivilegeMode {
+    Machine = 0,
+    Supervisor = 1,
+}
+
+pub struct Plic {
+    base_addr: usize,
+}
+
+impl Plic {
+    /// # Safety
+    /// base_addr必须是已经映射的PLIC寄存器基地址
+    pub const unsafe fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+    fn context(hart_id: usize, mode: PrivilegeMode) -> usize {
+        hart_id * 2 + mode as usize
+    }
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base_addr + offset) as *mut u32
+    }
+    fn priority_ptr(&self, irq: usize) -> *mut u32 {
+        assert!(irq > 0 && irq < IRQ_MAX);
+        self.reg(irq * 4)
+    }
+    fn enable_ptr(&self, hart_id: usize, mode: PrivilegeMode, irq: usize) -> (*mut u32, u32) {
+        let context = Self::context(hart_id, mode);
+        (
+            self.reg(0x2000 + 0x80 * context + (irq / 32) * 4),
+            1 << (irq % 32),
+        )
+    }
+    fn threshold_ptr(&self, hart_id: usize, mode: PrivilegeMode) -> *mut u32 {
+        self.reg(0x20_0000 + 0x1000 * Self::context(hart_id, mode))
+    }
+    fn claim_complete_ptr(&self, hart_id: usize, mode: PrivilegeMode) -> *mut u32 {
+        self.reg(0x20_0004 + 0x1000 * Self::context(hart_id, mode))
+    }
+    /// 设置中断源的优先级，优先级为0的中断源不会被送出
+    pub fn set_priority(&self, irq: usize, priority: u32) {
+        assert!(priority < 8);
+        unsafe { self.priority_ptr(irq).write_volatile(priority) }
+    }
+    pub fn enable(&self, hart_id: usize, mode: PrivilegeMode, irq: usize) {
+        let (ptr, mask) = self.enable_ptr(hart_id, mode, irq);
+        unsafe { ptr.write_volatile(ptr.read_volatile() | mask) }
+    }
+    pub fn disable(&self, hart_id: usize, mode: PrivilegeMode, irq: usize) {
+        let (ptr, mask) = self.enable_ptr(hart_id, mode, irq);
+        unsafe { ptr.write_volatile(ptr.read_volatile() & !mask) }
+    }
+    /// 只有优先级高于threshold的中断才会送到该目标
+    pub fn set_threshold(&self, hart_id: usize, mode: PrivilegeMode, threshold: u32) {
+        assert!(threshold < 8);
+        unsafe { self.threshold_ptr(hart_id, mode).write_volatile(threshold) }
+    }
+    /// 认领一个待处理的中断，返回中断号，0表示没有
+    pub fn claim(&self, hart_id: usize, mode: PrivilegeMode) -> usize {
+        unsafe { self.claim_complete_ptr(hart_id, mode).read_volatile() as usize }
+    }
+    /// 通知PLIC中断处理完毕
+    pub fn complete(&self, hart_id: usize, mode: PrivilegeMode, irq: usize) {
+        unsafe {
+            self.claim_complete_ptr(hart_id, mode)
+                .write_volatile(irq as u32)
+        }
+    }
+}
+
+/// 外部中断只送到调用init的启动hart，其余hart只处理时钟中断
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+lazy_static! {
+    pub static ref PLIC: Plic = unsafe { Plic::new(machine().plic.expect("no PLIC found").base) };
+    /// 各中断号对应的设备处理函数
+    static ref IRQ_HANDLERS: UPSafeCell<[Option<fn()>; IRQ_MAX]> =
+        unsafe { UPSafeCell::new_with_class([None; IRQ_MAX], LockClass::IrqHandlers) };
+}
+
+/// 只接收S态的外部中断，M态的中断目标全部屏蔽
+pub fn init() {
+    BOOT_HART.store(hart_id(), Ordering::Relaxed);
+    PLIC.set_threshold(boot_hart(), PrivilegeMode::Supervisor, 0);
+    PLIC.set_threshold(boot_hart(), PrivilegeMode::Machine, 1);
+}
+
+/// 注册irq号中断的处理函数，并在PLIC中打开该中断源。
+/// 设备树给出的中断号超出PLIC支持的范围时只打印警告，该设备收不到中断
+pub fn register_irq_handler(irq: usize, handler: fn()) {
+    if irq == 0 || irq >= IRQ_MAX {
+        warn!("irq {} out of range, handler not registered", irq);
+        return;
+    }
+    IRQ_HANDLERS.exclusive_access()[irq] = Some(handler);
+    PLIC.set_priority(irq, 1);
+    PLIC.enable(boot_hart(), PrivilegeMode::Supervisor, irq);
+}
+
+/// 处理S态外部中断：从PLIC认领中断号并交给对应的处理函数
+pub fn handle_external_interrupt() {
+    let irq = PLIC.claim(boot_hart(), PrivilegeMode::Supervisor);
+    if irq == 0 {
+        return;
+    }
+    let handler = IRQ_HANDLERS.exclusive_access().get(irq).copied().flatten();
+    match handler {
+        Some(handler) => handler(),
+        // 虚假中断或没有驱动的设备，完成认领后忽略
+        None => warn!("unhandled external interrupt {}", irq),
+    }
+    PLIC.complete(boot_hart(), PrivilegeMode::Supervisor, irq);
+}
@@ -0,0 +1,26 @@
+//! QEMU virt平台上的goldfish RTC驱动，只用于读取当前的墙上时间
+use core::ptr::read_volatile;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub struct GoldfishRtc {
+    base_addr: usize,
+}
+
+impl GoldfishRtc {
+    /// # Safety
+    /// base_addr必须是已经映射的goldfish RTC寄存器基地址
+    pub const unsafe fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+    /// 自1970-01-01 00:00:00 UTC以来的纳秒数。
+    /// 读TIME_LOW时设备会锁存高32位，因此必须先读低位
+    pub fn read_time_ns(&self) -> u64 {
+        unsafe {
+            let low = read_volatile((self.base_addr + TIME_LOW) as *const u32);
+            let high = read_volatile((self.base_addr + TIME_HIGH) as *const u32);
+            ((high as u64) << 32) | low as u64
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! virtio-gpu驱动，只使用2D命令：创建一个以内核内存为后备存储的资源作为帧缓冲，
+//! 把它设置为0号扫描输出，之后每次刷新时将改动的区域传给设备并显示
+use super::{MmioTransport, VirtIOError, VirtQueue};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc_contiguous, FrameTracker, PhysAddr};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::mem::size_of;
+
+const QUEUE_CONTROL: u16 = 0;
+const QUEUE_SIZE: u16 = 4;
+/// 请求与响应缓冲区的大小，足以容纳最大的命令（GET_DISPLAY_INFO的响应）
+const BUF_SIZE: usize = 512;
+
+// 控制命令与响应的类型
+const CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+const CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+const CMD_SET_SCANOUT: u32 = 0x0103;
+const CMD_RESOURCE_FLUSH: u32 = 0x0104;
+const CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+const CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+const RESP_OK_NODATA: u32 = 0x1100;
+const RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+/// 每像素4字节，按字节依次为B、G、R与未使用，即小端u32的0x00RRGGBB
+const FORMAT_B8G8R8X8_UNORM: u32 = 2;
+const MAX_SCANOUTS: usize = 16;
+const RESOURCE_ID: u32 = 1;
+const SCANOUT_ID: u32 = 0;
+/// 设备没有报告可用的显示器时使用的分辨率
+const DEFAULT_SIZE: (u32, u32) = (640, 480);
+pub const BYTES_PER_PIXEL: usize = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct CtrlHeader {
+    hdr_type: u32,
+    flags: u32,
+    fence_id: u64,
+    ctx_id: u32,
+    padding: u32,
+}
+
+impl CtrlHeader {
+    fn new(hdr_type: u32) -> Self {
+        Self {
+            hdr_type,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DisplayOne {
+    rect: Rect,
+    enabled: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RespDisplayInfo {
+    header: CtrlHeader,
+    pmodes: [DisplayOne; MAX_SCANOUTS],
+}
+
+#[repr(C)]
+struct ResourceCreate2D {
+    header: CtrlHeader,
+    resource_id: u32,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+/// 后备存储只有一段连续的物理内存，直接跟在命令后面
+#[repr(C)]
+struct ResourceAttachBacking {
+    header: CtrlHeader,
+    resource_id: u32,
+    nr_entries: u32,
+    addr: u64,
+    length: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+struct SetScanout {
+    header: CtrlHeader,
+    rect: Rect,
+    scanout_id: u32,
+    resource_id: u32,
+}
+
+#[repr(C)]
+struct TransferToHost2D {
+    header: CtrlHeader,
+    rect: Rect,
+    offset: u64,
+    resource_id: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+struct ResourceFlush {
+    header: CtrlHeader,
+    rect: Rect,
+    resource_id: u32,
+    padding: u32,
+}
+
+pub struct VirtIOGpu {
+    transport: MmioTransport,
+    queue: VirtQueue,
+    /// 请求与响应缓冲区，位于内核堆上以保证虚拟地址等于物理地址
+    request: Vec<u8>,
+    response: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// 帧缓冲所在的物理页帧，物理上连续，可以被映射到用户地址空间
+    frames: Vec<Arc<FrameTracker>>,
+}
+
+impl VirtIOGpu {
+    pub fn new(transport: MmioTransport) -> Result<Self, VirtIOError> {
+        transport.begin_init(|_| 0)?;
+        let queue = VirtQueue::new(&transport, QUEUE_CONTROL, QUEUE_SIZE)?;
+        transport.finish_init();
+        let mut gpu = Self {
+            transport,
+            queue,
+            request: vec![0; BUF_SIZE],
+            response: vec![0; BUF_SIZE],
+            width: 0,
+            height: 0,
+            frames: Vec::new(),
+        };
+        let (width, height) = gpu.display_size()?;
+        let size = width as usize * height as usize * BYTES_PER_PIXEL;
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        gpu.frames = frame_alloc_contiguous(pages)
+            .ok_or(VirtIOError::NoMemory)?
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        gpu.width = width;
+        gpu.height = height;
+
+        gpu.command(ResourceCreate2D {
+            header: CtrlHeader::new(CMD_RESOURCE_CREATE_2D),
+            resource_id: RESOURCE_ID,
+            format: FORMAT_B8G8R8X8_UNORM,
+            width,
+            height,
+        })?;
+        gpu.command(ResourceAttachBacking {
+            header: CtrlHeader::new(CMD_RESOURCE_ATTACH_BACKING),
+            resource_id: RESOURCE_ID,
+            nr_entries: 1,
+            addr: gpu.paddr() as u64,
+            length: size as u32,
+            padding: 0,
+        })?;
+        gpu.command(SetScanout {
+            header: CtrlHeader::new(CMD_SET_SCANOUT),
+            rect: gpu.screen(),
+            scanout_id: SCANOUT_ID,
+            resource_id: RESOURCE_ID,
+        })?;
+        gpu.flush(gpu.screen())?;
+        Ok(gpu)
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn frames(&self) -> &[Arc<FrameTracker>] {
+        &self.frames
+    }
+    /// 帧缓冲的起始物理地址
+    pub fn paddr(&self) -> usize {
+        PhysAddr::from(self.frames[0].ppn).into()
+    }
+    /// 整个屏幕所在的矩形
+    pub fn screen(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+    /// 将帧缓冲中rect范围内的内容传给设备并刷新到屏幕上，rect须位于屏幕之内
+    pub fn flush(&mut self, rect: Rect) -> Result<(), VirtIOError> {
+        let offset = (rect.y as usize * self.width as usize + rect.x as usize) * BYTES_PER_PIXEL;
+        self.command(TransferToHost2D {
+            header: CtrlHeader::new(CMD_TRANSFER_TO_HOST_2D),
+            rect,
+            offset: offset as u64,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })?;
+        self.command(ResourceFlush {
+            header: CtrlHeader::new(CMD_RESOURCE_FLUSH),
+            rect,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })
+    }
+    /// 第一个启用的显示器的分辨率
+    fn display_size(&mut self) -> Result<(u32, u32), VirtIOError> {
+        let info: RespDisplayInfo = self.request(CtrlHeader::new(CMD_GET_DISPLAY_INFO))?;
+        if info.header.hdr_type != RESP_OK_DISPLAY_INFO {
+            return Err(VirtIOError::DeviceError);
+        }
+        Ok(info
+            .pmodes
+            .iter()
+            .find(|mode| mode.enabled != 0 && mode.rect.width != 0 && mode.rect.height != 0)
+            .map_or(DEFAULT_SIZE, |mode| (mode.rect.width, mode.rect.height)))
+    }
+    /// 发送一个只需要成功与否的命令
+    fn command<Req>(&mut self, req: Req) -> Result<(), VirtIOError> {
+        let resp: CtrlHeader = self.request(req)?;
+        if resp.hdr_type == RESP_OK_NODATA {
+            Ok(())
+        } else {
+            Err(VirtIOError::DeviceError)
+        }
+    }
+    /// 同步地发送一个命令并等待响应。命令很少且设备处理得很快，因此轮询等待而不使用中断
+    fn request<Req, Resp>(&mut self, req: Req) -> Result<Resp, VirtIOError> {
+        let (req_len, resp_len) = (size_of::<Req>(), size_of::<Resp>());
+        assert!(req_len <= BUF_SIZE && resp_len <= BUF_SIZE);
+        unsafe {
+            (self.request.as_mut_ptr() as *mut Req).write_unaligned(req);
+        }
+        self.response[..resp_len].fill(0);
+        let token = unsafe {
+            self.queue
+                .add(
+                    &[&self.request[..req_len]],
+                    &[&mut self.response[..resp_len]],
+                )
+                .unwrap()
+        };
+        self.queue.notify(&self.transport);
+        loop {
+            match self.queue.pop_used() {
+                Some((used, _)) => {
+                    assert_eq!(used, token);
+                    break;
+                }
+                None => spin_loop(),
+            }
+        }
+        self.transport.ack_interrupt();
+        Ok(unsafe { (self.response.as_ptr() as *const Resp).read_unaligned() })
+    }
+}
@@ -0,0 +1,87 @@
+//! virtio输入设备驱动（键盘、鼠标、触摸板等），设备把evdev格式的事件写入驱动预先提供的缓冲区
+use super::{MmioTransport, VirtIOError, VirtQueue};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const QUEUE_EVENT: u16 = 0;
+const QUEUE_SIZE: u16 = 32;
+
+// 配置空间中的字段：先写入select与subsel，再从size与data读出结果
+const CFG_SELECT: usize = 0;
+const CFG_SUBSEL: usize = 1;
+const CFG_SIZE: usize = 2;
+const CFG_DATA: usize = 8;
+const CFG_ID_NAME: u8 = 0x01;
+
+/// 设备写入的事件，各字段与Linux的evdev相同
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VirtIOInputEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: u32,
+}
+
+pub struct VirtIOInput {
+    transport: MmioTransport,
+    queue: VirtQueue,
+    buffers: Vec<[u8; size_of::<VirtIOInputEvent>()]>,
+    /// 以请求标识为下标，记录正在被设备使用的缓冲区编号
+    inflight: Vec<Option<usize>>,
+    name: String,
+}
+
+impl VirtIOInput {
+    pub fn new(transport: MmioTransport) -> Result<Self, VirtIOError> {
+        transport.begin_init(|_| 0)?;
+        let queue = VirtQueue::new(&transport, QUEUE_EVENT, QUEUE_SIZE)?;
+        transport.config_write_u8(CFG_SELECT, CFG_ID_NAME);
+        transport.config_write_u8(CFG_SUBSEL, 0);
+        let name_len = transport.config_read_u8(CFG_SIZE) as usize;
+        let name = (0..name_len)
+            .map(|i| transport.config_read_u8(CFG_DATA + i) as char)
+            .collect();
+        let size = queue.size() as usize;
+        let mut input = Self {
+            transport,
+            queue,
+            buffers: vec![[0; size_of::<VirtIOInputEvent>()]; size],
+            inflight: vec![None; size],
+            name,
+        };
+        // 缓冲区全部预先交给设备
+        for i in 0..size {
+            input.post_buffer(i);
+        }
+        input.transport.finish_init();
+        input.queue.notify(&input.transport);
+        Ok(input)
+    }
+    /// 设备报告的名字，如"QEMU Virtio Keyboard"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// 应答设备中断，返回是否确实有中断
+    pub fn ack_interrupt(&self) -> bool {
+        self.transport.ack_interrupt() != 0
+    }
+    fn post_buffer(&mut self, buf: usize) {
+        let token = unsafe { self.queue.add(&[], &[&mut self.buffers[buf][..]]).unwrap() };
+        self.inflight[token as usize] = Some(buf);
+    }
+    /// 取出一个设备发来的事件，没有时返回None
+    pub fn pop_event(&mut self) -> Option<VirtIOInputEvent> {
+        let (token, _) = self.queue.pop_used()?;
+        let buf = self.inflight[token as usize].take().unwrap();
+        let bytes = self.buffers[buf];
+        self.post_buffer(buf);
+        self.queue.notify(&self.transport);
+        Some(VirtIOInputEvent {
+            event_type: u16::from_le_bytes([bytes[0], bytes[1]]),
+            code: u16::from_le_bytes([bytes[2], bytes[3]]),
+            value: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
@@ -0,0 +1,196 @@
+//! virtio-mmio传输层，同时支持legacy（版本1）与modern（版本2）接口
+mod gpu;
+mod input;
+mod net;
+mod queue;
+mod rng;
+
+use crate::config::PAGE_SIZE;
+use crate::fdt::Device;
+use core::sync::atomic::{fence, Ordering};
+
+pub use gpu::{Rect, VirtIOGpu, BYTES_PER_PIXEL};
+pub use input::{VirtIOInput, VirtIOInputEvent};
+pub use net::{VirtIONet, MAX_FRAME_SIZE};
+pub use queue::VirtQueue;
+pub use rng::VirtIORng;
+
+const MAGIC_VALUE: u32 = 0x7472_6976;
+
+// 寄存器相对于基地址的偏移
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_GUEST_PAGE_SIZE: usize = 0x028;
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_ALIGN: usize = 0x03c;
+const REG_QUEUE_PFN: usize = 0x040;
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC_LOW: usize = 0x080;
+const REG_QUEUE_DESC_HIGH: usize = 0x084;
+const REG_QUEUE_DRIVER_LOW: usize = 0x090;
+const REG_QUEUE_DRIVER_HIGH: usize = 0x094;
+const REG_QUEUE_DEVICE_LOW: usize = 0x0a0;
+const REG_QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const REG_CONFIG: usize = 0x100;
+
+// 设备状态位
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// modern设备必须协商的特性位
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// 设备类型
+pub const DEVICE_ID_NET: u32 = 1;
+pub const DEVICE_ID_RNG: u32 = 4;
+pub const DEVICE_ID_GPU: u32 = 16;
+pub const DEVICE_ID_INPUT: u32 = 18;
+
+#[derive(Debug)]
+pub enum VirtIOError {
+    /// 设备拒绝了驱动选择的特性
+    FeaturesRejected,
+    /// 设备上不存在该队列
+    QueueUnavailable,
+    /// 没有足够的连续物理内存
+    NoMemory,
+    /// 设备对请求返回了错误
+    DeviceError,
+}
+
+/// 一个virtio-mmio设备的寄存器窗口
+pub struct MmioTransport {
+    base: usize,
+    version: u32,
+    pub device_id: u32,
+    pub irq: usize,
+}
+
+impl MmioTransport {
+    /// 检查device处是否有virtio设备，没有设备的空槽位返回None
+    pub fn probe(device: &Device) -> Option<Self> {
+        let mut transport = Self {
+            base: device.base,
+            version: 0,
+            device_id: 0,
+            irq: device.irq,
+        };
+        if transport.read(REG_MAGIC) != MAGIC_VALUE {
+            return None;
+        }
+        transport.version = transport.read(REG_VERSION);
+        transport.device_id = transport.read(REG_DEVICE_ID);
+        if transport.device_id == 0 || !(1..=2).contains(&transport.version) {
+            return None;
+        }
+        Some(transport)
+    }
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.base + offset) as *const u32).read_volatile() }
+    }
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.base + offset) as *mut u32).write_volatile(value) }
+    }
+    pub fn is_legacy(&self) -> bool {
+        self.version == 1
+    }
+    /// 复位设备并协商特性，negotiate根据设备提供的特性返回驱动接受的特性，
+    /// 返回最终协商的特性
+    pub fn begin_init(&self, negotiate: impl FnOnce(u64) -> u64) -> Result<u64, VirtIOError> {
+        self.write(REG_STATUS, 0);
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE);
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        self.write(REG_DEVICE_FEATURES_SEL, 0);
+        let mut device_features = self.read(REG_DEVICE_FEATURES) as u64;
+        self.write(REG_DEVICE_FEATURES_SEL, 1);
+        device_features |= (self.read(REG_DEVICE_FEATURES) as u64) << 32;
+        let mut features = negotiate(device_features) & device_features;
+        if !self.is_legacy() {
+            features |= VIRTIO_F_VERSION_1 & device_features;
+        }
+        self.write(REG_DRIVER_FEATURES_SEL, 0);
+        self.write(REG_DRIVER_FEATURES, features as u32);
+        self.write(REG_DRIVER_FEATURES_SEL, 1);
+        self.write(REG_DRIVER_FEATURES, (features >> 32) as u32);
+        if self.is_legacy() {
+            self.write(REG_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        } else {
+            let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+            self.write(REG_STATUS, status);
+            if self.read(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+                return Err(VirtIOError::FeaturesRejected);
+            }
+        }
+        Ok(features)
+    }
+    /// 通知设备驱动已经准备就绪
+    pub fn finish_init(&self) {
+        let status = self.read(REG_STATUS);
+        self.write(REG_STATUS, status | STATUS_DRIVER_OK);
+    }
+    /// 队列idx的最大长度，0表示队列不存在
+    pub fn max_queue_size(&self, idx: u16) -> u16 {
+        self.write(REG_QUEUE_SEL, idx as u32);
+        self.read(REG_QUEUE_NUM_MAX) as u16
+    }
+    /// 将virtqueue告知设备
+    pub fn setup_queue(&self, idx: u16, queue: &VirtQueue) {
+        self.write(REG_QUEUE_SEL, idx as u32);
+        self.write(REG_QUEUE_NUM, queue.size() as u32);
+        if self.is_legacy() {
+            self.write(REG_QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write(REG_QUEUE_PFN, (queue.desc_paddr() / PAGE_SIZE) as u32);
+        } else {
+            let set = |low: usize, high: usize, paddr: usize| {
+                self.write(low, paddr as u32);
+                self.write(high, (paddr >> 32) as u32);
+            };
+            set(REG_QUEUE_DESC_LOW, REG_QUEUE_DESC_HIGH, queue.desc_paddr());
+            set(
+                REG_QUEUE_DRIVER_LOW,
+                REG_QUEUE_DRIVER_HIGH,
+                queue.avail_paddr(),
+            );
+            set(
+                REG_QUEUE_DEVICE_LOW,
+                REG_QUEUE_DEVICE_HIGH,
+                queue.used_paddr(),
+            );
+            self.write(REG_QUEUE_READY, 1);
+        }
+    }
+    /// 通知设备队列idx中有新的请求
+    pub fn notify(&self, idx: u16) {
+        fence(Ordering::SeqCst);
+        self.write(REG_QUEUE_NOTIFY, idx as u32);
+    }
+    /// 读取并应答中断状态
+    pub fn ack_interrupt(&self) -> u32 {
+        let status = self.read(REG_INTERRUPT_STATUS);
+        if status != 0 {
+            self.write(REG_INTERRUPT_ACK, status);
+        }
+        status
+    }
+    /// 读取设备配置空间中offset处的一个字节
+    pub fn config_read_u8(&self, offset: usize) -> u8 {
+        unsafe { ((self.base + REG_CONFIG + offset) as *const u8).read_volatile() }
+    }
+    /// 写入设备配置空间中offset处的一个字节
+    pub fn config_write_u8(&self, offset: usize, value: u8) {
+        unsafe { ((self.base + REG_CONFIG + offset) as *mut u8).write_volatile(value) }
+    }
+}
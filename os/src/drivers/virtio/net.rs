@@ -0,0 +1,122 @@
+//! virtio网卡驱动，只负责收发以太网帧
+use super::{MmioTransport, VirtIOError, VirtQueue};
+use alloc::vec;
+use alloc::vec::Vec;
+
+const QUEUE_RECEIVE: u16 = 0;
+const QUEUE_TRANSMIT: u16 = 1;
+const QUEUE_SIZE: u16 = 16;
+
+/// 设备在配置空间中提供MAC地址
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// 每个收发缓冲区的大小，足以容纳virtio-net头部与一个以太网帧
+const BUF_SIZE: usize = 2048;
+/// 不含FCS的以太网帧的最大长度
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+pub struct VirtIONet {
+    transport: MmioTransport,
+    rx_queue: VirtQueue,
+    tx_queue: VirtQueue,
+    rx_buffers: Vec<Vec<u8>>,
+    /// 以请求标识为下标，记录正在被设备使用的缓冲区编号
+    rx_inflight: Vec<Option<usize>>,
+    tx_buffers: Vec<Vec<u8>>,
+    tx_inflight: Vec<Option<usize>>,
+    tx_free: Vec<usize>,
+    mac: [u8; 6],
+    /// virtio-net头部的长度，legacy设备不协商MRG_RXBUF时没有num_buffers字段
+    hdr_len: usize,
+}
+
+impl VirtIONet {
+    pub fn new(transport: MmioTransport) -> Result<Self, VirtIOError> {
+        let features = transport.begin_init(|_| VIRTIO_NET_F_MAC)?;
+        let rx_queue = VirtQueue::new(&transport, QUEUE_RECEIVE, QUEUE_SIZE)?;
+        let tx_queue = VirtQueue::new(&transport, QUEUE_TRANSMIT, QUEUE_SIZE)?;
+        let mut mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+        if features & VIRTIO_NET_F_MAC != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = transport.config_read_u8(i);
+            }
+        }
+        let hdr_len = if transport.is_legacy() { 10 } else { 12 };
+        let (rx_size, tx_size) = (rx_queue.size() as usize, tx_queue.size() as usize);
+        let mut net = Self {
+            transport,
+            rx_queue,
+            tx_queue,
+            rx_buffers: (0..rx_size).map(|_| vec![0; BUF_SIZE]).collect(),
+            rx_inflight: vec![None; rx_size],
+            tx_buffers: (0..tx_size).map(|_| vec![0; BUF_SIZE]).collect(),
+            tx_inflight: vec![None; tx_size],
+            tx_free: (0..tx_size).collect(),
+            mac,
+            hdr_len,
+        };
+        // 接收缓冲区全部预先交给设备
+        for i in 0..rx_size {
+            net.post_rx_buffer(i);
+        }
+        net.transport.finish_init();
+        net.rx_queue.notify(&net.transport);
+        Ok(net)
+    }
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+    /// 应答设备中断，返回是否确实有中断
+    pub fn ack_interrupt(&self) -> bool {
+        self.transport.ack_interrupt() != 0
+    }
+    fn post_rx_buffer(&mut self, buf: usize) {
+        let token = unsafe {
+            self.rx_queue
+                .add(&[], &[self.rx_buffers[buf].as_mut_slice()])
+                .unwrap()
+        };
+        self.rx_inflight[token as usize] = Some(buf);
+    }
+    /// 取出一个收到的以太网帧，没有时返回None
+    pub fn receive(&mut self) -> Option<Vec<u8>> {
+        let (token, len) = self.rx_queue.pop_used()?;
+        let buf = self.rx_inflight[token as usize].take().unwrap();
+        let len = len.clamp(self.hdr_len, BUF_SIZE);
+        let frame = self.rx_buffers[buf][self.hdr_len..len].to_vec();
+        self.post_rx_buffer(buf);
+        self.rx_queue.notify(&self.transport);
+        Some(frame)
+    }
+    /// 回收设备已经发送完毕的缓冲区
+    fn reclaim_tx(&mut self) {
+        while let Some((token, _)) = self.tx_queue.pop_used() {
+            let buf = self.tx_inflight[token as usize].take().unwrap();
+            self.tx_free.push(buf);
+        }
+    }
+    /// 是否有空闲的发送缓冲区
+    pub fn can_send(&mut self) -> bool {
+        self.reclaim_tx();
+        !self.tx_free.is_empty()
+    }
+    /// 发送一个长为len的以太网帧，帧的内容由fill填写。没有空闲缓冲区时返回None
+    pub fn send<R>(&mut self, len: usize, fill: impl FnOnce(&mut [u8]) -> R) -> Option<R> {
+        assert!(len <= MAX_FRAME_SIZE);
+        self.reclaim_tx();
+        let buf = self.tx_free.pop()?;
+        let hdr_len = self.hdr_len;
+        let data = &mut self.tx_buffers[buf];
+        // 不使用任何卸载功能，头部全为0
+        data[..hdr_len].fill(0);
+        let result = fill(&mut data[hdr_len..hdr_len + len]);
+        let token = unsafe {
+            self.tx_queue
+                .add(&[&self.tx_buffers[buf][..hdr_len + len]], &[])
+                .unwrap()
+        };
+        self.tx_inflight[token as usize] = Some(buf);
+        self.tx_queue.notify(&self.transport);
+        Some(result)
+    }
+}
@@ -0,0 +1,170 @@
+//! split virtqueue：描述符表、可用环与已用环放在一段连续的物理内存中
+use super::{MmioTransport, VirtIOError};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc_contiguous, FrameTracker, PhysAddr};
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+pub struct VirtQueue {
+    idx: u16,
+    size: u16,
+    frames: Vec<FrameTracker>,
+    desc: *mut Descriptor,
+    /// 可用环：flags, idx, ring[size]
+    avail: *mut u16,
+    /// 已用环：flags, idx, 之后是ring[size]
+    used: *mut u16,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+// 队列内存只由持有VirtQueue的驱动访问
+unsafe impl Send for VirtQueue {}
+unsafe impl Sync for VirtQueue {}
+
+impl VirtQueue {
+    /// 为transport上的第idx个队列分配至多max_size项的virtqueue并告知设备
+    pub fn new(transport: &MmioTransport, idx: u16, max_size: u16) -> Result<Self, VirtIOError> {
+        let device_max = transport.max_queue_size(idx);
+        if device_max == 0 {
+            return Err(VirtIOError::QueueUnavailable);
+        }
+        let size = max_size.min(device_max);
+        let n = size as usize;
+        let avail_offset = size_of::<Descriptor>() * n;
+        let used_offset = align_up(avail_offset + 2 * (3 + n), PAGE_SIZE);
+        let total = used_offset + align_up(2 * 3 + size_of::<UsedElem>() * n, PAGE_SIZE);
+        let frames = frame_alloc_contiguous(total / PAGE_SIZE).ok_or(VirtIOError::NoMemory)?;
+        let base: usize = PhysAddr::from(frames[0].ppn).into();
+        let desc = base as *mut Descriptor;
+        // 将所有描述符串成空闲链表
+        for i in 0..n {
+            unsafe {
+                (*desc.add(i)).next = (i + 1) as u16;
+            }
+        }
+        let queue = Self {
+            idx,
+            size,
+            frames,
+            desc,
+            avail: (base + avail_offset) as *mut u16,
+            used: (base + used_offset) as *mut u16,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        transport.setup_queue(idx, &queue);
+        Ok(queue)
+    }
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+    pub fn desc_paddr(&self) -> usize {
+        PhysAddr::from(self.frames[0].ppn).into()
+    }
+    pub fn avail_paddr(&self) -> usize {
+        self.avail as usize
+    }
+    pub fn used_paddr(&self) -> usize {
+        self.used as usize
+    }
+    /// 将一组缓冲区作为一个请求放入队列，inputs由设备读取，outputs由设备写入。
+    /// 返回请求的标识（首个描述符的编号），队列空间不足时返回None
+    ///
+    /// # Safety
+    /// 在该请求被pop_used取回之前，缓冲区必须保持有效且不被访问。
+    /// 内核地址与物理地址相同，缓冲区的地址直接交给设备
+    pub unsafe fn add(&mut self, inputs: &[&[u8]], outputs: &[&mut [u8]]) -> Option<u16> {
+        let count = inputs.len() + outputs.len();
+        if count == 0 || count > self.num_free as usize {
+            return None;
+        }
+        let head = self.free_head;
+        let mut last = head;
+        let buffers = inputs
+            .iter()
+            .map(|buf| (buf.as_ptr() as usize, buf.len(), 0))
+            .chain(
+                outputs
+                    .iter()
+                    .map(|buf| (buf.as_ptr() as usize, buf.len(), DESC_F_WRITE)),
+            );
+        for (addr, len, flags) in buffers {
+            let desc = &mut *self.desc.add(self.free_head as usize);
+            desc.addr = addr as u64;
+            desc.len = len as u32;
+            desc.flags = flags | DESC_F_NEXT;
+            last = self.free_head;
+            self.free_head = desc.next;
+        }
+        (*self.desc.add(last as usize)).flags &= !DESC_F_NEXT;
+        self.num_free -= count as u16;
+
+        // 放入可用环，先写环中的项再更新idx
+        let slot = self.avail_idx % self.size;
+        self.avail.add(2 + slot as usize).write_volatile(head);
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        self.avail.add(1).write_volatile(self.avail_idx);
+        Some(head)
+    }
+    /// 通知设备处理新放入的请求
+    pub fn notify(&self, transport: &MmioTransport) {
+        transport.notify(self.idx);
+    }
+    /// 设备是否已经处理完某个请求
+    pub fn can_pop(&self) -> bool {
+        fence(Ordering::SeqCst);
+        unsafe { self.used.add(1).read_volatile() != self.last_used_idx }
+    }
+    /// 取回一个已经处理完的请求，返回其标识与设备写入的字节数，并回收它的描述符
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        if !self.can_pop() {
+            return None;
+        }
+        let slot = (self.last_used_idx % self.size) as usize;
+        let elem = unsafe { &*((self.used.add(2) as *const UsedElem).add(slot)) };
+        let (head, len) = (elem.id as u16, elem.len as usize);
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        // 将描述符链归还到空闲链表
+        let mut idx = head;
+        loop {
+            let desc = unsafe { &mut *self.desc.add(idx as usize) };
+            self.num_free += 1;
+            if desc.flags & DESC_F_NEXT == 0 {
+                desc.next = self.free_head;
+                break;
+            }
+            idx = desc.next;
+        }
+        self.free_head = head;
+        Some((head, len))
+    }
+}
@@ -0,0 +1,56 @@
+//! virtio熵源设备驱动，设备向驱动提供的缓冲区中写入随机字节
+use super::{MmioTransport, VirtIOError, VirtQueue};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+
+const QUEUE_REQUEST: u16 = 0;
+const QUEUE_SIZE: u16 = 4;
+/// 单次请求的最大长度
+const BUF_SIZE: usize = 64;
+
+pub struct VirtIORng {
+    transport: MmioTransport,
+    queue: VirtQueue,
+    /// 设备直接写入的缓冲区，位于内核堆上以保证虚拟地址等于物理地址
+    buffer: Vec<u8>,
+}
+
+impl VirtIORng {
+    pub fn new(transport: MmioTransport) -> Result<Self, VirtIOError> {
+        transport.begin_init(|_| 0)?;
+        let queue = VirtQueue::new(&transport, QUEUE_REQUEST, QUEUE_SIZE)?;
+        transport.finish_init();
+        Ok(Self {
+            transport,
+            queue,
+            buffer: vec![0; BUF_SIZE],
+        })
+    }
+    /// 同步地向设备请求随机字节填满buf，返回实际得到的字节数。
+    /// 只在初始化和偶尔的重新播种时调用，因此轮询等待而不使用中断
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let want = (buf.len() - filled).min(BUF_SIZE);
+            let token = unsafe { self.queue.add(&[], &[&mut self.buffer[..want]]).unwrap() };
+            self.queue.notify(&self.transport);
+            let len = loop {
+                match self.queue.pop_used() {
+                    Some((used, len)) => {
+                        assert_eq!(used, token);
+                        break len.min(want);
+                    }
+                    None => spin_loop(),
+                }
+            };
+            self.transport.ack_interrupt();
+            if len == 0 {
+                break;
+            }
+            buf[filled..filled + len].copy_from_slice(&self.buffer[..len]);
+            filled += len;
+        }
+        filled
+    }
+}
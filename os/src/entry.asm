@@ -1,15 +1,52 @@
 # os/src/entry.asm
+    .equ MAX_HARTS, 8           # 须与config.rs中的MAX_HARTS一致
+    .equ BOOT_STACK_SIZE, 4096 * 16   # 须与config.rs中的BOOT_STACK_SIZE一致
+
     .section .text.entry
     .global _start
+# SBI跳转到这里时a0为hartid，a1为设备树的物理地址。
+# 有的SBI实现会让所有hart同时进入，只有抢到boot_lottery的hart执行初始化，其余的等待初始化完成
 _start:
-    la sp, boot_stack_top
+    call set_boot_stack
+    la t0, boot_lottery
+    li t1, 1
+    amoswap.w t1, t1, (t0)
+    bnez t1, 1f
     call rust_main
+1:
+    call rust_main_secondary
+
+    .global _start_secondary
+# 启动hart通过SBI HSM扩展启动其余hart时的入口，a0为hartid
+_start_secondary:
+    call set_boot_stack
+    call rust_main_secondary
+
+# tp = hartid，sp指向该hart自己的64KB启动栈，编号超出MAX_HARTS的hart停在这里
+set_boot_stack:
+    li t0, MAX_HARTS
+    bgeu a0, t0, park
+    mv tp, a0
+    addi t0, a0, 1
+    li t1, BOOT_STACK_SIZE
+    mul t0, t0, t1
+    la sp, boot_stack
+    add sp, sp, t0
+    ret
+park:
+    wfi
+    j park
 
     # stack
     .section .bss.stack
     .global boot_stack
 boot_stack:
-    # 64KB
-    .space 4096 * 16
+    # 每个hart 64KB
+    .space BOOT_STACK_SIZE * MAX_HARTS
     .global boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
+
+    .section .data
+    .align 2
+boot_lottery:
+    .word 0
@@ -0,0 +1,289 @@
+//! 扁平设备树（FDT）解析。
+//! SBI在跳转到内核时通过a1传入设备树的物理地址，这里从中取出内存范围、
+//! 时钟频率、各hart编号、内核命令行以及串口、PLIC、RTC和virtio-mmio设备的位置，
+//! 没有设备树时使用板级默认值
+use crate::board;
+use crate::config::MAX_HARTS;
+use crate::sync::{LockClass, UPSafeCell};
+use core::str;
+use lazy_static::*;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// 最多记录的virtio-mmio设备数，QEMU virt平台上有8个
+pub const MAX_VIRTIO: usize = 8;
+/// 节点的最大嵌套深度
+const MAX_DEPTH: usize = 16;
+
+/// 设备的寄存器区间与中断号
+#[derive(Copy, Clone, Debug)]
+pub struct Device {
+    pub base: usize,
+    pub size: usize,
+    pub irq: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MachineInfo {
+    /// 可用物理内存的[起始，结束)地址
+    pub memory: (usize, usize),
+    /// time寄存器的频率
+    pub timebase_frequency: usize,
+    /// 设备树中各cpu节点的hart编号组成的位图，为0时只知道启动hart
+    pub harts: usize,
+    pub uart: Option<Device>,
+    pub plic: Option<Device>,
+    pub rtc: Option<Device>,
+    pub virtio: [Option<Device>; MAX_VIRTIO],
+    /// 设备树所在的物理地址区间，没有设备树时为(0, 0)
+    pub dtb: (usize, usize),
+    /// /chosen节点的bootargs，即内核命令行，指向设备树中的字符串
+    pub bootargs: &'static str,
+}
+
+impl MachineInfo {
+    /// 没有设备树时使用的板级默认配置
+    fn board_default() -> Self {
+        Self {
+            memory: board::MEMORY,
+            timebase_frequency: board::CLOCK_FREQ,
+            harts: 0,
+            uart: board::UART,
+            plic: board::PLIC,
+            rtc: board::RTC,
+            virtio: [None; MAX_VIRTIO],
+            dtb: (0, 0),
+            bootargs: "",
+        }
+    }
+    /// 所有需要在内核地址空间中映射的设备寄存器区间
+    pub fn mmio_regions(&self) -> impl Iterator<Item = Device> + '_ {
+        self.uart
+            .iter()
+            .chain(self.plic.iter())
+            .chain(self.rtc.iter())
+            .chain(self.virtio.iter().flatten())
+            .copied()
+    }
+}
+
+lazy_static! {
+    static ref MACHINE: UPSafeCell<MachineInfo> =
+        unsafe { UPSafeCell::new_with_class(MachineInfo::board_default(), LockClass::Other) };
+}
+
+/// 返回解析得到的机器信息
+pub fn machine() -> MachineInfo {
+    *MACHINE.exclusive_access()
+}
+
+/// 解析dtb处的设备树，覆盖板级默认值。设备树无效时返回false
+pub fn init(dtb: usize) -> bool {
+    if dtb == 0 || dtb % 4 != 0 {
+        return false;
+    }
+    let header = unsafe { core::slice::from_raw_parts(dtb as *const u8, 40) };
+    if be32(header, 0) != FDT_MAGIC {
+        return false;
+    }
+    let total_size = be32(header, 4) as usize;
+    // 设备树所在的内存不会被分配出去，其中的字符串可以一直使用
+    let fdt: &'static [u8] = unsafe { core::slice::from_raw_parts(dtb as *const u8, total_size) };
+    let mut info = MachineInfo::board_default();
+    info.uart = None;
+    info.plic = None;
+    info.rtc = None;
+    info.dtb = (dtb, dtb + total_size);
+    Parser {
+        structs: &fdt[be32(header, 8) as usize..],
+        strings: &fdt[be32(header, 12) as usize..],
+        pos: 0,
+        info: &mut info,
+    }
+    .parse();
+    // 设备树中的virtio-mmio节点按地址降序排列，这里按地址升序排列以便按序探测
+    info.virtio
+        .sort_unstable_by_key(|device| device.map_or(usize::MAX, |device| device.base));
+    *MACHINE.exclusive_access() = info;
+    true
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// 读取由cells个32位大端数拼成的数
+fn read_cells(data: &[u8], offset: usize, cells: usize) -> usize {
+    (0..cells).fold(0, |acc, i| {
+        (acc << 32) | be32(data, offset + i * 4) as usize
+    })
+}
+
+/// 一个节点中我们关心的属性
+#[derive(Default)]
+struct Node<'a> {
+    name: &'a str,
+    device_type: &'a [u8],
+    compatible: &'a [u8],
+    reg: &'a [u8],
+    interrupts: &'a [u8],
+    timebase_frequency: Option<usize>,
+    bootargs: &'a [u8],
+    /// 该节点为子节点规定的#address-cells和#size-cells
+    address_cells: usize,
+    size_cells: usize,
+}
+
+impl<'a> Node<'a> {
+    fn is_compatible(&self, name: &str) -> bool {
+        self.compatible
+            .split(|&b| b == 0)
+            .any(|s| s == name.as_bytes())
+    }
+    /// reg属性中的第一个(地址，长度)对，cells由父节点给出
+    fn first_reg(&self, address_cells: usize, size_cells: usize) -> Option<(usize, usize)> {
+        if self.reg.len() < (address_cells + size_cells) * 4 {
+            return None;
+        }
+        Some((
+            read_cells(self.reg, 0, address_cells),
+            read_cells(self.reg, address_cells * 4, size_cells),
+        ))
+    }
+    fn device(&self, address_cells: usize, size_cells: usize) -> Option<Device> {
+        let (base, size) = self.first_reg(address_cells, size_cells)?;
+        let irq = if self.interrupts.len() >= 4 {
+            be32(self.interrupts, 0) as usize
+        } else {
+            0
+        };
+        Some(Device { base, size, irq })
+    }
+}
+
+struct Parser<'a> {
+    structs: &'static [u8],
+    strings: &'static [u8],
+    pos: usize,
+    info: &'a mut MachineInfo,
+}
+
+impl<'a> Parser<'a> {
+    fn next_u32(&mut self) -> u32 {
+        let value = be32(self.structs, self.pos);
+        self.pos += 4;
+        value
+    }
+    /// 读出从pos开始的以0结尾的字符串，并将pos对齐到4字节
+    fn next_str(&mut self) -> &'static str {
+        let data = &self.structs[self.pos..];
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        self.pos += (len + 1 + 3) & !3;
+        str::from_utf8(&data[..len]).unwrap_or("")
+    }
+    fn string_at(&self, offset: usize) -> &'static str {
+        let data = &self.strings[offset..];
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        str::from_utf8(&data[..len]).unwrap_or("")
+    }
+    fn parse(&mut self) {
+        // 每一层节点的属性，子节点的reg按父节点的cells解释
+        let mut stack: [Node<'static>; MAX_DEPTH] = Default::default();
+        let mut depth = 0;
+        loop {
+            match self.next_u32() {
+                FDT_BEGIN_NODE => {
+                    let name = self.next_str();
+                    assert!(depth < MAX_DEPTH, "device tree nested too deep");
+                    stack[depth] = Node {
+                        name,
+                        // 规范规定的默认值
+                        address_cells: 2,
+                        size_cells: 1,
+                        ..Default::default()
+                    };
+                    depth += 1;
+                }
+                FDT_END_NODE => {
+                    depth -= 1;
+                    let (address_cells, size_cells) = if depth > 0 {
+                        (stack[depth - 1].address_cells, stack[depth - 1].size_cells)
+                    } else {
+                        (2, 1)
+                    };
+                    self.handle_node(&stack[depth], address_cells, size_cells);
+                }
+                FDT_PROP => {
+                    let len = self.next_u32() as usize;
+                    let name = self.string_at(self.next_u32() as usize);
+                    let value = &self.structs[self.pos..self.pos + len];
+                    self.pos += (len + 3) & !3;
+                    let node = &mut stack[depth - 1];
+                    match name {
+                        "device_type" => node.device_type = value,
+                        "compatible" => node.compatible = value,
+                        "reg" => node.reg = value,
+                        "interrupts" => node.interrupts = value,
+                        "#address-cells" => node.address_cells = be32(value, 0) as usize,
+                        "#size-cells" => node.size_cells = be32(value, 0) as usize,
+                        "timebase-frequency" => {
+                            node.timebase_frequency = Some(read_cells(value, 0, len / 4))
+                        }
+                        "bootargs" => node.bootargs = value,
+                        _ => {}
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                token => panic!("invalid device tree token {:#x}", token),
+            }
+        }
+    }
+    fn handle_node(&mut self, node: &Node<'static>, address_cells: usize, size_cells: usize) {
+        if let Some(freq) = node.timebase_frequency {
+            self.info.timebase_frequency = freq;
+        }
+        if node.name == "chosen" {
+            let len = node
+                .bootargs
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(node.bootargs.len());
+            self.info.bootargs = str::from_utf8(&node.bootargs[..len]).unwrap_or("");
+        }
+        if node.device_type == b"memory\0" || node.name.starts_with("memory@") {
+            if let Some((base, size)) = node.first_reg(address_cells, size_cells) {
+                self.info.memory = (base, base + size);
+            }
+        } else if node.device_type == b"cpu\0" {
+            // cpu节点的reg就是hartid
+            if let Some((hartid, _)) = node.first_reg(address_cells, size_cells) {
+                if hartid < MAX_HARTS {
+                    self.info.harts |= 1 << hartid;
+                }
+            }
+        } else if node.is_compatible("ns16550a") {
+            if self.info.uart.is_none() {
+                self.info.uart = node.device(address_cells, size_cells);
+            }
+        } else if node.is_compatible("riscv,plic0") || node.is_compatible("sifive,plic-1.0.0") {
+            self.info.plic = node.device(address_cells, size_cells);
+        } else if node.is_compatible("google,goldfish-rtc") {
+            self.info.rtc = node.device(address_cells, size_cells);
+        } else if node.is_compatible("virtio,mmio") {
+            if let Some(slot) = self.info.virtio.iter_mut().find(|slot| slot.is_none()) {
+                *slot = node.device(address_cells, size_cells);
+            }
+        }
+    }
+}
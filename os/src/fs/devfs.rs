@@ -0,0 +1,148 @@
+//! 字符设备注册表与/dev下的设备文件。
+//! 驱动以主设备号注册一组操作，次设备号区分同一驱动管理的各个设备；
+//! devfs把/dev下的名字映射到设备号，打开时得到转发到驱动操作的DeviceFile
+use super::{File, OpenFlags, PollEvents};
+use crate::mm::{FrameTracker, UserBuffer};
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::syscall::errno::*;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// 设备号
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DevId {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DevId {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// 字符设备驱动提供的操作，返回isize的操作出错时返回错误码的相反数
+pub trait CharDevOps: Send + Sync {
+    /// 打开设备时调用，可以拒绝不支持的访问方式
+    fn open(&self, _minor: u32, _flags: OpenFlags) -> SysResult<()> {
+        Ok(())
+    }
+    /// 读取数据，nonblock为真时没有数据应返回-EAGAIN而不是阻塞
+    fn read(&self, minor: u32, buf: UserBuffer, nonblock: bool) -> isize;
+    fn write(&self, minor: u32, buf: UserBuffer) -> isize;
+    /// 设备相关的控制命令，arg通常是用户地址空间中的指针
+    fn ioctl(&self, _minor: u32, _cmd: usize, _arg: usize) -> isize {
+        -ENOTTY
+    }
+    fn poll(&self, _minor: u32, events: PollEvents) -> PollEvents {
+        events & (PollEvents::IN | PollEvents::OUT)
+    }
+    fn wait_queue(&self, _minor: u32) -> Option<&WaitQueue> {
+        None
+    }
+    /// 返回设备内存中从offset开始、长为len字节的部分所在的物理页帧，供mmap映射
+    fn mmap(&self, _minor: u32, _offset: usize, _len: usize) -> SysResult<Vec<Arc<FrameTracker>>> {
+        Err(SysError::ENODEV)
+    }
+}
+
+struct Registry {
+    /// 主设备号 -> (驱动名, 操作)
+    drivers: BTreeMap<u32, (&'static str, Arc<dyn CharDevOps>)>,
+    /// /dev下的名字 -> 设备号
+    nodes: BTreeMap<String, DevId>,
+}
+
+lazy_static! {
+    static ref REGISTRY: UPSafeCell<Registry> = unsafe {
+        UPSafeCell::new_with_class(
+            Registry {
+                drivers: BTreeMap::new(),
+                nodes: BTreeMap::new(),
+            },
+            LockClass::DevFs,
+        )
+    };
+}
+
+/// 以主设备号major注册一个字符设备驱动
+pub fn register_chrdev(major: u32, name: &'static str, ops: Arc<dyn CharDevOps>) {
+    let mut registry = REGISTRY.exclusive_access();
+    assert!(
+        !registry.drivers.contains_key(&major),
+        "char device major {} registered twice",
+        major
+    );
+    registry.drivers.insert(major, (name, ops));
+}
+
+/// 在/dev下创建名为name的设备节点，name可以含有子目录，如"input/event0"
+pub fn devfs_add(name: &str, dev: DevId) {
+    let mut registry = REGISTRY.exclusive_access();
+    assert!(registry.drivers.contains_key(&dev.major));
+    registry.nodes.insert(String::from(name), dev);
+}
+
+/// 打开/dev下名为name的设备
+pub fn devfs_open(name: &str, flags: OpenFlags) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let registry = REGISTRY.exclusive_access();
+    let dev = *registry.nodes.get(name).ok_or(SysError::ENOENT)?;
+    let ops = registry
+        .drivers
+        .get(&dev.major)
+        .ok_or(SysError::ENODEV)?
+        .1
+        .clone();
+    drop(registry);
+    if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
+        return Err(SysError::EEXIST);
+    }
+    ops.open(dev.minor, flags)?;
+    let (readable, writable) = flags.read_write();
+    Ok(Arc::new(DeviceFile {
+        dev,
+        ops,
+        readable,
+        writable,
+        nonblock: flags.contains(OpenFlags::NONBLOCK),
+    }))
+}
+
+/// 打开的设备文件，所有操作转发给驱动
+pub struct DeviceFile {
+    dev: DevId,
+    ops: Arc<dyn CharDevOps>,
+    readable: bool,
+    writable: bool,
+    nonblock: bool,
+}
+
+impl File for DeviceFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> isize {
+        self.ops.read(self.dev.minor, buf, self.nonblock)
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        self.ops.write(self.dev.minor, buf)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        self.ops.poll(self.dev.minor, events)
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        self.ops.wait_queue(self.dev.minor)
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        self.ops.ioctl(self.dev.minor, cmd, arg)
+    }
+    fn mmap(&self, offset: usize, len: usize) -> SysResult<Vec<Arc<FrameTracker>>> {
+        self.ops.mmap(self.dev.minor, offset, len)
+    }
+}
@@ -0,0 +1,99 @@
+//! eventfd：内核维护的64位计数器，用作轻量的事件通知
+use super::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::syscall::errno::{EAGAIN, EINVAL};
+
+/// 计数器的最大值
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+pub struct EventFd {
+    count: UPSafeCell<u64>,
+    /// 信号量模式：每次read只将计数器减1并返回1
+    semaphore: bool,
+    nonblock: bool,
+    /// 读者、写者以及poll的等待者都在这里等待计数器变化
+    wait_queue: WaitQueue,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, nonblock: bool) -> Self {
+        Self {
+            count: unsafe { UPSafeCell::new_with_class(initval, LockClass::EventFd) },
+            semaphore,
+            nonblock,
+            wait_queue: WaitQueue::new(),
+        }
+    }
+}
+
+impl File for EventFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    /// 计数器为0时阻塞，否则取出计数器的值（信号量模式下为1）写入8字节的缓冲区
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        if buf.len() < 8 {
+            return -EINVAL;
+        }
+        let value = loop {
+            let mut count = self.count.exclusive_access();
+            if *count > 0 {
+                let value = if self.semaphore { 1 } else { *count };
+                *count -= value;
+                break value;
+            }
+            drop(count);
+            if self.nonblock {
+                return -EAGAIN;
+            }
+            self.wait_queue.wait(None);
+        };
+        self.wait_queue.wake_all();
+        buf.write_from(&value.to_ne_bytes());
+        8
+    }
+    /// 将8字节缓冲区中的值加到计数器上，计数器将要溢出时阻塞
+    fn write(&self, buf: UserBuffer) -> isize {
+        if buf.len() < 8 {
+            return -EINVAL;
+        }
+        let mut bytes = [0u8; 8];
+        buf.read_to(&mut bytes);
+        let value = u64::from_ne_bytes(bytes);
+        if value == u64::MAX {
+            return -EINVAL;
+        }
+        loop {
+            let mut count = self.count.exclusive_access();
+            if EVENTFD_MAX - *count >= value {
+                *count += value;
+                break;
+            }
+            drop(count);
+            if self.nonblock {
+                return -EAGAIN;
+            }
+            self.wait_queue.wait(None);
+        }
+        self.wait_queue.wake_all();
+        8
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let count = *self.count.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if count > 0 {
+            ready |= PollEvents::IN;
+        }
+        if count < EVENTFD_MAX {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
+}
@@ -0,0 +1,188 @@
+//! 帧缓冲设备/dev/fb0。用户程序用mmap把帧缓冲映射到自己的地址空间直接绘制，
+//! 画完后用FBIOFLUSH通知设备刷新屏幕上改动的区域
+use super::devfs::{devfs_add, register_chrdev, CharDevOps, DevId};
+use crate::config::PAGE_SIZE;
+use crate::drivers::virtio::{Rect, VirtIOGpu, BYTES_PER_PIXEL};
+use crate::mm::{FrameTracker, UserBuffer, UserPtr};
+use crate::sync::{LockClass, UPSafeCell};
+use crate::syscall::errno::*;
+use crate::task::current_user_token;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const FB_MAJOR: u32 = 29;
+const FB_MINOR: u32 = 0;
+
+const FBIOGET_VSCREENINFO: usize = 0x4600;
+/// ApolloOS特有的命令：arg为0时刷新整个屏幕，否则指向一个FbRect，只刷新其中的区域
+const FBIOFLUSH: usize = 0x46f0;
+
+/// 与Linux的struct fb_bitfield布局相同
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+/// 与Linux的struct fb_var_screeninfo布局相同，只填写分辨率与像素格式
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct FbVarScreenInfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+/// FBIOFLUSH的参数
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct FbRect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+struct Framebuffer {
+    gpu: UPSafeCell<VirtIOGpu>,
+    width: u32,
+    height: u32,
+    frames: Vec<Arc<FrameTracker>>,
+}
+
+impl Framebuffer {
+    fn var_screeninfo(&self) -> FbVarScreenInfo {
+        let bitfield = |offset| FbBitfield {
+            offset,
+            length: 8,
+            msb_right: 0,
+        };
+        FbVarScreenInfo {
+            xres: self.width,
+            yres: self.height,
+            xres_virtual: self.width,
+            yres_virtual: self.height,
+            bits_per_pixel: (BYTES_PER_PIXEL * 8) as u32,
+            red: bitfield(16),
+            green: bitfield(8),
+            blue: bitfield(0),
+            ..Default::default()
+        }
+    }
+    /// 把用户给出的矩形裁剪到屏幕之内，完全在屏幕之外时返回None
+    fn clip(&self, rect: FbRect) -> Option<Rect> {
+        let x = rect.x.min(self.width);
+        let y = rect.y.min(self.height);
+        let width = rect.width.min(self.width - x);
+        let height = rect.height.min(self.height - y);
+        if width == 0 || height == 0 {
+            None
+        } else {
+            Some(Rect {
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+    }
+}
+
+impl CharDevOps for Framebuffer {
+    /// 帧缓冲只能通过mmap访问
+    fn read(&self, _minor: u32, _buf: UserBuffer, _nonblock: bool) -> isize {
+        -EINVAL
+    }
+    fn write(&self, _minor: u32, _buf: UserBuffer) -> isize {
+        -EINVAL
+    }
+    fn ioctl(&self, _minor: u32, cmd: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        match cmd {
+            FBIOGET_VSCREENINFO => {
+                let info = UserPtr::new(token, arg as *const FbVarScreenInfo);
+                match info.write(self.var_screeninfo()) {
+                    Ok(()) => 0,
+                    Err(err) => -err.errno(),
+                }
+            }
+            FBIOFLUSH => {
+                let rect = if arg == 0 {
+                    FbRect {
+                        x: 0,
+                        y: 0,
+                        width: self.width,
+                        height: self.height,
+                    }
+                } else {
+                    match UserPtr::new(token, arg as *const FbRect).read() {
+                        Ok(rect) => rect,
+                        Err(err) => return -err.errno(),
+                    }
+                };
+                match self.clip(rect) {
+                    Some(rect) => match self.gpu.exclusive_access().flush(rect) {
+                        Ok(()) => 0,
+                        Err(_) => -EIO,
+                    },
+                    None => 0,
+                }
+            }
+            _ => -ENOTTY,
+        }
+    }
+    fn mmap(&self, _minor: u32, offset: usize, len: usize) -> SysResult<Vec<Arc<FrameTracker>>> {
+        let first = offset / PAGE_SIZE;
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        if first + pages > self.frames.len() {
+            return Err(SysError::EINVAL);
+        }
+        Ok(self.frames[first..first + pages].to_vec())
+    }
+}
+
+/// 用探测到的virtio-gpu创建/dev/fb0
+pub fn add_framebuffer(gpu: VirtIOGpu) {
+    info!(
+        "fb0: {}x{} at {:#x}",
+        gpu.width(),
+        gpu.height(),
+        gpu.paddr()
+    );
+    let fb = Framebuffer {
+        width: gpu.width(),
+        height: gpu.height(),
+        frames: gpu.frames().to_vec(),
+        gpu: unsafe { UPSafeCell::new_with_class(gpu, LockClass::Framebuffer) },
+    };
+    register_chrdev(FB_MAJOR, "fb", Arc::new(fb));
+    devfs_add("fb0", DevId::new(FB_MAJOR, FB_MINOR));
+}
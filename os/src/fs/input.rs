@@ -0,0 +1,186 @@
+//! 输入事件设备/dev/input/event*。每个virtio输入设备对应一个节点，
+//! 中断到来时把设备发来的事件加上时间戳放入该设备的队列，读取时按struct input_event逐个取出
+use super::devfs::{devfs_add, register_chrdev, CharDevOps, DevId};
+use super::{OpenFlags, PollEvents};
+use crate::drivers::virtio::VirtIOInput;
+use crate::mm::{UserBuffer, UserSlice};
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::syscall::errno::*;
+use crate::task::current_user_token;
+use crate::timer::get_realtime_ns;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::slice;
+use lazy_static::*;
+
+const INPUT_MAJOR: u32 = 13;
+const EVDEV_MINOR_BASE: u32 = 64;
+/// 每个设备最多缓存的事件数，读得太慢时丢弃最旧的事件
+const MAX_PENDING: usize = 256;
+
+/// EVIOCGNAME(len)：读取设备名，len编码在命令的16~29位
+const EVIOCGNAME: usize = 0x8000_4506;
+const IOC_SIZE_SHIFT: usize = 16;
+const IOC_SIZE_MASK: usize = 0x3fff;
+
+/// 与Linux的struct input_event布局相同，时间戳为墙上时间
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct InputEvent {
+    sec: u64,
+    usec: u64,
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+struct InputDevice {
+    driver: VirtIOInput,
+    pending: VecDeque<InputEvent>,
+}
+
+impl InputDevice {
+    /// 把设备已经发来的事件全部取到队列中，返回是否有新事件
+    fn collect(&mut self) -> bool {
+        let mut received = false;
+        while let Some(event) = self.driver.pop_event() {
+            let now_ns = get_realtime_ns();
+            if self.pending.len() == MAX_PENDING {
+                self.pending.pop_front();
+            }
+            self.pending.push_back(InputEvent {
+                sec: now_ns / 1_000_000_000,
+                usec: now_ns % 1_000_000_000 / 1000,
+                event_type: event.event_type,
+                code: event.code,
+                value: event.value as i32,
+            });
+            received = true;
+        }
+        received
+    }
+}
+
+lazy_static! {
+    /// 以次设备号减去EVDEV_MINOR_BASE为下标
+    static ref INPUT_DEVICES: UPSafeCell<Vec<InputDevice>> =
+        unsafe { UPSafeCell::new_with_class(Vec::new(), LockClass::Input) };
+    /// 任何输入设备有新事件时唤醒其中所有的任务
+    static ref INPUT_WAIT: WaitQueue = WaitQueue::new();
+}
+
+struct EventDevices;
+
+impl CharDevOps for EventDevices {
+    fn open(&self, minor: u32, _flags: OpenFlags) -> SysResult<()> {
+        let idx = (minor - EVDEV_MINOR_BASE) as usize;
+        if idx < INPUT_DEVICES.exclusive_access().len() {
+            Ok(())
+        } else {
+            Err(SysError::ENODEV)
+        }
+    }
+    /// 读出尽可能多的完整事件，缓冲区连一个事件都放不下时返回-EINVAL。
+    /// 没有事件时阻塞（或在非阻塞模式下返回-EAGAIN）
+    fn read(&self, minor: u32, mut buf: UserBuffer, nonblock: bool) -> isize {
+        let idx = (minor - EVDEV_MINOR_BASE) as usize;
+        let max_events = buf.len() / size_of::<InputEvent>();
+        if max_events == 0 {
+            return -EINVAL;
+        }
+        loop {
+            let mut devices = INPUT_DEVICES.exclusive_access();
+            let device = &mut devices[idx];
+            device.collect();
+            if !device.pending.is_empty() {
+                let count = max_events.min(device.pending.len());
+                let events: Vec<InputEvent> = device.pending.drain(..count).collect();
+                drop(devices);
+                let bytes = unsafe {
+                    slice::from_raw_parts(
+                        events.as_ptr() as *const u8,
+                        count * size_of::<InputEvent>(),
+                    )
+                };
+                return buf.write_from(bytes) as isize;
+            }
+            drop(devices);
+            if nonblock {
+                return -EAGAIN;
+            }
+            INPUT_WAIT.wait(None);
+        }
+    }
+    fn write(&self, _minor: u32, _buf: UserBuffer) -> isize {
+        -EINVAL
+    }
+    fn ioctl(&self, minor: u32, cmd: usize, arg: usize) -> isize {
+        let idx = (minor - EVDEV_MINOR_BASE) as usize;
+        if cmd & !(IOC_SIZE_MASK << IOC_SIZE_SHIFT) != EVIOCGNAME {
+            return -ENOTTY;
+        }
+        let len = (cmd >> IOC_SIZE_SHIFT) & IOC_SIZE_MASK;
+        // 与Linux相同，名字以'\0'结尾，过长时被截断
+        let mut name = Vec::from(
+            INPUT_DEVICES.exclusive_access()[idx]
+                .driver
+                .name()
+                .as_bytes(),
+        );
+        name.push(0);
+        name.truncate(len);
+        match UserSlice::new(current_user_token(), arg as *const u8, name.len()).write(&name) {
+            Ok(written) => written as isize,
+            Err(err) => -err.errno(),
+        }
+    }
+    fn poll(&self, minor: u32, events: PollEvents) -> PollEvents {
+        let idx = (minor - EVDEV_MINOR_BASE) as usize;
+        let mut devices = INPUT_DEVICES.exclusive_access();
+        let device = &mut devices[idx];
+        device.collect();
+        if device.pending.is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::IN & events
+        }
+    }
+    fn wait_queue(&self, _minor: u32) -> Option<&WaitQueue> {
+        Some(&INPUT_WAIT)
+    }
+}
+
+pub fn init() {
+    register_chrdev(INPUT_MAJOR, "input", Arc::new(EventDevices));
+}
+
+/// 为探测到的virtio输入设备创建/dev/input/eventN
+pub fn add_input_device(driver: VirtIOInput) {
+    let mut devices = INPUT_DEVICES.exclusive_access();
+    let idx = devices.len();
+    info!("input/event{}: {}", idx, driver.name());
+    devices.push(InputDevice {
+        driver,
+        pending: VecDeque::new(),
+    });
+    drop(devices);
+    devfs_add(
+        &format!("input/event{}", idx),
+        DevId::new(INPUT_MAJOR, EVDEV_MINOR_BASE + idx as u32),
+    );
+}
+
+/// virtio输入设备的中断处理函数。中断处理函数不知道是哪个设备，因此检查所有设备
+pub fn handle_input_irq() {
+    let mut received = false;
+    for device in INPUT_DEVICES.exclusive_access().iter_mut() {
+        device.driver.ack_interrupt();
+        received |= device.collect();
+    }
+    if received {
+        INPUT_WAIT.wake_all();
+    }
+}
@@ -0,0 +1,51 @@
+//! 主设备号为1的内存类设备：/dev/null、/dev/zero、/dev/random与/dev/urandom
+use super::devfs::{devfs_add, register_chrdev, CharDevOps, DevId};
+use crate::mm::UserBuffer;
+use crate::random::{add_entropy, fill_random};
+use alloc::sync::Arc;
+
+const MEM_MAJOR: u32 = 1;
+const MINOR_NULL: u32 = 3;
+const MINOR_ZERO: u32 = 5;
+/// random与urandom都直接读CSPRNG，播种之后就不会阻塞
+const MINOR_RANDOM: u32 = 8;
+const MINOR_URANDOM: u32 = 9;
+
+struct MemDevices;
+
+impl CharDevOps for MemDevices {
+    fn read(&self, minor: u32, mut buf: UserBuffer, _nonblock: bool) -> isize {
+        match minor {
+            MINOR_NULL => 0,
+            MINOR_ZERO => {
+                for buffer in buf.buffers.iter_mut() {
+                    buffer.fill(0);
+                }
+                buf.len() as isize
+            }
+            _ => {
+                for buffer in buf.buffers.iter_mut() {
+                    fill_random(buffer);
+                }
+                buf.len() as isize
+            }
+        }
+    }
+    /// 写入null与zero的数据被丢弃，写入random的数据被混入熵池但不会降低已有的熵
+    fn write(&self, minor: u32, buf: UserBuffer) -> isize {
+        if minor == MINOR_RANDOM || minor == MINOR_URANDOM {
+            for buffer in buf.buffers.iter() {
+                add_entropy(buffer);
+            }
+        }
+        buf.len() as isize
+    }
+}
+
+pub fn init() {
+    register_chrdev(MEM_MAJOR, "mem", Arc::new(MemDevices));
+    devfs_add("null", DevId::new(MEM_MAJOR, MINOR_NULL));
+    devfs_add("zero", DevId::new(MEM_MAJOR, MINOR_ZERO));
+    devfs_add("random", DevId::new(MEM_MAJOR, MINOR_RANDOM));
+    devfs_add("urandom", DevId::new(MEM_MAJOR, MINOR_URANDOM));
+}
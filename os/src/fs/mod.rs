@@ -0,0 +1,128 @@
+mod devfs;
+mod eventfd;
+mod fb;
+mod input;
+mod mem;
+mod proc;
+mod tty;
+
+use crate::mm::{FrameTracker, UserBuffer};
+use crate::sync::WaitQueue;
+use crate::syscall::errno::*;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use proc::proc_open;
+
+/// 用于将`dyn File`向下转型为具体类型，对所有'static类型自动实现
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 文件描述符背后的对象需要实现的接口，read/write出错时返回错误码的相反数
+pub trait File: AsAny + Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    fn read(&self, buf: UserBuffer) -> isize;
+    fn write(&self, buf: UserBuffer) -> isize;
+    /// 返回events中当前已经就绪的事件，默认总是可读（若readable）、可写（若writable）
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable() {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    /// 就绪状态可能发生变化时会被唤醒的等待队列。
+    /// 返回None表示无法得到通知，等待者只能定期调用poll查询
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        None
+    }
+    /// 设备相关的控制命令，默认不支持
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        -ENOTTY
+    }
+    /// 文件内容从offset开始、长为len字节的部分所在的物理页帧，供mmap共享映射。
+    /// 默认不支持，只有帧缓冲这样的设备内存可以被映射
+    fn mmap(&self, _offset: usize, _len: usize) -> SysResult<Vec<Arc<FrameTracker>>> {
+        Err(SysError::ENODEV)
+    }
+}
+
+impl dyn File + Send + Sync {
+    /// 若文件的具体类型为T，返回对它的引用
+    pub fn downcast_ref<T: File + Any>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+}
+
+bitflags! {
+    /// 打开文件时的标志位，取值与Linux相同
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 6;
+        const EXCL = 1 << 7;
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+bitflags! {
+    /// poll关注与返回的事件，取值与Linux相同
+    pub struct PollEvents: u16 {
+        const IN = 0x001;
+        const PRI = 0x002;
+        const OUT = 0x004;
+        const ERR = 0x008;
+        const HUP = 0x010;
+        const NVAL = 0x020;
+    }
+}
+
+impl OpenFlags {
+    /// 返回(readable, writable)
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.is_empty() || !self.intersects(Self::WRONLY | Self::RDWR) {
+            (true, false)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// 注册内核自带的字符设备并在/dev下创建它们的节点，须在创建第一个进程之前调用
+pub fn init() {
+    mem::init();
+    tty::init();
+    input::init();
+}
+
+/// 按路径打开文件。目前只有/dev下的设备文件与/proc下的只读文件，
+/// 没有工作目录，相对路径也从根目录开始
+pub fn open_file(path: &str, flags: OpenFlags) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let path = path.trim_start_matches('/');
+    if let Some(name) = path.strip_prefix("dev/") {
+        devfs_open(name, flags)
+    } else if let Some(name) = path.strip_prefix("proc/") {
+        proc_open(name, flags)
+    } else {
+        Err(SysError::ENOENT)
+    }
+}
+
+pub use devfs::{devfs_add, devfs_open, register_chrdev, CharDevOps, DevId};
+pub use eventfd::EventFd;
+pub use fb::add_framebuffer;
+pub use input::{add_input_device, handle_input_irq};
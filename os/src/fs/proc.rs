@@ -0,0 +1,62 @@
+//! /proc下的只读文件：内核命令行/proc/cmdline，以及与Linux格式相同的RTC时间/proc/driver/rtc
+use super::{File, OpenFlags};
+use crate::fdt::machine;
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::*;
+use crate::timer::DateTime;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 内容在打开时生成的只读文件，每次read从上次读到的位置继续
+struct ProcFile {
+    content: Vec<u8>,
+    offset: UPSafeCell<usize>,
+}
+
+impl ProcFile {
+    fn new(content: Vec<u8>) -> Self {
+        Self {
+            content,
+            offset: unsafe { UPSafeCell::new(0) },
+        }
+    }
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let mut offset = self.offset.exclusive_access();
+        let copied = buf.write_from(&self.content[*offset..]);
+        *offset += copied;
+        copied as isize
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        -EBADF
+    }
+}
+
+/// 打开/proc下名为name的文件
+pub fn proc_open(name: &str, flags: OpenFlags) -> SysResult<Arc<dyn File + Send + Sync>> {
+    if flags.read_write().1 {
+        return Err(SysError::EACCES);
+    }
+    let content = match name {
+        "cmdline" => format!("{}\n", machine().bootargs),
+        "driver/rtc" => {
+            let now = DateTime::now();
+            format!(
+                "rtc_time\t: {:02}:{:02}:{:02}\nrtc_date\t: {:04}-{:02}-{:02}\n",
+                now.hour, now.minute, now.second, now.year, now.month, now.day
+            )
+        }
+        _ => return Err(SysError::ENOENT),
+    };
+    Ok(Arc::new(ProcFile::new(content.into_bytes())))
+}
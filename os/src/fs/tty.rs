@@ -0,0 +1,100 @@
+//! 控制台串口对应的终端设备：/dev/console与/dev/ttyS0，进程的标准输入输出即打开的/dev/console
+use super::devfs::{devfs_add, register_chrdev, CharDevOps, DevId};
+use super::PollEvents;
+use crate::drivers::chardev::{CharDevice, UART};
+use crate::mm::{UserBuffer, UserPtr};
+use crate::sync::WaitQueue;
+use crate::syscall::errno::*;
+use crate::task::current_user_token;
+use alloc::sync::Arc;
+
+const TTY_MAJOR: u32 = 4;
+const SERIAL_MINOR_BASE: u32 = 64;
+const CONSOLE_MAJOR: u32 = 5;
+const CONSOLE_MINOR: u32 = 1;
+
+const TIOCGWINSZ: usize = 0x5413;
+/// 串口无法得知终端的大小，按常见的80x24报告
+const WINSIZE: WinSize = WinSize {
+    ws_row: 24,
+    ws_col: 80,
+    ws_xpixel: 0,
+    ws_ypixel: 0,
+};
+
+/// 与Linux的struct winsize布局相同
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+/// 控制台串口，两个主设备号下的节点都指向它
+struct SerialConsole;
+
+impl CharDevOps for SerialConsole {
+    /// 没有输入时阻塞（或在非阻塞模式下返回-EAGAIN），否则读出当前已收到的输入，至多填满缓冲区
+    fn read(&self, _minor: u32, buf: UserBuffer, nonblock: bool) -> isize {
+        if nonblock && !UART.has_input() {
+            return -EAGAIN;
+        }
+        let mut read = 0;
+        for byte in buf.into_iter() {
+            let ch = if read == 0 {
+                UART.read()
+            } else {
+                match UART.try_read() {
+                    Some(ch) => ch,
+                    None => break,
+                }
+            };
+            unsafe {
+                byte.write_volatile(ch);
+            }
+            read += 1;
+        }
+        read
+    }
+    fn write(&self, _minor: u32, buf: UserBuffer) -> isize {
+        for buffer in buf.buffers.iter() {
+            for &ch in buffer.iter() {
+                UART.write(ch);
+            }
+        }
+        UART.flush();
+        buf.len() as isize
+    }
+    fn ioctl(&self, _minor: u32, cmd: usize, arg: usize) -> isize {
+        match cmd {
+            TIOCGWINSZ => {
+                let winsize = UserPtr::new(current_user_token(), arg as *const WinSize);
+                match winsize.write(WINSIZE) {
+                    Ok(()) => 0,
+                    Err(err) => -err.errno(),
+                }
+            }
+            _ => -ENOTTY,
+        }
+    }
+    fn poll(&self, _minor: u32, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::OUT;
+        if UART.has_input() {
+            ready |= PollEvents::IN;
+        }
+        ready & events
+    }
+    fn wait_queue(&self, _minor: u32) -> Option<&WaitQueue> {
+        UART.wait_queue()
+    }
+}
+
+pub fn init() {
+    let console = Arc::new(SerialConsole);
+    register_chrdev(CONSOLE_MAJOR, "console", console.clone());
+    register_chrdev(TTY_MAJOR, "ttyS", console);
+    devfs_add("console", DevId::new(CONSOLE_MAJOR, CONSOLE_MINOR));
+    devfs_add("ttyS0", DevId::new(TTY_MAJOR, SERIAL_MINOR_BASE));
+}
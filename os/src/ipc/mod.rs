@@ -0,0 +1,4 @@
+//! 进程间通信
+mod mq;
+
+pub use mq::{mq_open, mq_unlink, MqAttr, MqDescriptor, MqError};
@@ -0,0 +1,284 @@
+//! POSIX风格的消息队列：有界、带优先级，队列满时发送者阻塞，队列空时接收者阻塞
+use crate::fs::{File, OpenFlags, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::syscall::errno::EBADF;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// 未指定属性时队列的容量与单条消息的最大长度
+const MQ_MAXMSG_DEFAULT: usize = 10;
+const MQ_MSGSIZE_DEFAULT: usize = 128;
+/// 单个队列的容量上限与单条消息的长度上限
+const MQ_MAXMSG_LIMIT: usize = 64;
+const MQ_MSGSIZE_LIMIT: usize = 4096;
+/// 系统中最多同时存在的队列数
+const MQ_QUEUES_MAX: usize = 16;
+
+/// 与Linux的struct mq_attr布局相同
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MqAttr {
+    pub mq_flags: usize,
+    pub mq_maxmsg: usize,
+    pub mq_msgsize: usize,
+    pub mq_curmsgs: usize,
+    pub reserved: [usize; 4],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MqError {
+    /// 非阻塞模式下队列已满或已空
+    WouldBlock,
+    TimedOut,
+    NotFound,
+    Exists,
+    Invalid,
+    /// 队列数目达到上限
+    NoSpace,
+    /// 消息长度超过队列允许的长度，或接收缓冲区过小
+    MsgSize,
+}
+
+struct MessageQueueInner {
+    /// 以优先级为键，同一优先级内先进先出
+    messages: BTreeMap<u32, VecDeque<Vec<u8>>>,
+    count: usize,
+}
+
+pub struct MessageQueue {
+    pub maxmsg: usize,
+    pub msgsize: usize,
+    inner: UPSafeCell<MessageQueueInner>,
+    /// 等待队列变为非满的发送者
+    senders: WaitQueue,
+    /// 等待队列变为非空的接收者
+    receivers: WaitQueue,
+    /// 通过ppoll等待队列状态变化的任务
+    pollers: WaitQueue,
+}
+
+impl MessageQueue {
+    fn new(maxmsg: usize, msgsize: usize) -> Self {
+        Self {
+            maxmsg,
+            msgsize,
+            inner: unsafe {
+                UPSafeCell::new_with_class(
+                    MessageQueueInner {
+                        messages: BTreeMap::new(),
+                        count: 0,
+                    },
+                    LockClass::MessageQueue,
+                )
+            },
+            senders: WaitQueue::new(),
+            receivers: WaitQueue::new(),
+            pollers: WaitQueue::new(),
+        }
+    }
+    /// 队列中当前的消息数
+    pub fn msg_count(&self) -> usize {
+        self.inner.exclusive_access().count
+    }
+    fn try_send(&self, msg: Vec<u8>, prio: u32) -> Result<(), Vec<u8>> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.count == self.maxmsg {
+            return Err(msg);
+        }
+        inner
+            .messages
+            .entry(prio)
+            .or_insert_with(VecDeque::new)
+            .push_back(msg);
+        inner.count += 1;
+        Ok(())
+    }
+    fn try_receive(&self) -> Option<(Vec<u8>, u32)> {
+        let mut inner = self.inner.exclusive_access();
+        // 优先取出优先级最高的消息
+        let prio = *inner.messages.keys().next_back()?;
+        let queue = inner.messages.get_mut(&prio).unwrap();
+        let msg = queue.pop_front().unwrap();
+        if queue.is_empty() {
+            inner.messages.remove(&prio);
+        }
+        inner.count -= 1;
+        Some((msg, prio))
+    }
+    /// 发送一条消息，队列已满时阻塞（nonblock时直接返回WouldBlock），
+    /// expire_ms为阻塞的截止时刻
+    pub fn send(
+        &self,
+        msg: Vec<u8>,
+        prio: u32,
+        nonblock: bool,
+        expire_ms: Option<usize>,
+    ) -> Result<(), MqError> {
+        if msg.len() > self.msgsize {
+            return Err(MqError::MsgSize);
+        }
+        let mut msg = msg;
+        loop {
+            match self.try_send(msg, prio) {
+                Ok(()) => {
+                    self.receivers.wake_one();
+                    self.pollers.wake_all();
+                    return Ok(());
+                }
+                Err(m) => msg = m,
+            }
+            if nonblock {
+                return Err(MqError::WouldBlock);
+            }
+            if !self.senders.wait(expire_ms) {
+                return Err(MqError::TimedOut);
+            }
+        }
+    }
+    /// 接收优先级最高的消息，队列为空时阻塞（nonblock时直接返回WouldBlock），
+    /// expire_ms为阻塞的截止时刻
+    pub fn receive(
+        &self,
+        nonblock: bool,
+        expire_ms: Option<usize>,
+    ) -> Result<(Vec<u8>, u32), MqError> {
+        loop {
+            if let Some(message) = self.try_receive() {
+                self.senders.wake_one();
+                self.pollers.wake_all();
+                return Ok(message);
+            }
+            if nonblock {
+                return Err(MqError::WouldBlock);
+            }
+            if !self.receivers.wait(expire_ms) {
+                return Err(MqError::TimedOut);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// 以名字索引的所有消息队列
+    static ref MQ_MANAGER: UPSafeCell<BTreeMap<String, Arc<MessageQueue>>> =
+        unsafe { UPSafeCell::new_with_class(BTreeMap::new(), LockClass::MessageQueues) };
+}
+
+/// 打开（或新建）名为name的消息队列
+pub fn mq_open(
+    name: &str,
+    flags: OpenFlags,
+    attr: Option<MqAttr>,
+) -> Result<Arc<MqDescriptor>, MqError> {
+    if !name.starts_with('/') || name.len() < 2 || name[1..].contains('/') {
+        return Err(MqError::Invalid);
+    }
+    let mut manager = MQ_MANAGER.exclusive_access();
+    let queue = match manager.get(name) {
+        Some(queue) => {
+            if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
+                return Err(MqError::Exists);
+            }
+            queue.clone()
+        }
+        None => {
+            if !flags.contains(OpenFlags::CREATE) {
+                return Err(MqError::NotFound);
+            }
+            if manager.len() == MQ_QUEUES_MAX {
+                return Err(MqError::NoSpace);
+            }
+            let (maxmsg, msgsize) = match attr {
+                Some(attr) => (attr.mq_maxmsg, attr.mq_msgsize),
+                None => (MQ_MAXMSG_DEFAULT, MQ_MSGSIZE_DEFAULT),
+            };
+            if maxmsg == 0 || maxmsg > MQ_MAXMSG_LIMIT || msgsize == 0 || msgsize > MQ_MSGSIZE_LIMIT
+            {
+                return Err(MqError::Invalid);
+            }
+            let queue = Arc::new(MessageQueue::new(maxmsg, msgsize));
+            manager.insert(String::from(name), queue.clone());
+            queue
+        }
+    };
+    let (readable, writable) = flags.read_write();
+    Ok(Arc::new(MqDescriptor {
+        queue,
+        readable,
+        writable,
+        nonblock: AtomicBool::new(flags.contains(OpenFlags::NONBLOCK)),
+    }))
+}
+
+/// 删除名为name的消息队列，已经打开的描述符仍然可以继续使用它
+pub fn mq_unlink(name: &str) -> Result<(), MqError> {
+    match MQ_MANAGER.exclusive_access().remove(name) {
+        Some(_) => Ok(()),
+        None => Err(MqError::NotFound),
+    }
+}
+
+/// 消息队列描述符，保存在进程的文件描述符表中
+pub struct MqDescriptor {
+    pub queue: Arc<MessageQueue>,
+    pub readable: bool,
+    pub writable: bool,
+    nonblock: AtomicBool,
+}
+
+impl MqDescriptor {
+    pub fn nonblock(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+    pub fn attr(&self) -> MqAttr {
+        MqAttr {
+            mq_flags: if self.nonblock() {
+                OpenFlags::NONBLOCK.bits() as usize
+            } else {
+                0
+            },
+            mq_maxmsg: self.queue.maxmsg,
+            mq_msgsize: self.queue.msgsize,
+            mq_curmsgs: self.queue.msg_count(),
+            reserved: [0; 4],
+        }
+    }
+}
+
+/// 消息队列不是字节流，不能通过read/write访问，但可以用ppoll等待
+impl File for MqDescriptor {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> isize {
+        -EBADF
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        -EBADF
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let count = self.queue.msg_count();
+        let mut ready = PollEvents::empty();
+        if self.readable && count > 0 {
+            ready |= PollEvents::IN;
+        }
+        if self.writable && count < self.queue.maxmsg {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.queue.pollers)
+    }
+}
@@ -2,8 +2,13 @@
 // Rust 的核心库core，可以理解为是经过大幅精简的标准库
 // 它被应用在标准库不能覆盖到的某些特定领域，如裸机环境下
 // 用于操作系统和嵌入式系统的开发，它不需要底层操作系统的支持。
+use crate::backtrace::print_kernel_backtrace;
 use crate::{println, sbi::shutdown};
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 已经有hart在panic（包括回溯途中再次panic）时不再打印调用链
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -17,5 +22,8 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         println!("Panicked: {}", info.message().unwrap());
     }
-    shutdown()
+    if !PANICKING.swap(true, Ordering::SeqCst) {
+        print_kernel_backtrace();
+    }
+    shutdown(true)
 }
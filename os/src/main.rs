@@ -8,6 +8,9 @@ extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
+#[macro_use]
+extern crate log;
+
 #[cfg(feature = "board_k210")]
 #[path = "boards/k210.rs"]
 mod board;
@@ -17,11 +20,20 @@ mod board;
 
 #[macro_use]
 mod console;
+mod backtrace;
+mod cmdline;
 mod config;
+mod drivers;
+mod fdt;
+mod fs;
+mod ipc;
 mod lang_items;
 mod loader;
 mod mm;
+mod net;
+mod random;
 mod sbi;
+mod smp;
 mod sync;
 mod syscall;
 mod task;
@@ -29,9 +41,11 @@ mod timer;
 mod trap;
 
 use core::arch::global_asm;
+use drivers::chardev::{CharDevice, UART};
 
 global_asm!(include_str!("entry.asm"));
 global_asm!(include_str!("link_app.S"));
+global_asm!(include_str!("ksyms.S"));
 
 fn clear_bss() {
     extern "C" {
@@ -44,27 +58,81 @@ fn clear_bss() {
     }
 }
 
+/// SBI跳转到内核时，a0为当前hart的编号，a1为设备树的物理地址。
+/// 只有启动hart会进入这里，其余hart进入rust_main_secondary
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(hartid: usize, dtb: usize) -> ! {
+    // 控制台串口的状态保存在.bss中，需要先清零再输出
+    clear_bss();
+    // idle控制流与trap_return都假定当前hart持有大内核锁，启动hart从这里开始持有
+    sync::kernel_lock();
+    // 串口的地址也来自设备树
+    let has_fdt = fdt::init(dtb);
+    UART.init();
+    console::init();
     println!("    _                _ _        ___  ____");
     println!("   / \\   _ __   ___ | | | ___  / _ \\/ ___|");
     println!("  / _ \\ | '_ \\ / _ \\| | |/ _ \\| | | \\___ \\");
     println!(" / ___ \\| |_) | (_) | | | (_) | |_| |___) |");
     println!("/_/   \\_\\ .__/ \\___/|_|_|\\___/ \\___/|____/");
     println!("        |_|");
-    clear_bss();
-    println!("[kernel] Hello, World!");
-    println!("[kernel] Now init the memory manager...");
+    cmdline::init();
+    info!("Hello, World!");
+    let machine = fdt::machine();
+    if !has_fdt {
+        warn!("no valid device tree at {:#x}, using board defaults", dtb);
+    }
+    info!(
+        "memory [{:#x}, {:#x}), timebase {} Hz, {} virtio-mmio slots",
+        machine.memory.0,
+        machine.memory.1,
+        machine.timebase_frequency,
+        machine.virtio.iter().flatten().count()
+    );
+    info!(
+        "boot hart {}, {} harts in device tree",
+        hartid,
+        machine.harts.count_ones().max(1)
+    );
+    timer::init();
+    info!("Now init the memory manager...");
     mm::init();
-    println!("[kernel] back to rust_main!");
+    info!("back to rust_main!");
     mm::remap_test();
+    fs::init();
     // 第一个加载init_proc
     task::add_initproc();
-    println!("after initproc!");
+    debug!("after initproc!");
     trap::init();
+    drivers::init();
+    // 随机数生成器在探测到virtio熵源之后播种，网络协议栈随后才使用随机数
+    random::init();
+    net::init();
     trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
     timer::set_next_trigger();
     loader::list_apps();
+    smp::hart_online();
+    smp::start_secondary_harts();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
+}
+
+/// 其余hart的入口，a0为当前hart的编号。等启动hart完成全部初始化后，
+/// 切换到内核地址空间，打开时钟中断并进入自己的idle控制流；外部中断仍然只送到启动hart
+#[no_mangle]
+pub fn rust_main_secondary(hartid: usize) -> ! {
+    smp::wait_for_boot_hart();
+    sync::kernel_lock();
+    mm::KERNEL_SPACE.lock().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    info!(
+        "hart {} online, {} harts running",
+        hartid,
+        smp::hart_online()
+    );
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use super::{PhysAddr, PhysPageNum};
-use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::fdt::machine;
+use crate::sync::{IrqSafeMutex, LockClass};
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -58,6 +58,19 @@ impl StackFrameAllocator {
         self.current = l.0;
         self.end = r.0;
     }
+    /// 分配pages个物理地址连续的页帧，返回第一个页帧的页号。
+    /// 只从尚未分配过的区间中分配，供需要连续物理内存的设备DMA使用
+    pub fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if self.end - self.current < pages {
+            return None;
+        }
+        self.current += pages;
+        Some((self.current - pages).into())
+    }
+    /// 还可以分配的物理页帧数
+    pub fn available(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
 }
 
 // 这里是具体实现
@@ -104,23 +117,29 @@ impl FrameAllocator for StackFrameAllocator {
 
 /// StackFrameAllocator的全局实例：FRAME_ALLOCATOR，
 /// FrameAllocatorImpl是StackFrameAllocator类型的别名
-/// 这里使用UPSafeCell对这个全局变量进行包装
-/// 每次对分配器操作时，都需要通过FRAME_ALLOCATOR.exclusive_access()拿到分配器的可变借用
+/// 这里使用IrqSafeMutex对这个全局变量进行包装，中断处理中释放页帧时也不会死锁
+/// 每次对分配器操作时，都需要通过FRAME_ALLOCATOR.lock()拿到分配器的可变引用
 // 什么是RAII？
 type FrameAllocatorImpl = StackFrameAllocator;
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> = unsafe {
-        UPSafeCell::new(FrameAllocatorImpl::new())
-    };
+    pub static ref FRAME_ALLOCATOR: IrqSafeMutex<FrameAllocatorImpl> =
+        IrqSafeMutex::new_with_class(FrameAllocatorImpl::new(), LockClass::FrameAllocator);
 }
 
 /// 物理页帧全局管理器FRAME_ALLOCATOR初始化
-/// 根据ekernel和MEMORY_END指定可分配的物理页帧
+/// 根据ekernel和设备树给出的内存范围指定可分配的物理页帧，
+/// 设备树位于内存末尾时，它及其之后的内存不会被分配出去，以便之后再次读取
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(PhysAddr::from(ekernel as usize).ceil(), PhysAddr::from(MEMORY_END).floor());
+    let machine = machine();
+    let (dtb_start, _) = machine.dtb;
+    let mut memory_end = machine.memory.1;
+    if dtb_start > ekernel as usize && dtb_start < memory_end {
+        memory_end = dtb_start;
+    }
+    FRAME_ALLOCATOR.lock().init(PhysAddr::from(ekernel as usize).ceil(), PhysAddr::from(memory_end).floor());
 }
 
 /// 给其它内核模块调用的分配物理页帧的接口，
@@ -129,12 +148,27 @@ pub fn init_frame_allocator() {
 /// 将一个物理页帧的生命周期绑定到一个FrameTracker变量上。
 pub fn frame_alloc() -> Option<FrameTracker> {
     // 将每个分配来的物理页帧的页号都作为参数传给FrameTracker的new方法来创建一个FrameTracker实例
-    FRAME_ALLOCATOR.exclusive_access().alloc().map(|ppn| FrameTracker::new(ppn))
+    FRAME_ALLOCATOR.lock().alloc().map(|ppn| FrameTracker::new(ppn))
+}
+
+/// 分配pages个物理地址连续的页帧
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let start = FRAME_ALLOCATOR.lock().alloc_contiguous(pages)?;
+    Some(
+        (start.0..start.0 + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// 还可以分配的物理页帧数，供分配大量页帧之前预先检查
+pub fn frame_available() -> usize {
+    FRAME_ALLOCATOR.lock().available()
 }
 
 /// 回收物理页帧的接口
 fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
 }
 
 #[allow(unused)]
@@ -9,9 +9,12 @@ use bitflags::*;
 use lazy_static::*;
 use riscv::register::satp;
 
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
-use crate::sync::UPSafeCell;
+use crate::config::{PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_SPACE_END, USER_STACK_SIZE};
+use crate::fdt::machine;
+use crate::sync::{preemptible, LockClass, SpinMutex};
+use crate::syscall::errno::{SysError, SysResult};
 
+use super::shm::{ShmAttachment, ShmSegment};
 use super::{frame_alloc, FrameTracker};
 use super::{PageTable, PageTableEntry, PTEFlags};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
@@ -31,12 +34,12 @@ extern "C" {
 }
 
 // 创建一个内核地址空间的实例，是静态变量
-// Arc<T>提供共享引用，UPSafeCell<T>提供内部可变引用访问
+// Arc<T>提供共享引用，SpinMutex<T>在多个hart之间互斥地提供可变引用
 lazy_static! {
     /// 内核地址空间
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> = Arc::new(unsafe{
-        UPSafeCell::new(MemorySet::new_kernel())
-    });
+    pub static ref KERNEL_SPACE: Arc<SpinMutex<MemorySet>> = Arc::new(
+        SpinMutex::new_with_class(MemorySet::new_kernel(), LockClass::KernelSpace)
+    );
 }
 
 /// 地址空间
@@ -65,17 +68,32 @@ impl MemorySet {
     }
     /// Assume that no conflicts.
     /// 在当前地址空间插入一个Framed方式映射到物理内存的逻辑段，
-    /// 调用者要保证同一地址空间内的任意两个逻辑段不能存在交集
+    /// 调用者要保证同一地址空间内的任意两个逻辑段不能存在交集。
+    /// 分配不到物理页帧时返回ENOMEM，地址空间不变
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
-        self.push(
+    ) -> SysResult<()> {
+        self.try_push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
+    }
+    /// 插入一个由mmap建立的匿名映射，分配不到物理页帧时返回ENOMEM，地址空间不变
+    pub fn insert_anonymous_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> SysResult<()> {
+        self.try_push(MapArea::new(
+            start_va,
+            end_va,
+            MapType::Anonymous,
+            permission,
+        ))
     }
     /// 通过逻辑段的起始虚拟页号删除整个逻辑段
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
@@ -89,15 +107,92 @@ impl MemorySet {
             self.areas.remove(idx);
         }
     }
+    /// 将共享内存段挂接到start_va开始的位置
+    pub fn attach_shm(
+        &mut self,
+        start_va: VirtAddr,
+        segment: Arc<ShmSegment>,
+        permission: MapPermission,
+    ) -> SysResult<()> {
+        self.try_push(MapArea::new_shared(start_va, segment, permission), None)
+    }
+    /// 解除起始虚拟页号为start_vpn的共享内存段的挂接，找不到这样的挂接则返回false
+    pub fn detach_shm(&mut self, start_vpn: VirtPageNum) -> bool {
+        let found = self.areas.iter().any(|area| {
+            area.map_type == MapType::Shared && area.vpn_range.get_start() == start_vpn
+        });
+        if found {
+            self.remove_area_with_start_vpn(start_vpn);
+        }
+        found
+    }
+    /// 将设备内存的物理页帧frames映射到start_va开始的位置
+    pub fn insert_device_area(
+        &mut self,
+        start_va: VirtAddr,
+        frames: Vec<Arc<FrameTracker>>,
+        permission: MapPermission,
+    ) -> SysResult<()> {
+        self.try_push(MapArea::new_device(start_va, frames, permission), None)
+    }
+    /// 删除恰好从start_vpn开始、长为page_count页、由mmap建立的逻辑段。
+    /// ELF段、用户栈与trap上下文属于内核，共享内存段只能用shmdt解除挂接，
+    /// 找不到这样的逻辑段则返回false
+    pub fn remove_mapping(&mut self, start_vpn: VirtPageNum, page_count: usize) -> bool {
+        let found = self.areas.iter().any(|area| {
+            matches!(area.map_type, MapType::Anonymous | MapType::Device)
+                && area.vpn_range.get_start() == start_vpn
+                && area.vpn_range.get_end().0 - start_vpn.0 == page_count
+        });
+        if found {
+            self.remove_area_with_start_vpn(start_vpn);
+        }
+        found
+    }
+    /// [start_vpn, start_vpn + page_count)是否与已有的逻辑段都不相交
+    pub fn is_free(&self, start_vpn: VirtPageNum, page_count: usize) -> bool {
+        let end_vpn = VirtPageNum(start_vpn.0 + page_count);
+        !self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+        })
+    }
+    /// 从hint开始向高地址寻找一段长为page_count页、未被使用的虚拟地址区间，
+    /// 区间不能超出用户地址空间，找不到时返回None
+    pub fn find_free_area(&self, hint: VirtAddr, page_count: usize) -> Option<VirtAddr> {
+        let mut start_vpn = hint.ceil();
+        loop {
+            let end_vpn = match start_vpn.0.checked_add(page_count) {
+                Some(end) if end <= USER_SPACE_END / PAGE_SIZE => VirtPageNum(end),
+                _ => return None,
+            };
+            match self.areas.iter().find(|area| {
+                area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+            }) {
+                Some(area) => start_vpn = area.vpn_range.get_end(),
+                None => return Some(start_vpn.into()),
+            }
+        }
+    }
     /// 在当前地址空间插入一个新的逻辑段map_area，
     /// 如果是以相对随机方式映射到内存，可选地在那些被映射到的物理页帧上写入一些初始化数据data
-    /// 先将逻辑段对应的虚拟页号
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// 先将逻辑段对应的虚拟页号。
+    /// 只用于建立内核地址空间，分配不到物理页帧时panic
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        let start_vpn = map_area.vpn_range.get_start();
+        if self.try_push(map_area, data).is_err() {
+            panic!("out of frames when mapping {:?}", start_vpn);
+        }
+    }
+    /// 同push，但分配不到物理页帧时返回ENOMEM，地址空间不变
+    fn try_push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> SysResult<()> {
+        if !map_area.map(&mut self.page_table) {
+            return Err(SysError::ENOMEM);
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        Ok(())
     }
     /// Mention that trampoline is not collected by areas.
     /// 直接在多级页表中插入一个
@@ -118,17 +213,17 @@ impl MemorySet {
         memory_set.map_trampoline();
         // map kernel sections
         // 打印内核中各个段的起始地址和终结地址
-        println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
-        println!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
-        println!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
-        println!(
+        debug!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
+        debug!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
+        debug!(".data [{:#x}, {:#x})", sdata as usize, edata as usize);
+        debug!(
             ".bss [{:#x}, {:#x})",
             sbss_with_stack as usize, ebss as usize
         );
         // 从低地址到高地址依次创建5个逻辑段
         // 并通过push方法将它们插入到内核地址空间
         // 对于内核的.text .rodata .data .bss这四个逻辑段，全部采用恒等映射
-        println!("mapping .text section");
+        debug!("mapping .text section");
         memory_set.push(
             MapArea::new(
                 (stext as usize).into(),
@@ -138,7 +233,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .rodata section");
+        debug!("mapping .rodata section");
         memory_set.push(
             MapArea::new(
                 (srodata as usize).into(),
@@ -148,7 +243,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .data section");
+        debug!("mapping .data section");
         memory_set.push(
             MapArea::new(
                 (sdata as usize).into(),
@@ -158,7 +253,7 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping .bss section");
+        debug!("mapping .bss section");
         memory_set.push(
             MapArea::new(
                 (sbss_with_stack as usize).into(),
@@ -168,27 +263,40 @@ impl MemorySet {
             ),
             None,
         );
-        println!("mapping physical memory");
+        debug!("mapping physical memory");
         // 内核的地址空间中需要存在这样一个恒等映射
         // 从内核数据段结束的地方开始一直到整个内存的终结地址
         // 这样保证了启用页表机制之后，内核仍能以纯软件的方式来读写这些物理页帧
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
-                MEMORY_END.into(),
+                machine().memory.1.into(),
                 MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
+        debug!("mapping memory-mapped registers");
+        for device in machine().mmio_regions() {
+            memory_set.push(
+                MapArea::new(
+                    device.base.into(),
+                    (device.base + device.size).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp and entry point.
     /// 以ELF格式解析出应用的各个数据段并对应生成应用的地址空间，
     /// 返回应用地址空间，用户栈栈底地址，和入口点。
-    /// 栈底在高地址！在本函数中用user_stack_top标识
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// 栈底在高地址！在本函数中用user_stack_top标识。
+    /// 分配不到物理页帧时返回ENOMEM，已分配的页帧随地址空间一同释放
+    pub fn from_elf(elf_data: &[u8]) -> SysResult<(Self, usize, usize)> {
         let mut memory_set = Self::new_bare();
         // map trampoline
         // 将跳板插入到应用地址空间的最高页面！
@@ -228,11 +336,10 @@ impl MemorySet {
                 // 新建虚拟地址空间中的一个逻辑段，对应ELF文件中要映射到虚拟内存的段
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                 max_end_vpn = map_area.vpn_range.get_end();
-                // 从ELF文件映射到上述逻辑段
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                // 从ELF文件映射到上述逻辑段，逐页复制可能很耗时，期间允许抢占
+                let data =
+                    &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
+                preemptible(|| memory_set.try_push(map_area, Some(data)))?;
             }
         }
         // 开始处理用户栈
@@ -243,7 +350,7 @@ impl MemorySet {
         // 栈底地址为bss段
         user_stack_bottom += PAGE_SIZE;
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
+        memory_set.try_push(
             MapArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
@@ -251,10 +358,10 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
         // map TrapContext
         // 次高页面存放trap上下文
-        memory_set.push(
+        memory_set.try_push(
             MapArea::new(
                 TRAP_CONTEXT.into(),
                 TRAMPOLINE.into(),
@@ -262,33 +369,58 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         // 返回
-        (
+        Ok((
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
+    }
+    /// 记下用户地址空间的布局，交给from_layout构建一个相同的地址空间。
+    /// 只在持有地址空间的借用时调用，布局中不含对原地址空间的引用
+    pub fn layout(&self) -> UserLayout {
+        let areas = self.areas.iter().map(MapArea::from_another).collect();
+        let mut frames = Vec::new();
+        for area in self.areas.iter() {
+            // 共享内存段与设备内存映射到同一组物理页帧上，不需要复制
+            if area.map_type == MapType::Shared || area.map_type == MapType::Device {
+                continue;
+            }
+            for vpn in area.vpn_range {
+                frames.push(self.translate(vpn).unwrap().ppn());
+            }
+        }
+        UserLayout { areas, frames }
     }
-    /// 构建一个**与传入的地址空间相同的**地址空间
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// 构建一个**与布局相同的**地址空间：分配页帧并复制源页帧的内容。
+    /// 复制整个地址空间可能很耗时，期间打开中断并允许抢占，以免推迟其他任务，
+    /// 调用者须保证源页帧在此期间不会被回收。分配不到物理页帧时返回ENOMEM
+    pub fn from_layout(layout: UserLayout) -> SysResult<MemorySet> {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
-        for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+        preemptible(|| {
+            let mut src_frames = layout.frames.iter();
+            // copy data sections/trap_context/user_stack
+            for area in layout.areas {
+                let copy = area.map_type != MapType::Shared && area.map_type != MapType::Device;
+                let vpn_range = area.vpn_range;
+                memory_set.try_push(area, None)?;
+                if !copy {
+                    continue;
+                }
+                for vpn in vpn_range {
+                    let src_ppn = src_frames.next().unwrap();
+                    let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
             }
-        }
-        memory_set
+            Ok(())
+        })?;
+        Ok(memory_set)
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -312,6 +444,13 @@ impl MemorySet {
     }
 }
 
+/// 用户地址空间的布局，由MemorySet::layout生成：
+/// 各逻辑段的副本（尚未映射），以及按逻辑段与页号顺序排列的、需要复制内容的源页帧
+pub struct UserLayout {
+    areas: Vec<MapArea>,
+    frames: Vec<PhysPageNum>,
+}
+
 /// **逻辑段MapArea**描述一段地址连续的虚拟内存，
 /// 即地址区间中的一段实际可用的地址连续的虚拟地址区间
 pub struct MapArea {
@@ -319,13 +458,15 @@ pub struct MapArea {
     vpn_range: VPNRange,
     /// 保存该逻辑段内的虚拟页号到物理页号的映射，
     /// 拥有物理页号对应的物理页帧的所有权！RAII
-    /// 仅当相对随机映射时才有用
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// 仅当相对随机映射或共享映射时才有用，共享映射时页帧由多个逻辑段共同持有
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     /// 描述逻辑段内的所有虚拟页面映射到物理页帧的方式：是恒等映射还是相对随机映射？
     map_type: MapType,
     /// 该逻辑段的访问方式，它是页表项标志位 PTEFlags 的一个子集，仅保留 U/R/W/X 四个标志位
     /// 是否可读可写可执行？在CPU处于U特权级下能否被访问？
     map_perm: MapPermission,
+    /// 共享映射时所挂接的共享内存段
+    shm: Option<ShmAttachment>,
 }
 
 impl MapArea {
@@ -345,21 +486,61 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            shm: None,
+        }
+    }
+    /// 新建一个映射到共享内存段的逻辑段
+    pub fn new_shared(
+        start_va: VirtAddr,
+        segment: Arc<ShmSegment>,
+        map_perm: MapPermission,
+    ) -> Self {
+        let start_vpn: VirtPageNum = start_va.floor();
+        let end_vpn = VirtPageNum(start_vpn.0 + segment.page_count());
+        Self {
+            vpn_range: VPNRange::new(start_vpn, end_vpn),
+            data_frames: BTreeMap::new(),
+            map_type: MapType::Shared,
+            map_perm,
+            shm: Some(ShmAttachment::new(segment)),
+        }
+    }
+    /// 新建一个映射到设备内存的逻辑段，frames中的页帧依次映射到start_va开始的各页
+    pub fn new_device(
+        start_va: VirtAddr,
+        frames: Vec<Arc<FrameTracker>>,
+        map_perm: MapPermission,
+    ) -> Self {
+        let start_vpn: VirtPageNum = start_va.floor();
+        let end_vpn = VirtPageNum(start_vpn.0 + frames.len());
+        Self {
+            vpn_range: VPNRange::new(start_vpn, end_vpn),
+            data_frames: (start_vpn.0..end_vpn.0).map(VirtPageNum).zip(frames).collect(),
+            map_type: MapType::Device,
+            map_perm,
+            shm: None,
         }
     }
     /// 从另一个逻辑段MapArea得到一个一样的逻辑段MapArea
     pub fn from_another(another: &MapArea) -> Self {
+        // 设备内存的页帧由各个映射共同持有，新的逻辑段映射到同一组页帧
+        let data_frames = if another.map_type == MapType::Device {
+            another.data_frames.clone()
+        } else {
+            BTreeMap::new()
+        };
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
-            data_frames: BTreeMap::new(),
+            data_frames,
             map_type: another.map_type,
             map_perm: another.map_perm,
+            shm: another.shm.clone(),
         }
     }
     // map和unmap的实现取决于映射方式：是恒等映射还是相对随机映射？
     /// 在多级页表中进行键值对的插入，
-    /// 也就是填充一个页表项，需要要提供虚拟页号和页表
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// 也就是填充一个页表项，需要要提供虚拟页号和页表。分配不到物理页帧时返回false
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum;
         match self.map_type {
             // 如果是恒等映射，那么虚拟页号=物理页号
@@ -367,24 +548,45 @@ impl MapArea {
                 ppn = PhysPageNum(vpn.0);
             }
             // 如果是相对随机映射，需要分配一个物理页帧
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+            MapType::Framed | MapType::Anonymous => {
+                let frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                ppn = frame.ppn;
+                self.data_frames.insert(vpn, Arc::new(frame));
+            }
+            // 如果是共享映射，使用共享内存段中对应的物理页帧
+            MapType::Shared => {
+                let idx = vpn.0 - self.vpn_range.get_start().0;
+                let frame = self.shm.as_ref().unwrap().segment.frames[idx].clone();
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
+            // 如果是设备内存，页帧在创建逻辑段时就已经确定
+            MapType::Device => {
+                ppn = self.data_frames[&vpn].ppn;
+            }
         }
         // 页表项标志位取决于逻辑段的映射方式，即self.map_perm
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         // 插入页表项
         // 冲突问题？如果在一个地址空间内，同时启用恒等映射和相对随机映射，可能会引发冲突导致map函数中panic！
         // 当然，不会出现这种情况
-        page_table.map(vpn, ppn, pte_flags);
+        if !page_table.try_map(vpn, ppn, pte_flags) {
+            // 设备内存的页帧在创建逻辑段时就已放入，其余的撤销上面的插入
+            if self.map_type != MapType::Device {
+                self.data_frames.remove(&vpn);
+            }
+            return false;
+        }
+        true
     }
     #[allow(unused)]
     /// 删除一个页表项
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            // 回收相对随机映射得到的物理页帧
+        if self.map_type != MapType::Identical {
+            // 回收相对随机映射得到的物理页帧，共享映射的页帧在最后一个引用消失时回收
             self.data_frames.remove(&vpn);
         }
         // 恒等映射得到的物理页帧在哪里回收？
@@ -396,11 +598,17 @@ impl MapArea {
     }
     /// 将当前逻辑段到物理内存的映射，
     /// 加入到**当前逻辑段所属的地址空间**的多级页表中
-    /// 也就是填充页表项
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// 也就是填充页表项。分配不到物理页帧时撤销已经建立的映射并返回false
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if !self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return false;
+            }
         }
+        true
     }
     #[allow(unused)]
     /// 删除当前逻辑段到物理内存的映射
@@ -446,6 +654,12 @@ pub enum MapType {
     Identical,
     /// 虚地址和物理地址的映射关系相对随机
     Framed,
+    /// 由mmap建立的匿名映射，与Framed相同，但可以被munmap解除
+    Anonymous,
+    /// 映射到共享内存段的物理页帧，fork时不复制
+    Shared,
+    /// 映射到设备内存（如帧缓冲）的物理页帧，fork时不复制
+    Device,
 }
 
 bitflags! {
@@ -462,7 +676,7 @@ bitflags! {
 /// 检查内核地址空间的多级页表是否被正确设置
 #[allow(unused)]
 pub fn remap_test() {
-    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
     let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
@@ -481,5 +695,5 @@ pub fn remap_test() {
         kernel_space.page_table.translate(mid_data.floor()).unwrap().executable(),
         false,
     );
-    println!("remap_test passed!");
+    info!("remap_test passed!");
 }
\ No newline at end of file
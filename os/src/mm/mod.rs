@@ -3,14 +3,18 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod user_ptr;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_alloc_contiguous, frame_available, FrameTracker};
 pub use memory_set::remap_test;
 pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry};
-use page_table::{PTEFlags, PageTable};
+pub use page_table::{PageTable, PageTableEntry, UserBuffer};
+use page_table::PTEFlags;
+pub use shm::{shm_create, shm_find, shm_find_by_key};
+pub use user_ptr::{UserCStr, UserPtr, UserSlice};
 
 /// 内存管理系统的初始化
 pub fn init() {
@@ -21,6 +25,6 @@ pub fn init() {
     // 开启分页模式
     // 当一个函数接受类型为 &mut T 的参数却被传入一个类型为 &mut RefMut<'_, T> 的参数的时候
     // 编译器会自动进行类型转换使参数匹配
-    KERNEL_SPACE.exclusive_access().activate();
+    KERNEL_SPACE.lock().activate();
     // 自此，启用了内核动态内存分配，物理页帧管理和分页模式
 }
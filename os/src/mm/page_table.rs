@@ -1,6 +1,5 @@
 // os/src/mm/page_table.rs
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-use alloc::string::String;
+use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
@@ -67,6 +66,10 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// 判断页表项是否允许用户态访问
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
 }
 
 /// PageTable类型用于描述某个应用的地址空间对应的页表，我将其称之为总页表，
@@ -102,7 +105,7 @@ impl PageTable {
     }
 
     /// 根据虚拟页号，在多级页表的各个节点中找到一个虚拟页号对应的页表项
-    /// 找不到就创建，返回对这个页表项的可变引用
+    /// 找不到就创建，返回对这个页表项的可变引用，分配不到页表节点时返回None
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -118,7 +121,7 @@ impl PageTable {
             // 也就是分配一个页面给这个页表项节点，并将页面的标志位置为有效
             // 但是不修改叶节点，因为i=2时已返回
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 // 还要将新分配的物理页帧移动到向量frames中方便后续的自动回收
                 self.frames.push(frame);
@@ -154,10 +157,20 @@ impl PageTable {
     /// 通过map方法在多级页表中插入一个键值对
     /// 要求传入虚拟页号、物理页号和标志位
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        if !self.try_map(vpn, ppn, flags) {
+            panic!("out of frames for the page table when mapping {:?}", vpn);
+        }
+    }
+    /// 同map，但分配不到页表节点所需的物理页帧时返回false
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        let pte = match self.find_pte_create(vpn) {
+            Some(pte) => pte,
+            None => return false,
+        };
         // 如果页表项是有效的，直接panic，因为这表示该页已被分配
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        true
     }
     #[allow(unused)]
     /// 通过unmap方法来删除一个键值对，仅需给出作为索引的虚拟页号
@@ -170,6 +183,7 @@ impl PageTable {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| { pte.clone() })
     }
+    #[allow(unused)]
     /// 根据传入的虚拟地址返回对应的Option<物理地址>
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
         self.find_pte(va.clone().floor()).map(|pte| {
@@ -188,70 +202,77 @@ impl PageTable {
     }
 }
 
-/// 传入当前应用的token，buf的虚拟地址和长度，返回对u8缓冲区的可变引用
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);    // 构建临时页表
-    let mut start = ptr as usize; // 缓冲区buf起始地址
-    let end = start + len; // 缓冲区buf终止地址
-    // v用于保存对u8缓冲区的可变引用，由于缓冲区可能因为不在同一页要被分成多段，所以使用vec保存多个引用
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        // 将缓冲区起始地址strat_va转换成虚拟页号继而转化成物理页号
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        // 得到下一页面的页面号
-        vpn.step();
-        // 下一页面的页面号转化成当前页面的终止地址
-        let mut end_va: VirtAddr = vpn.into();
-        // 缓冲区终止地址 > 当前页面的终止地址？
-        // 如果是，那么先取 缓冲区终止地址 和 当前页面终止地址的 最小值，也就是当前页面的终止地址
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            // 对于buf超过当前这一页的情况
-            // push 一整物理页上 从start_va的偏移地址开始到一整页终止 u8字节数组 的可变引用
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            // 对于buf未超过当前这一页的情况
-            // push 一整物理页上 从start_va的偏移地址开始到end_va的偏移地址结束 u8字节数组 的可变引用
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+/// 用户地址空间中的一段缓冲区，由于可能跨越多个物理页帧，被拆分成若干段
+pub struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.len()).sum()
+    }
+    /// 将src拷贝到缓冲区开头，返回拷贝的字节数
+    pub fn write_from(&mut self, src: &[u8]) -> usize {
+        let mut copied = 0;
+        for buffer in self.buffers.iter_mut() {
+            if copied == src.len() {
+                break;
+            }
+            let n = buffer.len().min(src.len() - copied);
+            buffer[..n].copy_from_slice(&src[copied..copied + n]);
+            copied += n;
         }
-        // 对于buf超过当前这一页
-        start = end_va.into();
+        copied
+    }
+    /// 将缓冲区开头的内容拷贝到dst，返回拷贝的字节数
+    pub fn read_to(&self, dst: &mut [u8]) -> usize {
+        let mut copied = 0;
+        for buffer in self.buffers.iter() {
+            if copied == dst.len() {
+                break;
+            }
+            let n = buffer.len().min(dst.len() - copied);
+            dst[copied..copied + n].copy_from_slice(&buffer[..n]);
+            copied += n;
+        }
+        copied
     }
-    v
 }
 
-/// 在内核中查找用户地址空间中的字符串。
-// 这个函数签名可能设计的不太好，因为ptr是应用名字字符串在用户地址空间的起始地址，用usize类型比较好
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    // 这里可能也设计的不太好？对于物理地址仅进行一次查询即可，没必要每次循环都根据虚拟地址来查物理地址，每次循环物理地址+1即可
-    // 非也，万一不在同一个页面呢？还是有必要对于每个虚拟地址都查找物理地址的
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
+impl IntoIterator for UserBuffer {
+    type Item = *mut u8;
+    type IntoIter = UserBufferIterator;
+    fn into_iter(self) -> Self::IntoIter {
+        UserBufferIterator {
+            buffers: self.buffers,
+            current_buffer: 0,
+            current_idx: 0,
         }
     }
-    string
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    //println!("into translated_refmut!");
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
-}
\ No newline at end of file
+/// 按字节遍历UserBuffer
+pub struct UserBufferIterator {
+    buffers: Vec<&'static mut [u8]>,
+    current_buffer: usize,
+    current_idx: usize,
+}
+
+impl Iterator for UserBufferIterator {
+    type Item = *mut u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current_buffer < self.buffers.len() {
+            if self.current_idx < self.buffers[self.current_buffer].len() {
+                let r = &mut self.buffers[self.current_buffer][self.current_idx] as *mut _;
+                self.current_idx += 1;
+                return Some(r);
+            }
+            self.current_idx = 0;
+            self.current_buffer += 1;
+        }
+        None
+    }
+}
@@ -0,0 +1,109 @@
+//! System V风格的共享内存段
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::sync::{LockClass, UPSafeCell};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// 共享内存段，持有一组引用计数的物理页帧，
+/// 每个挂接了该段的逻辑段都持有这些页帧的一份引用
+pub struct ShmSegment {
+    pub id: usize,
+    pub key: usize,
+    pub size: usize,
+    pub frames: Vec<Arc<FrameTracker>>,
+    /// 当前挂接数
+    nattch: AtomicUsize,
+}
+
+impl ShmSegment {
+    pub fn page_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+struct ShmManager {
+    next_id: usize,
+    segments: BTreeMap<usize, Arc<ShmSegment>>,
+}
+
+lazy_static! {
+    static ref SHM_MANAGER: UPSafeCell<ShmManager> = unsafe {
+        UPSafeCell::new_with_class(
+            ShmManager {
+                next_id: 0,
+                segments: BTreeMap::new(),
+            },
+            LockClass::Shm,
+        )
+    };
+}
+
+/// 按key查找共享内存段
+pub fn shm_find_by_key(key: usize) -> Option<Arc<ShmSegment>> {
+    SHM_MANAGER
+        .exclusive_access()
+        .segments
+        .values()
+        .find(|segment| segment.key == key)
+        .cloned()
+}
+
+/// 按id查找共享内存段
+pub fn shm_find(id: usize) -> Option<Arc<ShmSegment>> {
+    SHM_MANAGER.exclusive_access().segments.get(&id).cloned()
+}
+
+/// 新建一个大小至少为size字节的共享内存段，物理内存不足时返回None
+pub fn shm_create(key: usize, size: usize) -> Option<Arc<ShmSegment>> {
+    let page_count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    let mut frames = Vec::with_capacity(page_count);
+    for _ in 0..page_count {
+        frames.push(Arc::new(frame_alloc()?));
+    }
+    let mut manager = SHM_MANAGER.exclusive_access();
+    let id = manager.next_id;
+    manager.next_id += 1;
+    let segment = Arc::new(ShmSegment {
+        id,
+        key,
+        size,
+        frames,
+        nattch: AtomicUsize::new(0),
+    });
+    manager.segments.insert(id, segment.clone());
+    Some(segment)
+}
+
+/// 共享内存段的一次挂接，随所属的逻辑段一起复制（fork）和销毁（shmdt、exec、exit），
+/// 最后一个挂接被销毁时，共享内存段也随之释放
+pub struct ShmAttachment {
+    pub segment: Arc<ShmSegment>,
+}
+
+impl ShmAttachment {
+    pub fn new(segment: Arc<ShmSegment>) -> Self {
+        segment.nattch.fetch_add(1, Ordering::Relaxed);
+        Self { segment }
+    }
+}
+
+impl Clone for ShmAttachment {
+    fn clone(&self) -> Self {
+        Self::new(self.segment.clone())
+    }
+}
+
+impl Drop for ShmAttachment {
+    fn drop(&mut self) {
+        if self.segment.nattch.fetch_sub(1, Ordering::Relaxed) == 1 {
+            SHM_MANAGER
+                .exclusive_access()
+                .segments
+                .remove(&self.segment.id);
+        }
+    }
+}
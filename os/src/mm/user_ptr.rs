@@ -0,0 +1,203 @@
+//! 内核访问用户内存的接口。每次访问都通过进程的页表检查所涉及的每一页：
+//! 必须已映射、允许用户态访问，并且按读写方向具有R或W权限，否则返回EFAULT。
+//! 访问可以跨越页边界，值按字节复制，因此对用户指针的对齐没有要求
+use super::{PageTable, PhysAddr, UserBuffer, VirtAddr};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::syscall::errno::{SysError, SysResult};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::{size_of, MaybeUninit};
+use core::slice;
+
+/// 从用户内存读取的字符串的最大长度（不含结尾的'\0'），与Linux的PATH_MAX相当
+const MAX_CSTR_LEN: usize = 4095;
+/// UserSlice::read一次读入内核的最大字节数，以免用户给出的长度耗尽内核堆
+const MAX_READ_LEN: usize = 64 * 1024;
+
+/// 访问用户内存的方向，读取要求R权限，写入要求W权限
+#[derive(Copy, Clone)]
+enum Access {
+    Read,
+    Write,
+}
+
+/// 把用户地址空间中的[va, va + len)拆分为各物理页上的片段
+fn user_pages(
+    token: usize,
+    va: usize,
+    len: usize,
+    access: Access,
+) -> SysResult<Vec<&'static mut [u8]>> {
+    let end = va
+        .checked_add(len)
+        .filter(|&end| end <= USER_SPACE_END)
+        .ok_or(SysError::EFAULT)?;
+    let page_table = PageTable::from_token(token);
+    let mut pages = Vec::new();
+    let mut start = va;
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let pte = page_table
+            .translate(start_va.floor())
+            .ok_or(SysError::EFAULT)?;
+        let permitted = match access {
+            Access::Read => pte.readable(),
+            Access::Write => pte.writable(),
+        };
+        if !pte.is_valid() || !pte.is_user() || !permitted {
+            return Err(SysError::EFAULT);
+        }
+        let chunk_end = (start / PAGE_SIZE + 1) * PAGE_SIZE;
+        let chunk_end = chunk_end.min(end);
+        let offset = start_va.page_offset();
+        pages.push(&mut pte.ppn().get_bytes_array()[offset..offset + (chunk_end - start)]);
+        start = chunk_end;
+    }
+    Ok(pages)
+}
+
+/// 把各个片段中的内容依次复制到dst
+fn copy_from_pages(pages: Vec<&'static mut [u8]>, dst: &mut [u8]) {
+    let mut copied = 0;
+    for page in pages {
+        dst[copied..copied + page.len()].copy_from_slice(page);
+        copied += page.len();
+    }
+}
+
+/// 把src依次复制到各个片段中
+fn copy_to_pages(pages: Vec<&'static mut [u8]>, src: &[u8]) {
+    let mut copied = 0;
+    for page in pages {
+        page.copy_from_slice(&src[copied..copied + page.len()]);
+        copied += page.len();
+    }
+}
+
+/// 指向用户地址空间中一个T的指针。T须是任意位模式都合法的类型，
+/// 一般是与Linux布局相同的#[repr(C)]结构体或整数
+pub struct UserPtr<T> {
+    token: usize,
+    ptr: *const T,
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// token为ptr所在地址空间的页表
+    pub fn new(token: usize, ptr: *const T) -> Self {
+        Self { token, ptr }
+    }
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+    /// 读出用户内存中的值
+    pub fn read(&self) -> SysResult<T> {
+        let pages = user_pages(self.token, self.ptr as usize, size_of::<T>(), Access::Read)?;
+        let mut value = MaybeUninit::<T>::uninit();
+        let bytes =
+            unsafe { slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        copy_from_pages(pages, bytes);
+        Ok(unsafe { value.assume_init() })
+    }
+    /// 把value写入用户内存
+    pub fn write(&self, value: T) -> SysResult<()> {
+        let pages = user_pages(self.token, self.ptr as usize, size_of::<T>(), Access::Write)?;
+        let bytes =
+            unsafe { slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        copy_to_pages(pages, bytes);
+        Ok(())
+    }
+    /// 用户地址对应的物理地址，要求可读且T不跨越页边界，用作futex的键
+    pub fn paddr(&self) -> SysResult<PhysAddr> {
+        let pages = user_pages(self.token, self.ptr as usize, size_of::<T>(), Access::Read)?;
+        match pages.as_slice() {
+            // 内核恒等映射了物理内存，片段的地址就是物理地址
+            [page] => Ok(PhysAddr::from(page.as_ptr() as usize)),
+            _ => Err(SysError::EINVAL),
+        }
+    }
+}
+
+/// 用户地址空间中连续的len个T，对T的要求与UserPtr相同
+pub struct UserSlice<T> {
+    token: usize,
+    ptr: *const T,
+    len: usize,
+}
+
+impl<T: Copy> UserSlice<T> {
+    pub fn new(token: usize, ptr: *const T, len: usize) -> Self {
+        Self { token, ptr, len }
+    }
+    fn byte_len(&self) -> SysResult<usize> {
+        self.len.checked_mul(size_of::<T>()).ok_or(SysError::EFAULT)
+    }
+    /// 读出全部元素，总长度超过MAX_READ_LEN时返回EINVAL
+    pub fn read(&self) -> SysResult<Vec<T>> {
+        let byte_len = self.byte_len()?;
+        if byte_len > MAX_READ_LEN {
+            return Err(SysError::EINVAL);
+        }
+        let pages = user_pages(self.token, self.ptr as usize, byte_len, Access::Read)?;
+        let mut values = Vec::<T>::with_capacity(self.len);
+        let bytes = unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, byte_len) };
+        copy_from_pages(pages, bytes);
+        unsafe { values.set_len(self.len) };
+        Ok(values)
+    }
+    /// 把src写入开头的若干元素，返回写入的元素个数，超出len的部分被丢弃
+    pub fn write(&self, src: &[T]) -> SysResult<usize> {
+        let count = src.len().min(self.len);
+        let len = count * size_of::<T>();
+        let pages = user_pages(self.token, self.ptr as usize, len, Access::Write)?;
+        let bytes = unsafe { slice::from_raw_parts(src.as_ptr() as *const u8, len) };
+        copy_to_pages(pages, bytes);
+        Ok(count)
+    }
+}
+
+impl UserSlice<u8> {
+    /// 供文件从中读取数据（如write系统调用）的缓冲区，要求可读
+    pub fn readable_buffer(&self) -> SysResult<UserBuffer> {
+        let pages = user_pages(self.token, self.ptr as usize, self.len, Access::Read)?;
+        Ok(UserBuffer::new(pages))
+    }
+    /// 供文件向其中写入数据（如read系统调用）的缓冲区，要求可写
+    pub fn writable_buffer(&self) -> SysResult<UserBuffer> {
+        let pages = user_pages(self.token, self.ptr as usize, self.len, Access::Write)?;
+        Ok(UserBuffer::new(pages))
+    }
+}
+
+/// 用户地址空间中以'\0'结尾的字符串
+pub struct UserCStr {
+    token: usize,
+    ptr: *const u8,
+}
+
+impl UserCStr {
+    pub fn new(token: usize, ptr: *const u8) -> Self {
+        Self { token, ptr }
+    }
+    /// 读出字符串，不含结尾的'\0'。长度超过MAX_CSTR_LEN时返回ENAMETOOLONG，
+    /// 不是合法的UTF-8时返回EINVAL
+    pub fn read(&self) -> SysResult<String> {
+        let mut bytes = Vec::new();
+        let mut va = self.ptr as usize;
+        loop {
+            // 每次取到页尾为止，不必逐字节查询页表
+            let page_end = (va / PAGE_SIZE + 1) * PAGE_SIZE;
+            let pages = user_pages(self.token, va, page_end - va, Access::Read)?;
+            let page = &pages[0];
+            let nul = page.iter().position(|&byte| byte == 0);
+            bytes.extend_from_slice(&page[..nul.unwrap_or(page.len())]);
+            if bytes.len() > MAX_CSTR_LEN {
+                return Err(SysError::ENAMETOOLONG);
+            }
+            if nul.is_some() {
+                break;
+            }
+            va = page_end;
+        }
+        String::from_utf8(bytes).map_err(|_| SysError::EINVAL)
+    }
+}
@@ -0,0 +1,91 @@
+//! smoltcp使用的网络设备：virtio网卡或本地回环
+use crate::drivers::virtio::{VirtIONet, MAX_FRAME_SIZE};
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::phy::{self, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+use smoltcp::{Error, Result};
+
+/// 回环接口的最大传输单元
+const LOOPBACK_MTU: usize = 65535;
+
+pub enum NetDevice {
+    /// 发出的IP包直接放入接收队列
+    Loopback(VecDeque<Vec<u8>>),
+    Virtio(VirtIONet),
+}
+
+/// 收到的帧已经从接收缓冲区中拷贝出来
+pub struct RxToken(Vec<u8>);
+
+pub enum TxToken<'a> {
+    Loopback(&'a mut VecDeque<Vec<u8>>),
+    Virtio(&'a mut VirtIONet),
+}
+
+impl<'a> phy::Device<'a> for NetDevice {
+    type RxToken = RxToken;
+    type TxToken = TxToken<'a>;
+
+    fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
+        match self {
+            NetDevice::Loopback(queue) => {
+                let packet = queue.pop_front()?;
+                Some((RxToken(packet), TxToken::Loopback(queue)))
+            }
+            NetDevice::Virtio(net) => {
+                let frame = net.receive()?;
+                Some((RxToken(frame), TxToken::Virtio(net)))
+            }
+        }
+    }
+    fn transmit(&'a mut self) -> Option<Self::TxToken> {
+        match self {
+            NetDevice::Loopback(queue) => Some(TxToken::Loopback(queue)),
+            NetDevice::Virtio(net) if net.can_send() => Some(TxToken::Virtio(net)),
+            NetDevice::Virtio(_) => None,
+        }
+    }
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        match self {
+            NetDevice::Loopback(_) => {
+                caps.medium = Medium::Ip;
+                caps.max_transmission_unit = LOOPBACK_MTU;
+            }
+            NetDevice::Virtio(_) => {
+                caps.medium = Medium::Ethernet;
+                caps.max_transmission_unit = MAX_FRAME_SIZE;
+                caps.max_burst_size = Some(1);
+            }
+        }
+        caps
+    }
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, _timestamp: Instant, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> Result<R>,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl<'a> phy::TxToken for TxToken<'a> {
+    fn consume<R, F>(self, _timestamp: Instant, len: usize, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> Result<R>,
+    {
+        match self {
+            TxToken::Loopback(queue) => {
+                let mut packet = vec![0; len];
+                let result = f(&mut packet)?;
+                queue.push_back(packet);
+                Ok(result)
+            }
+            TxToken::Virtio(net) => net.send(len, f).unwrap_or(Err(Error::Exhausted)),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! 基于smoltcp的TCP/IP协议栈。
+//! 总是有一个127.0.0.1/8的回环接口；探测到virtio网卡时再加入以太网接口。
+//! 网卡的接收由中断驱动：virtio-net中断到来时处理收到的帧，
+//! 回环接口没有中断，由套接字操作和时钟中断推动，TCP的重传等定时任务也借助时钟中断完成
+mod device;
+mod socket;
+
+use crate::drivers::virtio::VirtIONet;
+use crate::random::random_u64;
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::timer::get_time_ms;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use device::NetDevice;
+use lazy_static::*;
+use smoltcp::iface::{Interface, InterfaceBuilder, NeighborCache, Routes, SocketHandle};
+use smoltcp::socket::{
+    TcpSocket, TcpSocketBuffer, TcpState, UdpPacketMetadata, UdpSocket, UdpSocketBuffer,
+};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
+
+pub use socket::{Socket, SocketType};
+
+/// 各网络接口在NetStack::ifaces中的下标
+const LOOPBACK: usize = 0;
+const ETHERNET: usize = 1;
+
+/// QEMU用户态网络中客户机的默认地址与网关
+const GUEST_IP: [u8; 4] = [10, 0, 2, 15];
+const GUEST_PREFIX_LEN: u8 = 24;
+const GATEWAY_IP: [u8; 4] = [10, 0, 2, 2];
+/// 内核中的UDP与TCP回显服务的端口
+const ECHO_PORT: u16 = 7;
+/// 自动分配的临时端口范围
+const EPHEMERAL_PORT_START: u16 = 49152;
+/// 一次poll中最多轮询各接口的次数，回环接口发出的包要下一轮才会被收到
+const MAX_POLL_ROUNDS: usize = 8;
+
+const UDP_BUF_SIZE: usize = 4096;
+const UDP_PACKET_COUNT: usize = 8;
+const TCP_BUF_SIZE: usize = 4096;
+
+struct NetStack {
+    ifaces: Vec<Interface<'static, NetDevice>>,
+    /// 以太网接口上的回显服务
+    echo: Option<(SocketHandle, SocketHandle)>,
+    /// 已被用户关闭、等待TCP连接正常结束后再回收的套接字
+    closing: Vec<(usize, SocketHandle)>,
+    next_port: u16,
+}
+
+lazy_static! {
+    static ref NET: UPSafeCell<NetStack> =
+        unsafe { UPSafeCell::new_with_class(NetStack::new(), LockClass::Net) };
+    /// 任何套接字的就绪状态可能变化时唤醒其中所有的任务
+    static ref NET_WAIT: WaitQueue = WaitQueue::new();
+}
+
+fn now() -> Instant {
+    Instant::from_millis(get_time_ms() as i64)
+}
+
+fn new_udp_socket() -> UdpSocket<'static> {
+    UdpSocket::new(
+        UdpSocketBuffer::new(
+            vec![UdpPacketMetadata::EMPTY; UDP_PACKET_COUNT],
+            vec![0; UDP_BUF_SIZE],
+        ),
+        UdpSocketBuffer::new(
+            vec![UdpPacketMetadata::EMPTY; UDP_PACKET_COUNT],
+            vec![0; UDP_BUF_SIZE],
+        ),
+    )
+}
+
+fn new_tcp_socket() -> TcpSocket<'static> {
+    TcpSocket::new(
+        TcpSocketBuffer::new(vec![0; TCP_BUF_SIZE]),
+        TcpSocketBuffer::new(vec![0; TCP_BUF_SIZE]),
+    )
+}
+
+/// 初始化协议栈，须在随机数生成器播种之后调用
+pub fn init() {
+    let mut stack = NET.exclusive_access();
+    // 临时端口从随机位置开始分配，使端口号不可预测
+    stack.next_port =
+        EPHEMERAL_PORT_START + (random_u64() % (u16::MAX - EPHEMERAL_PORT_START) as u64) as u16;
+    info!("net: loopback {}", stack.ifaces[LOOPBACK].ip_addrs()[0]);
+}
+
+/// 用探测到的virtio网卡加入以太网接口，并启动回显服务
+pub fn add_virtio(net: VirtIONet) {
+    let mac = net.mac();
+    let [a, b, c, d] = GUEST_IP;
+    let mut routes = Routes::new(BTreeMap::new());
+    let [ga, gb, gc, gd] = GATEWAY_IP;
+    routes
+        .add_default_ipv4_route(Ipv4Address::new(ga, gb, gc, gd))
+        .unwrap();
+    let mut iface = InterfaceBuilder::new(NetDevice::Virtio(net), vec![])
+        .hardware_addr(EthernetAddress(mac).into())
+        .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+        .ip_addrs(vec![IpCidr::new(
+            IpAddress::v4(a, b, c, d),
+            GUEST_PREFIX_LEN,
+        )])
+        .routes(routes)
+        .finalize();
+
+    let mut udp_echo = new_udp_socket();
+    udp_echo.bind(ECHO_PORT).unwrap();
+    let udp_echo = iface.add_socket(udp_echo);
+    let tcp_echo = iface.add_socket(new_tcp_socket());
+    info!(
+        "net: mac {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}, ip {}.{}.{}.{}/{}, echo on port {}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5], a, b, c, d, GUEST_PREFIX_LEN, ECHO_PORT
+    );
+    let mut stack = NET.exclusive_access();
+    assert_eq!(stack.ifaces.len(), ETHERNET);
+    stack.ifaces.push(iface);
+    stack.echo = Some((udp_echo, tcp_echo));
+    drop(stack);
+    poll();
+}
+
+/// 让协议栈处理收到的包和到期的定时任务，再运行回显服务。
+/// 有套接字的状态可能发生变化时唤醒所有等待网络的任务
+pub fn poll() {
+    let mut stack = NET.exclusive_access();
+    let mut changed = false;
+    for _ in 0..MAX_POLL_ROUNDS {
+        let mut progress = false;
+        for iface in stack.ifaces.iter_mut() {
+            match iface.poll(now()) {
+                Ok(true) => progress = true,
+                Ok(false) => {}
+                Err(err) => warn!("net: poll error {}", err),
+            }
+        }
+        stack.echo();
+        if !progress {
+            break;
+        }
+        changed = true;
+    }
+    stack.reap_closing();
+    drop(stack);
+    if changed {
+        NET_WAIT.wake_all();
+    }
+}
+
+/// virtio-net的中断处理函数
+pub fn handle_irq() {
+    if let Some(NetDevice::Virtio(net)) = NET
+        .exclusive_access()
+        .ifaces
+        .get_mut(ETHERNET)
+        .map(|iface| iface.device_mut())
+    {
+        net.ack_interrupt();
+    }
+    poll();
+}
+
+/// 时钟中断时调用，协议栈有到期的定时任务时处理它们
+pub fn on_timer() {
+    let timestamp = now();
+    let due = NET.exclusive_access().ifaces.iter_mut().any(|iface| {
+        iface
+            .poll_delay(timestamp)
+            .map_or(false, |delay| delay.total_millis() == 0)
+    });
+    if due {
+        poll();
+    }
+}
+
+impl NetStack {
+    fn new() -> Self {
+        let loopback = InterfaceBuilder::new(NetDevice::Loopback(VecDeque::new()), vec![])
+            .ip_addrs(vec![IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8)])
+            .finalize();
+        Self {
+            ifaces: vec![loopback],
+            echo: None,
+            closing: Vec::new(),
+            next_port: EPHEMERAL_PORT_START,
+        }
+    }
+
+    /// 分配一个临时端口
+    fn alloc_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = if port == u16::MAX {
+            EPHEMERAL_PORT_START
+        } else {
+            port + 1
+        };
+        port
+    }
+
+    /// 发往或绑定到addr时应使用的接口，未指定地址时为所有接口
+    fn ifaces_for(&self, addr: IpAddress) -> Vec<usize> {
+        match addr {
+            IpAddress::Ipv4(ip) if ip.is_unspecified() => (0..self.ifaces.len()).collect(),
+            IpAddress::Ipv4(ip) if ip.is_loopback() => vec![LOOPBACK],
+            IpAddress::Unspecified => (0..self.ifaces.len()).collect(),
+            _ if self.ifaces.len() > ETHERNET => vec![ETHERNET],
+            _ => Vec::new(),
+        }
+    }
+
+    /// 回收连接已经结束的已关闭TCP套接字
+    fn reap_closing(&mut self) {
+        let ifaces = &mut self.ifaces;
+        self.closing.retain(|&(iface, handle)| {
+            let state = ifaces[iface].get_socket::<TcpSocket>(handle).state();
+            if state == TcpState::Closed || state == TcpState::TimeWait {
+                ifaces[iface].remove_socket(handle);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn echo(&mut self) {
+        let (udp_echo, tcp_echo) = match self.echo {
+            Some(echo) => echo,
+            None => return,
+        };
+        let iface = &mut self.ifaces[ETHERNET];
+        let udp = iface.get_socket::<UdpSocket>(udp_echo);
+        let mut buf = vec![0u8; UDP_BUF_SIZE];
+        while let Ok((len, endpoint)) = udp.recv_slice(&mut buf) {
+            let _ = udp.send_slice(&buf[..len], endpoint);
+        }
+
+        let tcp = iface.get_socket::<TcpSocket>(tcp_echo);
+        if !tcp.is_open() {
+            tcp.listen(ECHO_PORT).unwrap();
+        }
+        if tcp.can_recv() && tcp.can_send() {
+            let len = tcp.recv_slice(&mut buf[..tcp.send_capacity() - tcp.send_queue()]);
+            if let Ok(len) = len {
+                let _ = tcp.send_slice(&buf[..len]);
+            }
+        } else if !tcp.may_recv() && tcp.may_send() {
+            // 对方已经关闭了发送方向
+            tcp.close();
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! 用户进程通过文件描述符使用的套接字。
+//! 一个套接字在它所绑定地址涉及的每个接口上各有一个smoltcp套接字，
+//! 例如绑定到0.0.0.0的UDP套接字可以同时从回环接口和网卡收包
+use super::{
+    new_tcp_socket, new_udp_socket, poll, NetStack, LOOPBACK, NET, NET_WAIT, TCP_BUF_SIZE,
+    UDP_BUF_SIZE,
+};
+use crate::fs::{File, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{LockClass, UPSafeCell, WaitQueue};
+use crate::syscall::errno::*;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{TcpSocket, TcpState, UdpSocket};
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+/// 监听套接字最多同时等待被accept的连接数
+const MAX_BACKLOG: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SocketType {
+    Stream,
+    Datagram,
+}
+
+struct SocketInner {
+    /// (接口下标, smoltcp套接字)，绑定或连接之前为空
+    handles: Vec<(usize, SocketHandle)>,
+    local: Option<IpEndpoint>,
+    remote: Option<IpEndpoint>,
+    listening: bool,
+    shut_read: bool,
+    shut_write: bool,
+}
+
+pub struct Socket {
+    pub socket_type: SocketType,
+    nonblock: bool,
+    inner: UPSafeCell<SocketInner>,
+}
+
+/// TCP连接已经建立，可以被accept或完成connect
+fn is_connected(tcp: &TcpSocket) -> bool {
+    matches!(tcp.state(), TcpState::Established | TcpState::CloseWait)
+}
+
+impl Socket {
+    pub fn new(socket_type: SocketType, nonblock: bool) -> Self {
+        Self::with_inner(
+            socket_type,
+            nonblock,
+            SocketInner {
+                handles: Vec::new(),
+                local: None,
+                remote: None,
+                listening: false,
+                shut_read: false,
+                shut_write: false,
+            },
+        )
+    }
+
+    fn with_inner(socket_type: SocketType, nonblock: bool, inner: SocketInner) -> Self {
+        Self {
+            socket_type,
+            nonblock,
+            inner: unsafe { UPSafeCell::new_with_class(inner, LockClass::Socket) },
+        }
+    }
+
+    /// 反复尝试op直到它不再返回EAGAIN，非阻塞套接字则直接返回。
+    /// 每次尝试前先推动协议栈，回环接口上的数据只有这样才会送达
+    fn block_on<T>(
+        &self,
+        mut op: impl FnMut(&mut SocketInner, &mut NetStack) -> SysResult<T>,
+    ) -> SysResult<T> {
+        loop {
+            poll();
+            let mut inner = self.inner.exclusive_access();
+            let result = op(&mut *inner, &mut *NET.exclusive_access());
+            drop(inner);
+            match result {
+                Err(SysError::EAGAIN) if !self.nonblock => {
+                    NET_WAIT.wait(None);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 绑定到本地地址，端口为0时自动分配
+    pub fn bind(&self, mut endpoint: IpEndpoint) -> SysResult<()> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.local.is_some() {
+            return Err(SysError::EINVAL);
+        }
+        let mut stack = NET.exclusive_access();
+        if endpoint.port == 0 {
+            endpoint.port = stack.alloc_port();
+        }
+        let ifaces = stack.ifaces_for(endpoint.addr);
+        if ifaces.is_empty() {
+            return Err(SysError::EADDRNOTAVAIL);
+        }
+        if self.socket_type == SocketType::Datagram {
+            for iface in ifaces {
+                let mut udp = new_udp_socket();
+                udp.bind(endpoint).map_err(|_| SysError::EINVAL)?;
+                let handle = stack.ifaces[iface].add_socket(udp);
+                inner.handles.push((iface, handle));
+            }
+        }
+        inner.local = Some(endpoint);
+        Ok(())
+    }
+
+    /// 未绑定时绑定到所有接口上的临时端口
+    fn auto_bind(&self) -> SysResult<()> {
+        if self.inner.exclusive_access().local.is_some() {
+            return Ok(());
+        }
+        self.bind(IpEndpoint::new(IpAddress::Unspecified, 0))
+    }
+
+    pub fn listen(&self, backlog: usize) -> SysResult<()> {
+        if self.socket_type != SocketType::Stream {
+            return Err(SysError::EOPNOTSUPP);
+        }
+        self.auto_bind()?;
+        let mut inner = self.inner.exclusive_access();
+        if inner.listening {
+            return Ok(());
+        }
+        if !inner.handles.is_empty() {
+            return Err(SysError::EISCONN);
+        }
+        let local = inner.local.unwrap();
+        let mut stack = NET.exclusive_access();
+        for iface in stack.ifaces_for(local.addr) {
+            for _ in 0..backlog.clamp(1, MAX_BACKLOG) {
+                let mut tcp = new_tcp_socket();
+                tcp.listen(local).map_err(|_| SysError::EINVAL)?;
+                let handle = stack.ifaces[iface].add_socket(tcp);
+                inner.handles.push((iface, handle));
+            }
+        }
+        inner.listening = true;
+        Ok(())
+    }
+
+    /// 取出一个已经建立的连接，用一个新的监听套接字替换它
+    pub fn accept(&self) -> SysResult<(Arc<Socket>, IpEndpoint)> {
+        if !self.inner.exclusive_access().listening {
+            return Err(SysError::EINVAL);
+        }
+        let (handle, local, remote) = self.block_on(|inner, stack| {
+            let idx = inner
+                .handles
+                .iter()
+                .position(|&(iface, handle)| {
+                    is_connected(stack.ifaces[iface].get_socket::<TcpSocket>(handle))
+                })
+                .ok_or(SysError::EAGAIN)?;
+            let (iface, handle) = inner.handles[idx];
+            let tcp = stack.ifaces[iface].get_socket::<TcpSocket>(handle);
+            let (local, remote) = (tcp.local_endpoint(), tcp.remote_endpoint());
+            let mut listener = new_tcp_socket();
+            listener
+                .listen(inner.local.unwrap())
+                .map_err(|_| SysError::EINVAL)?;
+            inner.handles[idx] = (iface, stack.ifaces[iface].add_socket(listener));
+            Ok(((iface, handle), local, remote))
+        })?;
+        let socket = Socket::with_inner(
+            SocketType::Stream,
+            false,
+            SocketInner {
+                handles: vec![handle],
+                local: Some(local),
+                remote: Some(remote),
+                listening: false,
+                shut_read: false,
+                shut_write: false,
+            },
+        );
+        Ok((Arc::new(socket), remote))
+    }
+
+    /// 流套接字发起连接并等待建立；数据报套接字只记录默认的对端
+    pub fn connect(&self, remote: IpEndpoint) -> SysResult<()> {
+        if self.socket_type == SocketType::Datagram {
+            self.auto_bind()?;
+            self.inner.exclusive_access().remote = Some(remote);
+            return Ok(());
+        }
+        let mut inner = self.inner.exclusive_access();
+        if inner.listening || !inner.handles.is_empty() {
+            return Err(SysError::EISCONN);
+        }
+        let mut stack = NET.exclusive_access();
+        // 与Linux一样，连接0.0.0.0即连接本机
+        let iface = match remote.addr {
+            IpAddress::Unspecified => LOOPBACK,
+            IpAddress::Ipv4(ip) if ip.is_unspecified() => LOOPBACK,
+            addr => *stack
+                .ifaces_for(addr)
+                .first()
+                .ok_or(SysError::ENETUNREACH)?,
+        };
+        let local_port = match inner.local {
+            Some(local) => local.port,
+            None => stack.alloc_port(),
+        };
+        let handle = stack.ifaces[iface].add_socket(new_tcp_socket());
+        let (tcp, cx) = stack.ifaces[iface].get_socket_and_context::<TcpSocket>(handle);
+        if tcp
+            .connect(cx, remote, (IpAddress::Unspecified, local_port))
+            .is_err()
+        {
+            stack.ifaces[iface].remove_socket(handle);
+            return Err(SysError::EINVAL);
+        }
+        inner.handles.push((iface, handle));
+        inner.remote = Some(remote);
+        drop(stack);
+        drop(inner);
+        if self.nonblock {
+            poll();
+            return Err(SysError::EINPROGRESS);
+        }
+        self.block_on(|inner, stack| {
+            let tcp = stack.ifaces[iface].get_socket::<TcpSocket>(handle);
+            if is_connected(tcp) {
+                inner.local = Some(tcp.local_endpoint());
+                Ok(())
+            } else if tcp.state() == TcpState::Closed {
+                stack.ifaces[iface].remove_socket(handle);
+                inner.handles.clear();
+                inner.remote = None;
+                Err(SysError::ECONNREFUSED)
+            } else {
+                Err(SysError::EAGAIN)
+            }
+        })
+    }
+
+    /// 发送len字节的数据时实际从用户内存复制的长度：流套接字一次最多发送一个发送缓冲区，
+    /// 数据报不能被截断，超过缓冲区大小时返回EMSGSIZE
+    pub fn send_len(&self, len: usize) -> SysResult<usize> {
+        match self.socket_type {
+            SocketType::Stream => Ok(len.min(TCP_BUF_SIZE)),
+            SocketType::Datagram if len > UDP_BUF_SIZE => Err(SysError::EMSGSIZE),
+            SocketType::Datagram => Ok(len),
+        }
+    }
+
+    /// 接收至多len字节的数据时所需的内核缓冲区长度，不超过接收缓冲区的大小
+    pub fn recv_len(&self, len: usize) -> usize {
+        match self.socket_type {
+            SocketType::Stream => len.min(TCP_BUF_SIZE),
+            SocketType::Datagram => len.min(UDP_BUF_SIZE),
+        }
+    }
+
+    /// 发送数据，数据报套接字的目的地址为空时发往connect指定的对端
+    pub fn send(&self, data: &[u8], dest: Option<IpEndpoint>) -> SysResult<usize> {
+        if self.socket_type == SocketType::Datagram {
+            return self.send_datagram(data, dest);
+        }
+        {
+            let inner = self.inner.exclusive_access();
+            if inner.listening || inner.handles.is_empty() {
+                return Err(SysError::ENOTCONN);
+            }
+            if inner.shut_write {
+                return Err(SysError::EPIPE);
+            }
+        }
+        let len = self.block_on(|inner, stack| {
+            let (iface, handle) = inner.handles[0];
+            let tcp = stack.ifaces[iface].get_socket::<TcpSocket>(handle);
+            if !tcp.may_send() {
+                Err(SysError::EPIPE)
+            } else if tcp.can_send() {
+                tcp.send_slice(data).map_err(|_| SysError::EPIPE)
+            } else {
+                Err(SysError::EAGAIN)
+            }
+        })?;
+        poll();
+        Ok(len)
+    }
+
+    fn send_datagram(&self, data: &[u8], dest: Option<IpEndpoint>) -> SysResult<usize> {
+        if data.len() > UDP_BUF_SIZE {
+            return Err(SysError::EMSGSIZE);
+        }
+        let dest = dest
+            .or(self.inner.exclusive_access().remote)
+            .ok_or(SysError::EDESTADDRREQ)?;
+        self.auto_bind()?;
+        self.block_on(|inner, stack| {
+            let iface = match dest.addr {
+                IpAddress::Ipv4(ip) if ip.is_unspecified() => LOOPBACK,
+                addr => *stack
+                    .ifaces_for(addr)
+                    .first()
+                    .ok_or(SysError::ENETUNREACH)?,
+            };
+            let &(_, handle) = inner
+                .handles
+                .iter()
+                .find(|&&(i, _)| i == iface)
+                .ok_or(SysError::ENETUNREACH)?;
+            let udp = stack.ifaces[iface].get_socket::<UdpSocket>(handle);
+            if !udp.can_send() {
+                return Err(SysError::EAGAIN);
+            }
+            udp.send_slice(data, dest).map_err(|_| SysError::EAGAIN)
+        })?;
+        poll();
+        Ok(data.len())
+    }
+
+    /// 接收数据，返回长度与发送方地址。流套接字在对方关闭后返回0
+    pub fn recv(&self, buf: &mut [u8]) -> SysResult<(usize, IpEndpoint)> {
+        if self.socket_type == SocketType::Datagram {
+            self.auto_bind()?;
+        } else {
+            let inner = self.inner.exclusive_access();
+            if inner.listening || inner.handles.is_empty() {
+                return Err(SysError::ENOTCONN);
+            }
+        }
+        let socket_type = self.socket_type;
+        let result = self.block_on(|inner, stack| {
+            if inner.shut_read {
+                return Ok((
+                    0,
+                    inner
+                        .remote
+                        .unwrap_or(IpEndpoint::new(IpAddress::Unspecified, 0)),
+                ));
+            }
+            if socket_type == SocketType::Stream {
+                let (iface, handle) = inner.handles[0];
+                let tcp = stack.ifaces[iface].get_socket::<TcpSocket>(handle);
+                return if tcp.can_recv() {
+                    let len = tcp.recv_slice(buf).map_err(|_| SysError::ENOTCONN)?;
+                    Ok((len, tcp.remote_endpoint()))
+                } else if !tcp.may_recv() {
+                    Ok((0, tcp.remote_endpoint()))
+                } else {
+                    Err(SysError::EAGAIN)
+                };
+            }
+            for &(iface, handle) in inner.handles.iter() {
+                let udp = stack.ifaces[iface].get_socket::<UdpSocket>(handle);
+                while let Ok((len, endpoint)) = udp.recv_slice(buf) {
+                    // 已连接的数据报套接字只接收来自对端的数据
+                    if inner.remote.map_or(true, |remote| remote == endpoint) {
+                        return Ok((len, endpoint));
+                    }
+                }
+            }
+            Err(SysError::EAGAIN)
+        })?;
+        poll();
+        Ok(result)
+    }
+
+    /// how: 0关闭读方向，1关闭写方向，2关闭两个方向
+    pub fn shutdown(&self, how: usize) -> SysResult<()> {
+        let (shut_read, shut_write) = match how {
+            0 => (true, false),
+            1 => (false, true),
+            2 => (true, true),
+            _ => return Err(SysError::EINVAL),
+        };
+        let mut inner = self.inner.exclusive_access();
+        if self.socket_type == SocketType::Stream {
+            if inner.listening || inner.handles.is_empty() {
+                return Err(SysError::ENOTCONN);
+            }
+            if shut_write {
+                let (iface, handle) = inner.handles[0];
+                NET.exclusive_access().ifaces[iface]
+                    .get_socket::<TcpSocket>(handle)
+                    .close();
+            }
+        }
+        inner.shut_read |= shut_read;
+        inner.shut_write |= shut_write;
+        drop(inner);
+        poll();
+        Ok(())
+    }
+}
+
+impl File for Socket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let mut data = vec![0u8; self.recv_len(buf.len())];
+        match self.recv(&mut data) {
+            Ok((len, _)) => buf.write_from(&data[..len]) as isize,
+            Err(err) => -err.errno(),
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> isize {
+        let mut data = match self.send_len(buf.len()) {
+            Ok(len) => vec![0u8; len],
+            Err(err) => return -err.errno(),
+        };
+        buf.read_to(&mut data);
+        match self.send(&data, None) {
+            Ok(len) => len as isize,
+            Err(err) => -err.errno(),
+        }
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut stack = NET.exclusive_access();
+        let mut ready = PollEvents::empty();
+        for &(iface, handle) in inner.handles.iter() {
+            let iface = &mut stack.ifaces[iface];
+            match self.socket_type {
+                SocketType::Stream if inner.listening => {
+                    if is_connected(iface.get_socket::<TcpSocket>(handle)) {
+                        ready |= PollEvents::IN;
+                    }
+                }
+                SocketType::Stream => {
+                    let tcp = iface.get_socket::<TcpSocket>(handle);
+                    if tcp.can_recv() || !tcp.may_recv() {
+                        ready |= PollEvents::IN;
+                    }
+                    if tcp.can_send() || !tcp.may_send() {
+                        ready |= PollEvents::OUT;
+                    }
+                    if tcp.state() == TcpState::Closed {
+                        ready |= PollEvents::HUP;
+                    }
+                }
+                SocketType::Datagram => {
+                    let udp = iface.get_socket::<UdpSocket>(handle);
+                    if udp.can_recv() {
+                        ready |= PollEvents::IN;
+                    }
+                    if udp.can_send() {
+                        ready |= PollEvents::OUT;
+                    }
+                }
+            }
+        }
+        if inner.shut_read {
+            ready |= PollEvents::IN;
+        }
+        ready & (events | PollEvents::HUP)
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&NET_WAIT)
+    }
+}
+
+impl Drop for Socket {
+    /// 监听套接字和UDP套接字直接回收；已连接的TCP套接字先发出FIN，
+    /// 等连接结束后由协议栈回收
+    fn drop(&mut self) {
+        let inner = self.inner.exclusive_access();
+        let mut stack = NET.exclusive_access();
+        for &(iface, handle) in inner.handles.iter() {
+            if self.socket_type == SocketType::Stream && !inner.listening {
+                stack.ifaces[iface].get_socket::<TcpSocket>(handle).close();
+                stack.closing.push((iface, handle));
+            } else {
+                stack.ifaces[iface].remove_socket(handle);
+            }
+        }
+    }
+}
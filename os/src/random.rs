@@ -0,0 +1,162 @@
+//! 内核的密码学安全伪随机数生成器。
+//! 以ChaCha20分组函数为核心，每次输出之后立即用新产生的密钥覆盖旧密钥，
+//! 泄露当前状态也无法推出之前的输出。驱动初始化时探测到virtio熵源就用它播种，
+//! 随后再混入时钟抖动，并在输出一定量的数据后定期从熵源重新播种
+use crate::drivers::virtio::VirtIORng;
+use crate::sync::{LockClass, UPSafeCell};
+use lazy_static::*;
+use riscv::register::time;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+const BLOCK_SIZE: usize = 64;
+/// 从熵源重新播种前最多输出的字节数
+const RESEED_INTERVAL: usize = 1 << 20;
+/// 时钟抖动播种时的采样次数
+const JITTER_SAMPLES: usize = 512;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// ChaCha20分组函数，nonce固定为0
+fn chacha20_block(key: &[u32; 8], counter: u64) -> [u8; BLOCK_SIZE] {
+    let mut init = [0u32; 16];
+    init[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    init[4..12].copy_from_slice(key);
+    init[12] = counter as u32;
+    init[13] = (counter >> 32) as u32;
+    let mut state = init;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut out = [0u8; BLOCK_SIZE];
+    for (i, word) in state.iter().enumerate() {
+        let word = word.wrapping_add(init[i]);
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+pub struct ChaCha20Rng {
+    key: [u32; 8],
+    counter: u64,
+    /// 自上次重新播种以来输出的字节数
+    output_since_reseed: usize,
+    /// virtio熵源，没有时只能依靠时钟抖动
+    source: Option<VirtIORng>,
+}
+
+impl ChaCha20Rng {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            counter: 0,
+            output_since_reseed: 0,
+            source: None,
+        }
+    }
+    /// 用当前密钥产生的下一个分组覆盖密钥
+    fn rekey(&mut self, mix: &[u8; 32]) {
+        let block = chacha20_block(&self.key, self.counter);
+        self.counter = self.counter.wrapping_add(1);
+        for (i, word) in self.key.iter_mut().enumerate() {
+            let new = u32::from_le_bytes(block[i * 4..i * 4 + 4].try_into().unwrap());
+            let extra = u32::from_le_bytes(mix[i * 4..i * 4 + 4].try_into().unwrap());
+            *word = new ^ extra;
+        }
+    }
+    /// 将seed混入状态，不会降低已有的熵
+    pub fn reseed(&mut self, seed: &[u8]) {
+        for chunk in seed.chunks(32) {
+            let mut mix = [0u8; 32];
+            mix[..chunk.len()].copy_from_slice(chunk);
+            self.rekey(&mix);
+        }
+        self.output_since_reseed = 0;
+    }
+    fn reseed_from_source(&mut self) {
+        let mut seed = [0u8; 32];
+        let len = match self.source.as_mut() {
+            Some(source) => source.read(&mut seed),
+            None => return,
+        };
+        self.reseed(&seed[..len]);
+    }
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if self.output_since_reseed >= RESEED_INTERVAL {
+            self.reseed_from_source();
+        }
+        for chunk in dest.chunks_mut(BLOCK_SIZE) {
+            let block = chacha20_block(&self.key, self.counter);
+            self.counter = self.counter.wrapping_add(1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.rekey(&[0; 32]);
+        self.output_since_reseed = self.output_since_reseed.saturating_add(dest.len());
+    }
+}
+
+lazy_static! {
+    static ref RNG: UPSafeCell<ChaCha20Rng> =
+        unsafe { UPSafeCell::new_with_class(ChaCha20Rng::new(), LockClass::Random) };
+}
+
+/// 收集执行一段计算所用时钟周期数的抖动作为初始种子
+fn timer_jitter_seed() -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    let mut acc: u64 = 0;
+    for i in 0..JITTER_SAMPLES {
+        let start = time::read() as u64;
+        for j in 0..(i % 7 + 1) * 16 {
+            acc = acc.rotate_left(5) ^ (j as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+        let delta = (time::read() as u64).wrapping_sub(start);
+        seed[i % 32] ^= (delta ^ (delta >> 8) ^ acc) as u8;
+        seed[(i + 13) % 32] = seed[(i + 13) % 32].rotate_left(3) ^ start as u8;
+    }
+    seed
+}
+
+/// 混入时钟抖动，在驱动初始化之后、其他模块使用随机数之前调用
+pub fn init() {
+    RNG.exclusive_access().reseed(&timer_jitter_seed());
+}
+
+/// 使用virtio熵源重新播种，并在之后定期从它补充熵
+pub fn add_entropy_source(source: VirtIORng) {
+    let mut rng = RNG.exclusive_access();
+    rng.source = Some(source);
+    rng.reseed_from_source();
+    info!("random: seeded from virtio-rng");
+}
+
+/// 将用户写入随机设备等来源的数据混入状态
+pub fn add_entropy(data: &[u8]) {
+    RNG.exclusive_access().reseed(data);
+}
+
+/// 用随机字节填满dest，供内核各处使用
+pub fn fill_random(dest: &mut [u8]) {
+    RNG.exclusive_access().fill_bytes(dest);
+}
+
+pub fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill_random(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
@@ -12,6 +12,20 @@ const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
 const SBI_SHUTDOWN: usize = 8;
 
+/// Hart State Management扩展
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_HSM_HART_START: usize = 0;
+/// 目标hart已经在运行
+pub const SBI_ERR_ALREADY_AVAILABLE: isize = -6;
+
+/// System Reset扩展
+const SBI_EXT_SRST: usize = 0x53525354;
+const SBI_SRST_SYSTEM_RESET: usize = 0;
+const SBI_SRST_TYPE_SHUTDOWN: usize = 0;
+const SBI_SRST_REASON_NONE: usize = 0;
+/// 以失败为原因关机，QEMU上RustSBI据此以非零的退出码结束QEMU
+const SBI_SRST_REASON_SYSTEM_FAILURE: usize = 1;
+
 // 当需要使用 RustSBI 服务的时候调用sbi_call就行了
 #[inline(always)]
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
@@ -28,6 +42,23 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// SBI v0.2起的调用约定：a7为扩展号，a6为功能号，返回(错误码，返回值)
+#[inline(always)]
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        asm!(
+        "ecall",
+        inlateout("x10") arg0 => error,
+        inlateout("x11") arg1 => value,
+        in("x12") arg2,
+        in("x16") fid,
+        in("x17") eid,
+        );
+    }
+    (error, value)
+}
+
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
 }
@@ -41,7 +72,27 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
-pub fn shutdown() -> ! {
+/// 让hartid从物理地址start_addr处以S态开始执行，此时a0为hartid，a1为opaque，
+/// 返回SBI错误码，0表示成功
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    sbi_call_ext(SBI_EXT_HSM, SBI_HSM_HART_START, hartid, start_addr, opaque).0
+}
+
+/// 关机，failure为真时告知SBI这是一次失败。
+/// SBI不支持System Reset扩展时退回到不区分原因的旧式关机
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure {
+        SBI_SRST_REASON_SYSTEM_FAILURE
+    } else {
+        SBI_SRST_REASON_NONE
+    };
+    sbi_call_ext(
+        SBI_EXT_SRST,
+        SBI_SRST_SYSTEM_RESET,
+        SBI_SRST_TYPE_SHUTDOWN,
+        reason,
+        0,
+    );
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
 }
\ No newline at end of file
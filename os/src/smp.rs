@@ -0,0 +1,52 @@
+//! 多核启动。SBI选出的启动hart完成全部初始化后，通过SBI HSM扩展启动设备树中的其余hart，
+//! 每个hart都进入自己的idle控制流，从同一个就绪队列中取任务运行
+use crate::config::MAX_HARTS;
+use crate::fdt::machine;
+use crate::sbi::{hart_start, SBI_ERR_ALREADY_AVAILABLE};
+use core::arch::asm;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// 启动hart完成初始化后置位，其余hart在此之前不能访问内核的任何数据
+static SMP_READY: AtomicBool = AtomicBool::new(false);
+/// 已经进入idle控制流的hart数
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前hart的编号。在内核中tp始终保存hartid：
+/// 启动时由entry.asm设置，从用户态trap进来时由__alltraps从TrapContext中恢复
+#[inline(always)]
+pub fn hart_id() -> usize {
+    let hartid;
+    unsafe {
+        asm!("mv {}, tp", out(reg) hartid);
+    }
+    hartid
+}
+
+/// 由启动hart调用：允许其余hart继续执行，并启动设备树中尚未运行的hart
+pub fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    SMP_READY.store(true, Ordering::Release);
+    let harts = machine().harts;
+    for hartid in (0..MAX_HARTS).filter(|&id| harts & (1 << id) != 0 && id != hart_id()) {
+        match hart_start(hartid, _start_secondary as usize, 0) {
+            // 与启动hart同时进入_start的hart已经在等待SMP_READY
+            0 | SBI_ERR_ALREADY_AVAILABLE => {}
+            err => warn!("failed to start hart {}: sbi error {}", hartid, err),
+        }
+    }
+}
+
+/// 由其余hart调用：等待启动hart完成初始化
+pub fn wait_for_boot_hart() {
+    while !SMP_READY.load(Ordering::Acquire) {
+        spin_loop();
+    }
+}
+
+/// 记录一个hart进入了idle控制流，返回此时在线的hart数
+pub fn hart_online() -> usize {
+    ONLINE_HARTS.fetch_add(1, Ordering::Relaxed) + 1
+}
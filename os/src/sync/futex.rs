@@ -0,0 +1,68 @@
+use super::{LockClass, UPSafeCell};
+use crate::mm::PhysAddr;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use lazy_static::*;
+
+lazy_static! {
+    /// futex等待队列，以用户地址翻译后的物理地址为键，
+    /// 这样不同地址空间中映射到同一物理页的futex也能正确地互相唤醒
+    static ref FUTEX_QUEUES: UPSafeCell<BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>> =
+        unsafe { UPSafeCell::new_with_class(BTreeMap::new(), LockClass::Futex) };
+}
+
+/// 在key对应的futex上阻塞当前任务，直到被futex_wake唤醒或到达expire_ms时刻，
+/// 调用者需要事先确认futex字的值与期望值相同。
+/// 被唤醒返回true，超时返回false
+pub fn futex_wait(key: PhysAddr, expire_ms: Option<usize>) -> bool {
+    let task = current_task().unwrap();
+    FUTEX_QUEUES
+        .exclusive_access()
+        .entry(key.0)
+        .or_insert_with(VecDeque::new)
+        .push_back(task.clone());
+    if let Some(expire_ms) = expire_ms {
+        add_timer(expire_ms, task.clone());
+    }
+    drop(task);
+    block_current_and_run_next();
+
+    let task = current_task().unwrap();
+    remove_timer(&task);
+    // 若当前任务仍在等待队列中，说明是被定时器唤醒的
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    let mut timed_out = false;
+    if let Some(queue) = queues.get_mut(&key.0) {
+        if let Some(idx) = queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            queue.remove(idx);
+            timed_out = true;
+        }
+        if queue.is_empty() {
+            queues.remove(&key.0);
+        }
+    }
+    !timed_out
+}
+
+/// 唤醒key对应的futex上至多count个任务，返回实际唤醒的数目
+pub fn futex_wake(key: PhysAddr, count: usize) -> usize {
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    let mut woken = 0;
+    if let Some(queue) = queues.get_mut(&key.0) {
+        while woken < count {
+            match queue.pop_front() {
+                Some(task) => {
+                    wakeup_task(task);
+                    woken += 1;
+                }
+                None => break,
+            }
+        }
+        if queue.is_empty() {
+            queues.remove(&key.0);
+        }
+    }
+    woken
+}
@@ -0,0 +1,84 @@
+//! 可嵌套的关中断。push_off关闭当前hart的S态中断并把嵌套深度加一，
+//! 配对的pop_off在深度归零时恢复第一次push_off之前的中断状态。
+//! 以及内核态中断处理的进入与退出
+use super::{lockdep, preempt};
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use core::cell::UnsafeCell;
+use riscv::register::sstatus;
+
+#[derive(Copy, Clone)]
+struct IrqState {
+    /// push_off的嵌套深度
+    depth: usize,
+    /// 最外层push_off之前中断是否打开
+    enabled: bool,
+    /// 内核态中断处理的嵌套深度
+    irq: usize,
+}
+
+struct IrqStates(UnsafeCell<[IrqState; MAX_HARTS]>);
+
+// 每个hart只在关中断时访问自己的那一项
+unsafe impl Sync for IrqStates {}
+
+static IRQ_STATES: IrqStates = IrqStates(UnsafeCell::new(
+    [IrqState {
+        depth: 0,
+        enabled: false,
+        irq: 0,
+    }; MAX_HARTS],
+));
+
+fn state() -> &'static mut IrqState {
+    unsafe { &mut (*IRQ_STATES.0.get())[hart_id()] }
+}
+
+/// 关闭当前hart的S态中断
+pub fn push_off() {
+    let enabled = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+    }
+    let state = state();
+    if state.depth == 0 {
+        state.enabled = enabled;
+    }
+    state.depth += 1;
+}
+
+/// 撤销一次push_off，最外层的pop_off恢复原来的中断状态，并且是一个抢占点
+pub fn pop_off() {
+    assert!(!sstatus::read().sie(), "pop_off with interrupts enabled");
+    let state = state();
+    assert!(state.depth > 0, "pop_off without push_off");
+    state.depth -= 1;
+    if state.depth == 0 && state.enabled {
+        unsafe {
+            sstatus::set_sie();
+        }
+        preempt::preempt_check();
+    }
+}
+
+/// 当前hart的push_off嵌套深度，调用时须已关中断
+pub(super) fn push_off_depth() -> usize {
+    state().depth
+}
+
+/// 当前hart是否正在处理内核态中断，调用时须已关中断
+pub(super) fn in_irq() -> bool {
+    state().irq > 0
+}
+
+/// 内核态中断处理开始，此后获取的锁单独检查顺序，并且不会发生抢占
+pub fn irq_enter() {
+    state().irq += 1;
+    lockdep::enter_irq();
+}
+
+/// 内核态中断处理结束
+pub fn irq_exit() {
+    lockdep::exit_irq();
+    state().irq -= 1;
+}
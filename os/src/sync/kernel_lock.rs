@@ -0,0 +1,44 @@
+//! 大内核锁。内核的全局数据都放在只能被一个控制流访问的UPSafeCell中，
+//! 多核时每个hart在进入内核时获取这把锁，在返回用户态或idle等待中断前释放，
+//! 从而保证任意时刻至多只有一个hart在内核中执行
+use crate::smp::hart_id;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const NO_OWNER: usize = usize::MAX;
+
+/// 持有锁的hart编号
+static OWNER: AtomicUsize = AtomicUsize::new(NO_OWNER);
+
+/// 获取大内核锁，自旋等待其他hart离开内核
+pub fn kernel_lock() {
+    let hartid = hart_id();
+    while OWNER
+        .compare_exchange_weak(NO_OWNER, hartid, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        assert_ne!(
+            OWNER.load(Ordering::Relaxed),
+            hartid,
+            "hart {} acquires the kernel lock twice",
+            hartid
+        );
+        spin_loop();
+    }
+}
+
+/// 当前hart是否持有大内核锁
+pub fn holds_kernel_lock() -> bool {
+    OWNER.load(Ordering::Relaxed) == hart_id()
+}
+
+/// 释放当前hart持有的大内核锁
+pub fn kernel_unlock() {
+    let owner = OWNER.swap(NO_OWNER, Ordering::Release);
+    assert_eq!(
+        owner,
+        hart_id(),
+        "hart {} releases a kernel lock it does not hold",
+        hart_id()
+    );
+}
@@ -0,0 +1,378 @@
+//! UPSafeCell与自旋锁的获取顺序检查器。
+//! 在debug模式（或启用lockdep特性）下，按hart记录持有的锁以及每个锁类别之间的获取顺序，
+//! 发现顺序颠倒或对同一实例的重复借用时，打印双方的调用位置并panic，
+//! 而不是只得到一个没有上下文的BorrowMutError。
+//! 同一类别的多个实例同时持有时（如父进程与子进程的inner），
+//! 须用嵌套层次标明获取顺序：后获取的层次必须大于已持有的同类别锁的层次。
+use core::panic::Location;
+
+/// 锁类别，同一类别的不同实例（如各个任务的inner）共享顺序关系
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LockClass {
+    Processor,
+    TaskManager,
+    PidAllocator,
+    FrameAllocator,
+    KernelSpace,
+    TaskInner,
+    Timers,
+    Futex,
+    Shm,
+    MessageQueues,
+    MessageQueue,
+    WaitQueue,
+    EventFd,
+    Uart,
+    IrqHandlers,
+    Net,
+    Socket,
+    Random,
+    DevFs,
+    Framebuffer,
+    Input,
+    Log,
+    /// 未分类的UPSafeCell，只检查重复借用，不参与顺序检查
+    Other,
+}
+
+impl LockClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LockClass::Processor => "PROCESSOR",
+            LockClass::TaskManager => "TASK_MANAGER",
+            LockClass::PidAllocator => "PID_ALLOCATOR",
+            LockClass::FrameAllocator => "FRAME_ALLOCATOR",
+            LockClass::KernelSpace => "KERNEL_SPACE",
+            LockClass::TaskInner => "task inner",
+            LockClass::Timers => "TIMERS",
+            LockClass::Futex => "FUTEX_QUEUES",
+            LockClass::Shm => "SHM_MANAGER",
+            LockClass::MessageQueues => "MQ_MANAGER",
+            LockClass::MessageQueue => "message queue",
+            LockClass::WaitQueue => "wait queue",
+            LockClass::EventFd => "eventfd",
+            LockClass::Uart => "UART",
+            LockClass::IrqHandlers => "IRQ_HANDLERS",
+            LockClass::Net => "NET",
+            LockClass::Socket => "socket",
+            LockClass::Random => "RNG",
+            LockClass::DevFs => "devfs registry",
+            LockClass::Framebuffer => "framebuffer",
+            LockClass::Input => "INPUT_DEVICES",
+            LockClass::Log => "LOG_BUFFER",
+            LockClass::Other => "unclassified",
+        }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "lockdep"))]
+mod imp {
+    use super::LockClass;
+    use crate::config::MAX_HARTS;
+    use crate::smp::hart_id;
+    use core::cell::UnsafeCell;
+    use core::hint::spin_loop;
+    use core::panic::Location;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use riscv::register::sstatus;
+
+    const NUM_CLASSES: usize = LockClass::Other as usize;
+    const MAX_HELD: usize = 16;
+
+    type Site = &'static Location<'static>;
+
+    #[derive(Copy, Clone)]
+    struct HeldLock {
+        class: LockClass,
+        /// 同类别锁之间的嵌套层次
+        subclass: u8,
+        instance: usize,
+        site: Site,
+        /// 是否在中断处理中获取
+        irq: bool,
+    }
+
+    struct LockDep {
+        /// 各hart当前持有的锁
+        held: [[Option<HeldLock>; MAX_HELD]; MAX_HARTS],
+        /// 各hart中断处理的嵌套深度
+        irq_depth: [usize; MAX_HARTS],
+        /// order[a][b]记录首次观察到“持有a时获取b”的两个调用位置
+        order: [[Option<(Site, Site)>; NUM_CLASSES]; NUM_CLASSES],
+    }
+
+    /// 检查发现的问题，在释放检查器自身的锁之后再打印，因为打印也会获取锁
+    enum Violation {
+        Recursive {
+            class: LockClass,
+            site: Site,
+            held_site: Site,
+        },
+        Nesting {
+            class: LockClass,
+            subclass: u8,
+            site: Site,
+            held: HeldLock,
+        },
+        Inversion {
+            class: LockClass,
+            site: Site,
+            held: HeldLock,
+            prev_held_site: Site,
+            prev_site: Site,
+        },
+        TooMany {
+            site: Site,
+        },
+    }
+
+    struct LockDepCell(UnsafeCell<LockDep>);
+
+    // 只在持有LOCKDEP_LOCK且关中断时访问
+    unsafe impl Sync for LockDepCell {}
+
+    static LOCKDEP: LockDepCell = LockDepCell(UnsafeCell::new(LockDep {
+        held: [[None; MAX_HELD]; MAX_HARTS],
+        irq_depth: [0; MAX_HARTS],
+        order: [[None; NUM_CLASSES]; NUM_CLASSES],
+    }));
+    static LOCKDEP_LOCK: AtomicBool = AtomicBool::new(false);
+
+    /// 在关中断并持有检查器自身的锁时访问检查器的状态。
+    /// 这里不能使用SpinMutex或push_off，它们本身就要调用检查器
+    fn with_lockdep<R>(f: impl FnOnce(&mut LockDep) -> R) -> R {
+        let enabled = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        while LOCKDEP_LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        let ret = f(unsafe { &mut *LOCKDEP.0.get() });
+        LOCKDEP_LOCK.store(false, Ordering::Release);
+        if enabled {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+        ret
+    }
+
+    impl LockDep {
+        fn acquire(
+            &mut self,
+            hartid: usize,
+            class: LockClass,
+            subclass: u8,
+            instance: usize,
+            site: Site,
+        ) -> Option<Violation> {
+            let irq = self.irq_depth[hartid] > 0;
+            for held in self.held[hartid].iter().flatten() {
+                if held.instance == instance {
+                    return Some(Violation::Recursive {
+                        class,
+                        site,
+                        held_site: held.site,
+                    });
+                }
+                // 中断处理获取的锁与被打断的代码持有的锁之间没有嵌套关系
+                if held.irq != irq || class == LockClass::Other || held.class == LockClass::Other {
+                    continue;
+                }
+                if held.class == class {
+                    if subclass <= held.subclass {
+                        return Some(Violation::Nesting {
+                            class,
+                            subclass,
+                            site,
+                            held: *held,
+                        });
+                    }
+                    continue;
+                }
+                let (a, b) = (held.class as usize, class as usize);
+                if let Some((prev_site, prev_held_site)) = self.order[b][a] {
+                    return Some(Violation::Inversion {
+                        class,
+                        site,
+                        held: *held,
+                        prev_held_site,
+                        prev_site,
+                    });
+                }
+                if self.order[a][b].is_none() {
+                    self.order[a][b] = Some((held.site, site));
+                }
+            }
+            match self.held[hartid].iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => {
+                    *slot = Some(HeldLock {
+                        class,
+                        subclass,
+                        instance,
+                        site,
+                        irq,
+                    });
+                    None
+                }
+                None => Some(Violation::TooMany { site }),
+            }
+        }
+    }
+
+    impl Violation {
+        fn report(self) -> ! {
+            match self {
+                Violation::Recursive {
+                    class,
+                    site,
+                    held_site,
+                } => {
+                    println!(
+                        "[lockdep] recursive acquisition of {} at {}",
+                        class.name(),
+                        site
+                    );
+                    println!("[lockdep]   already held since {}", held_site);
+                    panic!("lockdep: recursive acquisition of {}", class.name());
+                }
+                Violation::Nesting {
+                    class,
+                    subclass,
+                    site,
+                    held,
+                } => {
+                    println!(
+                        "[lockdep] nested acquisition of {} (level {}) at {}",
+                        class.name(),
+                        subclass,
+                        site
+                    );
+                    println!(
+                        "[lockdep]   while holding another {} (level {}) acquired at {}",
+                        class.name(),
+                        held.subclass,
+                        held.site
+                    );
+                    panic!("lockdep: unordered nesting of {}", class.name());
+                }
+                Violation::Inversion {
+                    class,
+                    site,
+                    held,
+                    prev_held_site,
+                    prev_site,
+                } => {
+                    println!(
+                        "[lockdep] lock order inversion: acquiring {} at {}",
+                        class.name(),
+                        site
+                    );
+                    println!(
+                        "[lockdep]   while holding {} acquired at {}",
+                        held.class.name(),
+                        held.site
+                    );
+                    println!(
+                        "[lockdep]   but previously {} was acquired at {}",
+                        held.class.name(),
+                        prev_held_site
+                    );
+                    println!(
+                        "[lockdep]   while holding {} acquired at {}",
+                        class.name(),
+                        prev_site
+                    );
+                    panic!(
+                        "lockdep: lock order inversion between {} and {}",
+                        held.class.name(),
+                        class.name()
+                    );
+                }
+                Violation::TooMany { site } => {
+                    panic!("lockdep: too many locks held at {}", site)
+                }
+            }
+        }
+    }
+
+    pub fn acquire(class: LockClass, subclass: u8, instance: usize, site: Site) {
+        let hartid = hart_id();
+        if let Some(violation) =
+            with_lockdep(|lockdep| lockdep.acquire(hartid, class, subclass, instance, site))
+        {
+            violation.report();
+        }
+    }
+
+    pub fn release(instance: usize) {
+        let hartid = hart_id();
+        with_lockdep(|lockdep| {
+            // 借用不一定按照获取的逆序释放
+            if let Some(slot) = lockdep.held[hartid]
+                .iter_mut()
+                .find(|slot| matches!(slot, Some(held) if held.instance == instance))
+            {
+                *slot = None;
+            }
+        });
+    }
+
+    pub fn enter_irq() {
+        let hartid = hart_id();
+        with_lockdep(|lockdep| lockdep.irq_depth[hartid] += 1);
+    }
+
+    pub fn exit_irq() {
+        let hartid = hart_id();
+        with_lockdep(|lockdep| lockdep.irq_depth[hartid] -= 1);
+    }
+}
+
+#[cfg(not(any(debug_assertions, feature = "lockdep")))]
+mod imp {
+    use super::LockClass;
+    use core::panic::Location;
+
+    #[inline(always)]
+    pub fn acquire(
+        _class: LockClass,
+        _subclass: u8,
+        _instance: usize,
+        _site: &'static Location<'static>,
+    ) {
+    }
+
+    #[inline(always)]
+    pub fn release(_instance: usize) {}
+
+    #[inline(always)]
+    pub fn enter_irq() {}
+
+    #[inline(always)]
+    pub fn exit_irq() {}
+}
+
+/// 在借用instance之前调用，记录获取顺序。subclass为同类别锁之间的嵌套层次，一般为0
+pub fn acquire(class: LockClass, subclass: u8, instance: usize, site: &'static Location<'static>) {
+    imp::acquire(class, subclass, instance, site);
+}
+
+/// 借用结束时调用
+pub fn release(instance: usize) {
+    imp::release(instance);
+}
+
+/// 进入中断处理时调用，此后获取的锁单独检查顺序
+pub fn enter_irq() {
+    imp::enter_irq();
+}
+
+/// 离开中断处理时调用
+pub fn exit_irq() {
+    imp::exit_irq();
+}
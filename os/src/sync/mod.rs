@@ -1,3 +1,17 @@
+mod futex;
+mod irq;
+mod kernel_lock;
+mod lockdep;
+mod preempt;
+mod spin;
 mod up;
+mod wait_queue;
 
-pub use up::UPSafeCell;
\ No newline at end of file
+pub use futex::{futex_wait, futex_wake};
+pub use irq::{irq_enter, irq_exit, pop_off, push_off};
+pub use kernel_lock::{holds_kernel_lock, kernel_lock, kernel_unlock};
+pub use lockdep::LockClass;
+pub use preempt::{preempt_check, preemptible, restore_preempt, save_preempt, set_need_resched};
+pub use spin::{IrqSafeMutex, SpinMutex};
+pub use up::{UPRefMut, UPSafeCell};
+pub use wait_queue::WaitQueue;
@@ -0,0 +1,157 @@
+//! 内核抢占。内核默认不可抢占：只有在preemptible包住的耗时操作（如fork复制地址空间）中，
+//! 当前hart既没有持有任何锁、也不在中断处理或push_off之中时，
+//! 才会在中断返回或释放最后一把锁时切换到其他任务。
+//! 时钟中断只设置need_resched标志，由这些抢占点完成切换
+use super::irq;
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use crate::task::{current_task, suspend_current_and_run_next};
+use core::cell::UnsafeCell;
+use riscv::register::sstatus;
+
+/// 抢占状态，属于正在运行的任务，切换任务时由schedule保存并恢复
+#[derive(Copy, Clone)]
+pub struct PreemptState {
+    /// 持有的UPSafeCell借用与自旋锁数，不为0时不可抢占
+    count: usize,
+    /// preemptible的嵌套深度，为0时不可抢占
+    preemptible: usize,
+}
+
+impl PreemptState {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            preemptible: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct HartPreempt {
+    state: PreemptState,
+    /// 时钟中断请求在下一个抢占点重新调度
+    need_resched: bool,
+}
+
+struct HartPreempts(UnsafeCell<[HartPreempt; MAX_HARTS]>);
+
+// 每个hart只在关中断时访问自己的那一项
+unsafe impl Sync for HartPreempts {}
+
+static HART_PREEMPTS: HartPreempts = HartPreempts(UnsafeCell::new(
+    [HartPreempt {
+        state: PreemptState::new(),
+        need_resched: false,
+    }; MAX_HARTS],
+));
+
+/// 关中断后访问当前hart的抢占状态，以免中途被抢占并迁移到其他hart上
+fn with_hart<R>(f: impl FnOnce(&mut HartPreempt) -> R) -> R {
+    let enabled = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+    }
+    let ret = f(unsafe { &mut (*HART_PREEMPTS.0.get())[hart_id()] });
+    if enabled {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+    ret
+}
+
+/// 获取锁之前调用
+pub fn preempt_disable() {
+    with_hart(|hart| hart.state.count += 1);
+}
+
+/// 释放锁之后调用，释放了最后一把锁时是一个抢占点
+pub fn preempt_enable() {
+    let count = with_hart(|hart| {
+        assert!(
+            hart.state.count > 0,
+            "preempt_enable without preempt_disable"
+        );
+        hart.state.count -= 1;
+        hart.state.count
+    });
+    if count == 0 {
+        preempt_check();
+    }
+}
+
+/// 请求当前hart在下一个抢占点重新调度，由时钟中断调用
+pub fn set_need_resched() {
+    with_hart(|hart| hart.need_resched = true);
+}
+
+/// 抢占点：有重新调度的请求且当前可以抢占时，让出处理器
+pub fn preempt_check() {
+    let resched = with_hart(|hart| {
+        let resched = hart.need_resched
+            && hart.state.count == 0
+            && hart.state.preemptible > 0
+            && irq::push_off_depth() == 0
+            && !irq::in_irq();
+        if resched {
+            hart.need_resched = false;
+        }
+        resched
+    });
+    if resched {
+        let enabled = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        suspend_current_and_run_next();
+        // 可能已经换了一个hart
+        if enabled {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
+
+/// 打开中断并允许抢占地执行f，用于耗时较长、又不需要持有锁的内核操作。
+/// 调用者自己持有的锁仍然会阻止抢占
+pub fn preemptible<R>(f: impl FnOnce() -> R) -> R {
+    // 启动时创建初始进程等操作不属于任何任务，无处可切换，直接执行
+    if current_task().is_none() {
+        return f();
+    }
+    let enabled = sstatus::read().sie();
+    with_hart(|hart| {
+        assert_eq!(irq::push_off_depth(), 0, "preemptible inside push_off");
+        hart.state.preemptible += 1;
+    });
+    unsafe {
+        sstatus::set_sie();
+    }
+    preempt_check();
+    let ret = f();
+    unsafe {
+        sstatus::clear_sie();
+    }
+    with_hart(|hart| hart.state.preemptible -= 1);
+    if enabled {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+    ret
+}
+
+/// 切换任务前取出当前任务的抢占状态，hart换上一个全新的状态
+pub fn save_preempt() -> PreemptState {
+    with_hart(|hart| {
+        hart.need_resched = false;
+        core::mem::replace(&mut hart.state, PreemptState::new())
+    })
+}
+
+/// 任务重新运行时恢复它的抢占状态，此时它可能已经换了一个hart
+pub fn restore_preempt(state: PreemptState) {
+    with_hart(|hart| hart.state = state);
+}
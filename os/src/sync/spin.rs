@@ -0,0 +1,141 @@
+//! 自旋锁。与UPSafeCell不同，它们在多个hart之间真正互斥，守卫离开作用域时自动解锁。
+//! SpinMutex只自旋等待；IrqSafeMutex在持有期间还关闭当前hart的中断，
+//! 用于中断处理也会访问的数据，否则中断处理函数可能在同一hart上等待一把永远不会释放的锁
+use super::irq::{pop_off, push_off};
+use super::lockdep::{self, LockClass};
+use super::preempt::{preempt_disable, preempt_enable};
+use crate::smp::hart_id;
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const NO_OWNER: usize = usize::MAX;
+
+pub struct SpinMutex<T> {
+    /// 持有锁的hart编号，用于发现同一hart上的重复获取
+    owner: AtomicUsize,
+    /// 锁类别，用于获取顺序检查
+    class: LockClass,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinMutex<T> {}
+unsafe impl<T: Send> Send for SpinMutex<T> {}
+
+impl<T> SpinMutex<T> {
+    /// 创建一把锁，class用于获取顺序检查
+    pub const fn new_with_class(value: T, class: LockClass) -> Self {
+        Self {
+            owner: AtomicUsize::new(NO_OWNER),
+            class,
+            data: UnsafeCell::new(value),
+        }
+    }
+    /// 自旋直到获得锁，持有期间当前任务不会被抢占。
+    /// 当前hart已经持有这把锁时panic，而不是永远等待下去
+    #[track_caller]
+    pub fn lock(&self) -> SpinMutexGuard<'_, T> {
+        let site = Location::caller();
+        let instance = self as *const _ as usize;
+        preempt_disable();
+        lockdep::acquire(self.class, 0, instance, site);
+        let hartid = hart_id();
+        while self
+            .owner
+            .compare_exchange_weak(NO_OWNER, hartid, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if self.owner.load(Ordering::Relaxed) == hartid {
+                panic!(
+                    "{} is already held by hart {} when locked at {}",
+                    self.class.name(),
+                    hartid,
+                    site
+                );
+            }
+            spin_loop();
+        }
+        SpinMutexGuard {
+            mutex: self,
+            instance,
+        }
+    }
+}
+
+/// SpinMutex的守卫，释放时解锁并通知获取顺序检查器
+pub struct SpinMutexGuard<'a, T> {
+    mutex: &'a SpinMutex<T>,
+    instance: usize,
+}
+
+impl<T> Deref for SpinMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for SpinMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.owner.store(NO_OWNER, Ordering::Release);
+        lockdep::release(self.instance);
+        preempt_enable();
+    }
+}
+
+pub struct IrqSafeMutex<T> {
+    inner: SpinMutex<T>,
+}
+
+impl<T> IrqSafeMutex<T> {
+    /// 创建一把锁，class用于获取顺序检查
+    pub const fn new_with_class(value: T, class: LockClass) -> Self {
+        Self {
+            inner: SpinMutex::new_with_class(value, class),
+        }
+    }
+    /// 先关闭当前hart的中断再获取锁，守卫释放后恢复
+    #[track_caller]
+    pub fn lock(&self) -> IrqSafeMutexGuard<'_, T> {
+        push_off();
+        IrqSafeMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+        }
+    }
+}
+
+/// IrqSafeMutex的守卫，先解锁再恢复中断
+pub struct IrqSafeMutexGuard<'a, T> {
+    guard: ManuallyDrop<SpinMutexGuard<'a, T>>,
+}
+
+impl<T> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqSafeMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+        pop_off();
+    }
+}
@@ -1,23 +1,88 @@
+use super::lockdep::{self, LockClass};
+use super::preempt::{preempt_disable, preempt_enable};
 use core::cell::{RefCell, RefMut};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
 // Cell和RefCell用于单线程的共享引用，很多时候，都是用在struct的field。
 // 这样，你就可以共享这个struct，但是仍能够对某个field做修改。
 pub struct UPSafeCell<T> {
     /// inner data
     inner: RefCell<T>,
+    /// 锁类别，用于借用顺序检查
+    class: LockClass,
 }
 
+// 多核时由大内核锁保证同一时刻只有一个hart在访问
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
 impl<T> UPSafeCell<T> {
     /// User is responsible to guarantee that struct is only used in
     /// uniprocessor
     pub unsafe fn new(value: T) -> Self {
+        Self::new_with_class(value, LockClass::Other)
+    }
+    /// 同new，并指定借用顺序检查所使用的锁类别
+    pub unsafe fn new_with_class(value: T, class: LockClass) -> Self {
         Self {
             inner: RefCell::new(value),
+            class,
         }
     }
     /// Panic if the data has been borrowed
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+    /// 借用期间当前任务不会被抢占
+    #[track_caller]
+    pub fn exclusive_access(&self) -> UPRefMut<'_, T> {
+        self.exclusive_access_nested(0)
+    }
+    /// 同exclusive_access，用于同时借用同一类别的多个实例，
+    /// subclass须大于已持有的同类别实例的层次
+    #[track_caller]
+    pub fn exclusive_access_nested(&self, subclass: u8) -> UPRefMut<'_, T> {
+        let site = Location::caller();
+        let instance = self as *const _ as usize;
+        preempt_disable();
+        lockdep::acquire(self.class, subclass, instance, site);
+        match self.inner.try_borrow_mut() {
+            Ok(inner) => UPRefMut {
+                inner: ManuallyDrop::new(inner),
+                instance,
+            },
+            Err(_) => panic!(
+                "{} has been borrowed when accessed at {}",
+                self.class.name(),
+                site
+            ),
+        }
+    }
+}
+
+/// UPSafeCell的借用守卫，释放时通知借用顺序检查器
+pub struct UPRefMut<'a, T> {
+    inner: ManuallyDrop<RefMut<'a, T>>,
+    instance: usize,
+}
+
+impl<T> Deref for UPRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for UPRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T> Drop for UPRefMut<'_, T> {
+    fn drop(&mut self) {
+        // 先结束借用，再允许抢占
+        unsafe {
+            ManuallyDrop::drop(&mut self.inner);
+        }
+        lockdep::release(self.instance);
+        preempt_enable();
     }
 }
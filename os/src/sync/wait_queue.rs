@@ -0,0 +1,76 @@
+use super::{LockClass, UPSafeCell};
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// 等待队列，供需要阻塞等待某个条件的内核对象使用。
+/// 等待者被唤醒后需要重新检查条件
+pub struct WaitQueue {
+    queue: UPSafeCell<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: unsafe { UPSafeCell::new_with_class(VecDeque::new(), LockClass::WaitQueue) },
+        }
+    }
+    /// 将task加入等待队列但不阻塞，用于同时等待多个队列的情形
+    pub fn add(&self, task: Arc<TaskControlBlock>) {
+        self.queue.exclusive_access().push_back(task);
+    }
+    /// 将task移出等待队列，返回它是否仍在队列中（即尚未被唤醒）
+    pub fn remove(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut queue = self.queue.exclusive_access();
+        match queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+            Some(idx) => {
+                queue.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+    /// 阻塞当前任务，直到被唤醒或到达expire_ms时刻。
+    /// 被唤醒返回true，超时返回false
+    pub fn wait(&self, expire_ms: Option<usize>) -> bool {
+        let task = current_task().unwrap();
+        self.add(task.clone());
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, task.clone());
+        }
+        drop(task);
+        block_current_and_run_next();
+
+        let task = current_task().unwrap();
+        remove_timer(&task);
+        // 若当前任务仍在等待队列中，说明是被定时器唤醒的
+        !self.remove(&task)
+    }
+    /// 唤醒一个等待者，返回是否有等待者被唤醒
+    pub fn wake_one(&self) -> bool {
+        let task = self.queue.exclusive_access().pop_front();
+        match task {
+            Some(task) => {
+                wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+    /// 唤醒所有等待者，返回唤醒的数目
+    pub fn wake_all(&self) -> usize {
+        let tasks = core::mem::take(&mut *self.queue.exclusive_access());
+        let count = tasks.len();
+        for task in tasks {
+            wakeup_task(task);
+        }
+        count
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
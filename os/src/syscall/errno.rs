@@ -0,0 +1,60 @@
+//! 系统调用出错时返回的Linux错误码（取相反数后返回给用户）
+//!
+//! 错误码只在SysError中定义一次，各变体直接导出，`-EINVAL`即得到返回给用户的isize
+use core::ops::Neg;
+
+pub use SysError::*;
+
+/// 系统调用的错误，取值与Linux的错误码相同
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(isize)]
+pub enum SysError {
+    ENOENT = 2,
+    ESRCH = 3,
+    EIO = 5,
+    EBADF = 9,
+    ECHILD = 10,
+    EAGAIN = 11,
+    ENOMEM = 12,
+    EACCES = 13,
+    EFAULT = 14,
+    EEXIST = 17,
+    ENODEV = 19,
+    EINVAL = 22,
+    ENOTTY = 25,
+    ENOSPC = 28,
+    EPIPE = 32,
+    ENAMETOOLONG = 36,
+    ENOSYS = 38,
+    ENOTSOCK = 88,
+    EDESTADDRREQ = 89,
+    EMSGSIZE = 90,
+    EPROTONOSUPPORT = 93,
+    EOPNOTSUPP = 95,
+    EAFNOSUPPORT = 97,
+    EADDRNOTAVAIL = 99,
+    ENETUNREACH = 101,
+    EISCONN = 106,
+    ENOTCONN = 107,
+    ETIMEDOUT = 110,
+    ECONNREFUSED = 111,
+    EINPROGRESS = 115,
+}
+
+impl SysError {
+    /// 对应的错误码，为正数
+    pub fn errno(self) -> isize {
+        self as isize
+    }
+}
+
+/// `-err`得到系统调用的返回值
+impl Neg for SysError {
+    type Output = isize;
+    fn neg(self) -> isize {
+        -self.errno()
+    }
+}
+
+pub type SysResult<T> = Result<T, SysError>;
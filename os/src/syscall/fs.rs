@@ -1,48 +1,226 @@
-use crate::mm::translated_byte_buffer;
-use crate::sbi::console_getchar;
-use crate::task::{current_user_token, suspend_current_and_run_next};
+use super::errno::*;
+use crate::fs::{open_file, EventFd, File, OpenFlags, PollEvents};
+use crate::mm::{UserCStr, UserPtr, UserSlice};
+use crate::random::fill_random;
+use crate::task::{block_current_and_run_next, current_task, current_user_token};
+use crate::timer::{add_timer, get_time_ms, remove_timer, TimeSpec};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
+const EFD_SEMAPHORE: u32 = 1;
+const EFD_NONBLOCK: u32 = 0o4000;
+const EFD_CLOEXEC: u32 = 0o2000000;
+const GRND_NONBLOCK: u32 = 1;
+const GRND_RANDOM: u32 = 2;
+const GRND_INSECURE: u32 = 4;
+/// ppoll等待无法发出通知的文件（如标准输入）时，重新查询的间隔
+const POLL_INTERVAL_MS: usize = 10;
+
+/// 与Linux的struct pollfd布局相同
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PollFd {
+    fd: i32,
+    events: u16,
+    revents: u16,
+}
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.writable() {
+            return -EBADF;
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        let file = file.clone();
+        // release current task TCB manually to avoid multi-borrow
+        drop(inner);
+        match UserSlice::new(token, buf, len).readable_buffer() {
+            Ok(buffer) => file.write(buffer),
+            Err(err) => -err.errno(),
         }
+    } else {
+        -EBADF
     }
 }
 
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            let mut c: usize;
-            loop {
-                c = console_getchar();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.readable() {
+            return -EBADF;
+        }
+        let file = file.clone();
+        // release current task TCB manually to avoid multi-borrow
+        drop(inner);
+        match UserSlice::new(token, buf, len).writable_buffer() {
+            Ok(buffer) => file.read(buffer),
+            Err(err) => -err.errno(),
+        }
+    } else {
+        -EBADF
+    }
+}
+
+/// 打开path处的文件，返回新的文件描述符。没有工作目录，dirfd和mode被忽略
+pub fn sys_openat(_dirfd: isize, path: *const u8, flags: u32, _mode: usize) -> isize {
+    let path = match UserCStr::new(current_user_token(), path).read() {
+        Ok(path) => path,
+        Err(err) => return -err.errno(),
+    };
+    let file = match open_file(path.as_str(), OpenFlags::from_bits_truncate(flags)) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(file);
+    fd as isize
+}
+
+/// 对fd对应的设备执行控制命令cmd，arg的含义由命令决定
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    file.ioctl(cmd, arg)
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return -EBADF;
+    }
+    inner.fd_table[fd].take();
+    0
+}
+
+/// 新建一个初值为initval的eventfd，返回其文件描述符。EFD_CLOEXEC被忽略
+pub fn sys_eventfd2(initval: u32, flags: u32) -> isize {
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    let eventfd = EventFd::new(
+        initval as u64,
+        flags & EFD_SEMAPHORE != 0,
+        flags & EFD_NONBLOCK != 0,
+    );
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(eventfd));
+    fd as isize
+}
+
+/// 等待fds中的任意一个文件就绪，timeout为相对时间，空指针表示无限等待。
+/// 返回revents非空的文件数目，超时返回0。信号屏蔽字被忽略
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const TimeSpec) -> isize {
+    let token = current_user_token();
+    let timeout = UserPtr::new(token, timeout);
+    let expire_ms = if timeout.is_null() {
+        None
+    } else {
+        match timeout.read().and_then(|timeout| timeout.expire_ms()) {
+            Ok(expire_ms) => Some(expire_ms),
+            Err(err) => return -err.errno(),
+        }
+    };
+    let user_fds = UserSlice::new(token, fds as *const PollFd, nfds);
+    let mut pollfds = match user_fds.read() {
+        Ok(pollfds) => pollfds,
+        Err(err) => return -err.errno(),
+    };
+    // fd为负数的项被忽略，不存在的fd对应None
+    let files: Vec<Option<Arc<dyn File + Send + Sync>>> = {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        pollfds
+            .iter()
+            .map(|pollfd| {
+                if pollfd.fd < 0 {
+                    None
                 } else {
-                    break;
+                    inner.fd_table.get(pollfd.fd as usize).cloned().flatten()
                 }
+            })
+            .collect()
+    };
+    loop {
+        let mut ready = 0;
+        for (pollfd, file) in pollfds.iter_mut().zip(files.iter()) {
+            let revents = match file {
+                Some(file) => file.poll(PollEvents::from_bits_truncate(pollfd.events)),
+                None if pollfd.fd >= 0 => PollEvents::NVAL,
+                None => PollEvents::empty(),
+            };
+            pollfd.revents = revents.bits();
+            if !revents.is_empty() {
+                ready += 1;
             }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+        }
+        let expired = matches!(expire_ms, Some(expire_ms) if get_time_ms() >= expire_ms);
+        if ready > 0 || expired {
+            return match user_fds.write(&pollfds) {
+                Ok(_) => ready,
+                Err(err) => -err.errno(),
+            };
+        }
+        // 在所有文件的等待队列上等待，不能发出通知的文件需要定期重新查询
+        let task = current_task().unwrap();
+        let mut wake_ms = expire_ms;
+        for file in files.iter().flatten() {
+            match file.wait_queue() {
+                Some(wait_queue) => wait_queue.add(task.clone()),
+                None => {
+                    let poll_ms = get_time_ms() + POLL_INTERVAL_MS;
+                    wake_ms = Some(wake_ms.map_or(poll_ms, |ms| ms.min(poll_ms)));
+                }
             }
-            1
         }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+        if let Some(wake_ms) = wake_ms {
+            add_timer(wake_ms, task.clone());
         }
+        drop(task);
+        block_current_and_run_next();
+
+        let task = current_task().unwrap();
+        remove_timer(&task);
+        for file in files.iter().flatten() {
+            if let Some(wait_queue) = file.wait_queue() {
+                wait_queue.remove(&task);
+            }
+        }
+    }
+}
+
+/// 用随机字节填满buf，返回填写的字节数。CSPRNG在启动时即已播种，因此从不阻塞，
+/// 各标志位均被接受但不改变行为
+pub fn sys_getrandom(buf: *mut u8, len: usize, flags: u32) -> isize {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0 {
+        return -EINVAL;
     }
-}
\ No newline at end of file
+    let token = current_user_token();
+    let buffer = match UserSlice::new(token, buf, len).writable_buffer() {
+        Ok(buffer) => buffer,
+        Err(err) => return -err.errno(),
+    };
+    for chunk in buffer.buffers {
+        fill_random(chunk);
+    }
+    len as isize
+}
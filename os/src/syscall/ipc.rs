@@ -0,0 +1,285 @@
+use super::errno::*;
+use crate::config::{PAGE_SIZE, USER_SHM_BASE, USER_SPACE_END};
+use crate::fs::{File, OpenFlags};
+use crate::ipc::{mq_open, mq_unlink, MqAttr, MqDescriptor, MqError};
+use crate::mm::{
+    shm_create, shm_find, shm_find_by_key, MapPermission, UserCStr, UserPtr, UserSlice, VirtAddr,
+};
+use crate::task::{current_task, current_user_token};
+use crate::timer::TimeSpec;
+use alloc::sync::Arc;
+use core::mem::size_of;
+
+/// 总是新建一个共享内存段
+const IPC_PRIVATE: usize = 0;
+const IPC_CREAT: usize = 0o1000;
+const IPC_EXCL: usize = 0o2000;
+const SHM_RDONLY: usize = 0o10000;
+
+/// 获取key对应的共享内存段，返回其id
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    if key != IPC_PRIVATE {
+        if let Some(segment) = shm_find_by_key(key) {
+            if shmflg & IPC_CREAT != 0 && shmflg & IPC_EXCL != 0 {
+                return -EEXIST;
+            }
+            if size > segment.size {
+                return -EINVAL;
+            }
+            return segment.id as isize;
+        }
+        if shmflg & IPC_CREAT == 0 {
+            return -ENOENT;
+        }
+    }
+    if size == 0 {
+        return -EINVAL;
+    }
+    match shm_create(key, size) {
+        Some(segment) => segment.id as isize,
+        None => -ENOMEM,
+    }
+}
+
+/// 将共享内存段挂接到当前进程的地址空间，shmaddr为0时由内核选择地址，
+/// 返回挂接的起始虚拟地址
+pub fn sys_shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    let segment = match shm_find(shmid) {
+        Some(segment) => segment,
+        None => return -EINVAL,
+    };
+    let mut permission = MapPermission::U | MapPermission::R;
+    if shmflg & SHM_RDONLY == 0 {
+        permission |= MapPermission::W;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let start_va = if shmaddr == 0 {
+        match inner
+            .memory_set
+            .find_free_area(VirtAddr::from(USER_SHM_BASE), segment.page_count())
+        {
+            Some(start_va) => start_va,
+            None => return -ENOMEM,
+        }
+    } else {
+        // 先排除用户地址空间之外的地址，VirtAddr::from会截断高位
+        let end = shmaddr.checked_add(segment.page_count() * PAGE_SIZE);
+        if shmaddr >= USER_SPACE_END || !matches!(end, Some(end) if end <= USER_SPACE_END) {
+            return -EINVAL;
+        }
+        let start_va = VirtAddr::from(shmaddr);
+        if shmaddr % PAGE_SIZE != 0
+            || !inner
+                .memory_set
+                .is_free(start_va.floor(), segment.page_count())
+        {
+            return -EINVAL;
+        }
+        start_va
+    };
+    match inner.memory_set.attach_shm(start_va, segment, permission) {
+        Ok(()) => start_va.0 as isize,
+        Err(err) => -err.errno(),
+    }
+}
+
+/// 解除shmaddr处共享内存段的挂接
+pub fn sys_shmdt(shmaddr: usize) -> isize {
+    if shmaddr % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.memory_set.detach_shm(VirtAddr::from(shmaddr).floor()) {
+        0
+    } else {
+        -EINVAL
+    }
+}
+
+fn mq_errno(err: MqError) -> isize {
+    match err {
+        MqError::WouldBlock => -EAGAIN,
+        MqError::TimedOut => -ETIMEDOUT,
+        MqError::NotFound => -ENOENT,
+        MqError::Exists => -EEXIST,
+        MqError::Invalid => -EINVAL,
+        MqError::NoSpace => -ENOSPC,
+        MqError::MsgSize => -EMSGSIZE,
+    }
+}
+
+/// 取出fd对应的文件，仅当它是消息队列描述符时返回
+fn mq_file(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = inner.fd_table.get(fd)?.clone()?;
+    file.downcast_ref::<MqDescriptor>()?;
+    Some(file)
+}
+
+/// 将用户给出的绝对超时时刻（以get_time的时钟计）转换为毫秒，空指针表示无限等待
+fn mq_expire_ms(token: usize, abs_timeout: *const TimeSpec) -> SysResult<Option<usize>> {
+    let abs_timeout = UserPtr::new(token, abs_timeout);
+    if abs_timeout.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(abs_timeout.read()?.to_ms()?))
+}
+
+/// 打开名为name的消息队列，返回其描述符。mode被忽略，
+/// attr仅在新建队列时使用，为空指针时使用默认属性
+pub fn sys_mq_open(name: *const u8, oflag: u32, _mode: usize, attr: *const MqAttr) -> isize {
+    let token = current_user_token();
+    let name = match UserCStr::new(token, name).read() {
+        Ok(name) => name,
+        Err(err) => return -err.errno(),
+    };
+    let flags = OpenFlags::from_bits_truncate(oflag);
+    let attr = UserPtr::new(token, attr);
+    let attr = if attr.is_null() {
+        None
+    } else {
+        match attr.read() {
+            Ok(attr) => Some(attr),
+            Err(err) => return -err.errno(),
+        }
+    };
+    match mq_open(name.as_str(), flags, attr) {
+        Ok(mqd) => {
+            let task = current_task().unwrap();
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(mqd);
+            fd as isize
+        }
+        Err(err) => mq_errno(err),
+    }
+}
+
+pub fn sys_mq_unlink(name: *const u8) -> isize {
+    let name = match UserCStr::new(current_user_token(), name).read() {
+        Ok(name) => name,
+        Err(err) => return -err.errno(),
+    };
+    match mq_unlink(name.as_str()) {
+        Ok(()) => 0,
+        Err(err) => mq_errno(err),
+    }
+}
+
+/// 向消息队列发送长为msg_len的消息，队列已满时阻塞至abs_timeout
+pub fn sys_mq_timedsend(
+    mqdes: usize,
+    msg: *const u8,
+    msg_len: usize,
+    msg_prio: u32,
+    abs_timeout: *const TimeSpec,
+) -> isize {
+    let token = current_user_token();
+    let file = match mq_file(mqdes) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    let mqd = file.downcast_ref::<MqDescriptor>().unwrap();
+    if !mqd.writable {
+        return -EBADF;
+    }
+    // 先检查长度，再按msg_len分配内核内存
+    if msg_len > mqd.queue.msgsize {
+        return -EMSGSIZE;
+    }
+    let msg = match UserSlice::new(token, msg, msg_len).read() {
+        Ok(msg) => msg,
+        Err(err) => return -err.errno(),
+    };
+    let expire_ms = match mq_expire_ms(token, abs_timeout) {
+        Ok(expire_ms) => expire_ms,
+        Err(err) => return -err.errno(),
+    };
+    match mqd.queue.send(msg, msg_prio, mqd.nonblock(), expire_ms) {
+        Ok(()) => 0,
+        Err(err) => mq_errno(err),
+    }
+}
+
+/// 从消息队列接收优先级最高的消息，队列为空时阻塞至abs_timeout，
+/// 返回消息的长度，msg_prio不为空指针时写入消息的优先级
+pub fn sys_mq_timedreceive(
+    mqdes: usize,
+    msg: *mut u8,
+    msg_len: usize,
+    msg_prio: *mut u32,
+    abs_timeout: *const TimeSpec,
+) -> isize {
+    let token = current_user_token();
+    let file = match mq_file(mqdes) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    let mqd = file.downcast_ref::<MqDescriptor>().unwrap();
+    if !mqd.readable {
+        return -EBADF;
+    }
+    if msg_len < mqd.queue.msgsize {
+        return -EMSGSIZE;
+    }
+    let expire_ms = match mq_expire_ms(token, abs_timeout) {
+        Ok(expire_ms) => expire_ms,
+        Err(err) => return -err.errno(),
+    };
+    // 取出消息之前检查用户缓冲区与msg_prio，以免出错时消息丢失
+    let mut buffer = match UserSlice::new(token, msg, msg_len).writable_buffer() {
+        Ok(buffer) => buffer,
+        Err(err) => return -err.errno(),
+    };
+    let mut prio_buffer = if msg_prio.is_null() {
+        None
+    } else {
+        match UserSlice::new(token, msg_prio as *const u8, size_of::<u32>()).writable_buffer() {
+            Ok(buffer) => Some(buffer),
+            Err(err) => return -err.errno(),
+        }
+    };
+    match mqd.queue.receive(mqd.nonblock(), expire_ms) {
+        Ok((message, prio)) => {
+            buffer.write_from(&message);
+            if let Some(prio_buffer) = prio_buffer.as_mut() {
+                prio_buffer.write_from(&prio.to_ne_bytes());
+            }
+            message.len() as isize
+        }
+        Err(err) => mq_errno(err),
+    }
+}
+
+/// 读取消息队列的属性写入oldattr，newattr不为空指针时按其mq_flags设置O_NONBLOCK
+pub fn sys_mq_getsetattr(mqdes: usize, newattr: *const MqAttr, oldattr: *mut MqAttr) -> isize {
+    let token = current_user_token();
+    let file = match mq_file(mqdes) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    let mqd = file.downcast_ref::<MqDescriptor>().unwrap();
+    let attr = mqd.attr();
+    let newattr = UserPtr::new(token, newattr);
+    if !newattr.is_null() {
+        let newattr = match newattr.read() {
+            Ok(newattr) => newattr,
+            Err(err) => return -err.errno(),
+        };
+        let nonblock = OpenFlags::NONBLOCK.bits() as usize;
+        if newattr.mq_flags & !nonblock != 0 {
+            return -EINVAL;
+        }
+        mqd.set_nonblock(newattr.mq_flags & nonblock != 0);
+    }
+    let oldattr = UserPtr::new(token, oldattr);
+    if !oldattr.is_null() {
+        if let Err(err) = oldattr.write(attr) {
+            return -err.errno();
+        }
+    }
+    0
+}
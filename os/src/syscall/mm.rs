@@ -0,0 +1,121 @@
+use super::errno::*;
+use crate::config::{PAGE_SIZE, USER_MMAP_BASE, USER_SPACE_END};
+use crate::mm::{frame_available, MapPermission, VirtAddr};
+use crate::task::current_task;
+
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// 映射内存，返回映射的起始地址。
+/// 匿名映射只支持MAP_PRIVATE，得到清零的私有内存；
+/// 文件映射只支持MAP_SHARED，由文件提供被映射的物理页帧（如/dev/fb0的帧缓冲）
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    if len == 0 || offset % PAGE_SIZE != 0 || len > USER_SPACE_END {
+        return -EINVAL;
+    }
+    let page_count = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let mut permission = MapPermission::U;
+    if prot & PROT_READ != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let task = current_task().unwrap();
+    let frames = if flags & MAP_ANONYMOUS != 0 {
+        if flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE {
+            return -EINVAL;
+        }
+        // 映射时立即分配物理页帧，明显不够时不必逐页分配再回滚
+        if page_count > frame_available() {
+            return -ENOMEM;
+        }
+        None
+    } else {
+        if flags & (MAP_SHARED | MAP_PRIVATE) != MAP_SHARED {
+            return -EINVAL;
+        }
+        let inner = task.inner_exclusive_access();
+        let file = match inner.fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return -EBADF,
+        };
+        drop(inner);
+        if !file.readable() || (prot & PROT_WRITE != 0 && !file.writable()) {
+            return -EACCES;
+        }
+        match file.mmap(offset, page_count * PAGE_SIZE) {
+            Ok(frames) => Some(frames),
+            Err(err) => return -err.errno(),
+        }
+    };
+
+    let mut inner = task.inner_exclusive_access();
+    let start_va = if flags & MAP_FIXED != 0 {
+        // 不支持覆盖已有的映射
+        if addr % PAGE_SIZE != 0
+            || addr > USER_SPACE_END - page_count * PAGE_SIZE
+            || !inner
+                .memory_set
+                .is_free(VirtAddr::from(addr).floor(), page_count)
+        {
+            return -EINVAL;
+        }
+        VirtAddr::from(addr)
+    } else {
+        match inner
+            .memory_set
+            .find_free_area(VirtAddr::from(USER_MMAP_BASE), page_count)
+        {
+            Some(start_va) => start_va,
+            None => return -ENOMEM,
+        }
+    };
+    let result = match frames {
+        Some(frames) => inner
+            .memory_set
+            .insert_device_area(start_va, frames, permission),
+        None => inner.memory_set.insert_anonymous_area(
+            start_va,
+            VirtAddr::from(start_va.0 + page_count * PAGE_SIZE),
+            permission,
+        ),
+    };
+    match result {
+        Ok(()) => start_va.0 as isize,
+        Err(err) => -err.errno(),
+    }
+}
+
+/// 解除映射，只能一次解除一整个由mmap建立的映射
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    if addr % PAGE_SIZE != 0 || addr >= USER_SPACE_END || len == 0 {
+        return -EINVAL;
+    }
+    let page_count = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner
+        .memory_set
+        .remove_mapping(VirtAddr::from(addr).floor(), page_count)
+    {
+        0
+    } else {
+        -EINVAL
+    }
+}
@@ -1,31 +1,154 @@
 // os/src/syscall/mod.rs
+const SYSCALL_EVENTFD2: usize = 19;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PPOLL: usize = 73;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_SYSLOG: usize = 116;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_MQ_OPEN: usize = 180;
+const SYSCALL_MQ_UNLINK: usize = 181;
+const SYSCALL_MQ_TIMEDSEND: usize = 182;
+const SYSCALL_MQ_TIMEDRECEIVE: usize = 183;
+const SYSCALL_MQ_GETSETATTR: usize = 185;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_SOCKET: usize = 198;
+const SYSCALL_BIND: usize = 200;
+const SYSCALL_LISTEN: usize = 201;
+const SYSCALL_ACCEPT: usize = 202;
+const SYSCALL_CONNECT: usize = 203;
+const SYSCALL_SENDTO: usize = 206;
+const SYSCALL_RECVFROM: usize = 207;
+const SYSCALL_SHUTDOWN: usize = 210;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
+/// 本内核特有的系统调用，编号避开Linux已使用的范围
+const SYSCALL_STRACE: usize = 1000;
 
+pub mod errno;
 mod fs;
+mod ipc;
+mod mm;
+mod net;
 mod process;
+mod sync;
+mod syslog;
+mod trace;
 
+use crate::task::current_trace_mode;
+use errno::ENOSYS;
 use fs::*;
+use ipc::*;
+use mm::*;
+use net::*;
 use process::*;
+use sync::*;
+use syslog::*;
+use trace::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub use trace::{AtomicTraceMode, TraceMode, TraceState};
+
+/// 系统调用的入口，当前进程被跟踪时记录这次调用
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    if current_trace_mode() == TraceMode::Off {
+        dispatch(syscall_id, args)
+    } else {
+        trace_syscall(syscall_id, args, || dispatch(syscall_id, args))
+    }
+}
+
+/// 按编号分发系统调用，出错时返回相反数形式的错误码，未知的系统调用返回-ENOSYS
+fn dispatch(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
+        SYSCALL_EVENTFD2 => sys_eventfd2(args[0] as u32, args[1] as u32),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_OPENAT => sys_openat(
+            args[0] as isize,
+            args[1] as *const u8,
+            args[2] as u32,
+            args[3],
+        ),
+        SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_PPOLL => sys_ppoll(args[0] as *mut _, args[1], args[2] as *const _),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0] as *mut u32, args[1], args[2], args[3] as *const _),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut _),
+        SYSCALL_SYSLOG => sys_syslog(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_MQ_OPEN => sys_mq_open(
+            args[0] as *const u8,
+            args[1] as u32,
+            args[2],
+            args[3] as *const _,
+        ),
+        SYSCALL_MQ_UNLINK => sys_mq_unlink(args[0] as *const u8),
+        SYSCALL_MQ_TIMEDSEND => sys_mq_timedsend(
+            args[0],
+            args[1] as *const u8,
+            args[2],
+            args[3] as u32,
+            args[4] as *const _,
+        ),
+        SYSCALL_MQ_TIMEDRECEIVE => sys_mq_timedreceive(
+            args[0],
+            args[1] as *mut u8,
+            args[2],
+            args[3] as *mut u32,
+            args[4] as *const _,
+        ),
+        SYSCALL_MQ_GETSETATTR => sys_mq_getsetattr(args[0], args[1] as *const _, args[2] as *mut _),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
+        SYSCALL_SOCKET => sys_socket(args[0], args[1], args[2]),
+        SYSCALL_BIND => sys_bind(args[0], args[1] as *const _, args[2]),
+        SYSCALL_LISTEN => sys_listen(args[0], args[1]),
+        SYSCALL_ACCEPT => sys_accept(args[0], args[1] as *mut _, args[2] as *mut u32),
+        SYSCALL_CONNECT => sys_connect(args[0], args[1] as *const _, args[2]),
+        SYSCALL_SENDTO => sys_sendto(
+            args[0],
+            args[1] as *const u8,
+            args[2],
+            args[3],
+            args[4] as *const _,
+            args[5],
+        ),
+        SYSCALL_RECVFROM => sys_recvfrom(
+            args[0],
+            args[1] as *mut u8,
+            args[2],
+            args[3],
+            args[4] as *mut _,
+            args[5] as *mut u32,
+        ),
+        SYSCALL_SHUTDOWN => sys_shutdown(args[0], args[1]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_GETRANDOM => sys_getrandom(args[0] as *mut u8, args[1], args[2] as u32),
+        SYSCALL_STRACE => sys_strace(args[0], args[1], args[2] as *mut u8, args[3]),
+        _ => {
+            warn!("unsupported syscall_id: {}", syscall_id);
+            -ENOSYS
+        }
     }
 }
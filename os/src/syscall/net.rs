@@ -0,0 +1,244 @@
+use super::errno::*;
+use crate::fs::File;
+use crate::mm::{UserPtr, UserSlice};
+use crate::net::{Socket, SocketType};
+use crate::task::{current_task, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec;
+use core::mem::size_of;
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+const AF_INET: u16 = 2;
+const SOCK_STREAM: usize = 1;
+const SOCK_DGRAM: usize = 2;
+const SOCK_NONBLOCK: usize = 0o4000;
+const SOCK_CLOEXEC: usize = 0o2000000;
+const IPPROTO_TCP: usize = 6;
+const IPPROTO_UDP: usize = 17;
+
+/// 与Linux的struct sockaddr_in布局相同，端口与地址均为网络字节序
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SockAddrIn {
+    sin_family: u16,
+    sin_port: u16,
+    sin_addr: [u8; 4],
+    sin_zero: [u8; 8],
+}
+
+/// 读取用户给出的IPv4地址
+fn read_sockaddr(token: usize, addr: *const SockAddrIn, addrlen: usize) -> SysResult<IpEndpoint> {
+    if addr.is_null() {
+        return Err(SysError::EFAULT);
+    }
+    if addrlen < size_of::<SockAddrIn>() {
+        return Err(SysError::EINVAL);
+    }
+    let addr = UserPtr::new(token, addr).read()?;
+    if addr.sin_family != AF_INET {
+        return Err(SysError::EAFNOSUPPORT);
+    }
+    Ok(IpEndpoint::new(
+        IpAddress::Ipv4(Ipv4Address(addr.sin_addr)),
+        u16::from_be(addr.sin_port),
+    ))
+}
+
+/// addr不为空指针时写回对端地址及其长度
+fn write_sockaddr(
+    token: usize,
+    endpoint: IpEndpoint,
+    addr: *mut SockAddrIn,
+    addrlen: *mut u32,
+) -> SysResult<()> {
+    if addr.is_null() {
+        return Ok(());
+    }
+    let sin_addr = match endpoint.addr {
+        IpAddress::Ipv4(ip) => ip.0,
+        _ => [0; 4],
+    };
+    let sockaddr = SockAddrIn {
+        sin_family: AF_INET,
+        sin_port: endpoint.port.to_be(),
+        sin_addr,
+        sin_zero: [0; 8],
+    };
+    UserPtr::new(token, addr).write(sockaddr)?;
+    let addrlen = UserPtr::new(token, addrlen);
+    if !addrlen.is_null() {
+        addrlen.write(size_of::<SockAddrIn>() as u32)?;
+    }
+    Ok(())
+}
+
+/// 取出fd对应的文件，仅当它是套接字时返回
+fn socket_file(fd: usize) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = inner
+        .fd_table
+        .get(fd)
+        .cloned()
+        .flatten()
+        .ok_or(SysError::EBADF)?;
+    file.downcast_ref::<Socket>().ok_or(SysError::ENOTSOCK)?;
+    Ok(file)
+}
+
+fn alloc_socket_fd(socket: Arc<Socket>) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(socket);
+    fd as isize
+}
+
+/// 创建IPv4的TCP或UDP套接字
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    if domain != AF_INET as usize {
+        return -EAFNOSUPPORT;
+    }
+    let nonblock = socket_type & SOCK_NONBLOCK != 0;
+    let socket_type = match (socket_type & !(SOCK_NONBLOCK | SOCK_CLOEXEC), protocol) {
+        (SOCK_STREAM, 0) | (SOCK_STREAM, IPPROTO_TCP) => SocketType::Stream,
+        (SOCK_DGRAM, 0) | (SOCK_DGRAM, IPPROTO_UDP) => SocketType::Datagram,
+        (SOCK_STREAM, _) | (SOCK_DGRAM, _) => return -EPROTONOSUPPORT,
+        _ => return -EINVAL,
+    };
+    alloc_socket_fd(Arc::new(Socket::new(socket_type, nonblock)))
+}
+
+pub fn sys_bind(sockfd: usize, addr: *const SockAddrIn, addrlen: usize) -> isize {
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    let socket = file.downcast_ref::<Socket>().unwrap();
+    match read_sockaddr(current_user_token(), addr, addrlen).and_then(|ep| socket.bind(ep)) {
+        Ok(()) => 0,
+        Err(err) => -err.errno(),
+    }
+}
+
+pub fn sys_listen(sockfd: usize, backlog: usize) -> isize {
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    match file.downcast_ref::<Socket>().unwrap().listen(backlog) {
+        Ok(()) => 0,
+        Err(err) => -err.errno(),
+    }
+}
+
+/// 等待并取出一个连接，返回新连接的描述符，addr不为空指针时写入对端地址
+pub fn sys_accept(sockfd: usize, addr: *mut SockAddrIn, addrlen: *mut u32) -> isize {
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    match file.downcast_ref::<Socket>().unwrap().accept() {
+        Ok((socket, remote)) => {
+            if let Err(err) = write_sockaddr(current_user_token(), remote, addr, addrlen) {
+                return -err.errno();
+            }
+            alloc_socket_fd(socket)
+        }
+        Err(err) => -err.errno(),
+    }
+}
+
+pub fn sys_connect(sockfd: usize, addr: *const SockAddrIn, addrlen: usize) -> isize {
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    let socket = file.downcast_ref::<Socket>().unwrap();
+    match read_sockaddr(current_user_token(), addr, addrlen).and_then(|ep| socket.connect(ep)) {
+        Ok(()) => 0,
+        Err(err) => -err.errno(),
+    }
+}
+
+/// 发送数据，dest_addr为空指针时发往已连接的对端。flags被忽略
+pub fn sys_sendto(
+    sockfd: usize,
+    buf: *const u8,
+    len: usize,
+    _flags: usize,
+    dest_addr: *const SockAddrIn,
+    addrlen: usize,
+) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    let socket = file.downcast_ref::<Socket>().unwrap();
+    let dest = if dest_addr.is_null() {
+        None
+    } else {
+        match read_sockaddr(token, dest_addr, addrlen) {
+            Ok(dest) => Some(dest),
+            Err(err) => return -err.errno(),
+        }
+    };
+    // 只复制一次能发送的部分，不按用户给出的长度分配内核内存
+    let len = match socket.send_len(len) {
+        Ok(len) => len,
+        Err(err) => return -err.errno(),
+    };
+    let data = match UserSlice::new(token, buf, len).read() {
+        Ok(data) => data,
+        Err(err) => return -err.errno(),
+    };
+    match socket.send(&data, dest) {
+        Ok(len) => len as isize,
+        Err(err) => -err.errno(),
+    }
+}
+
+/// 接收数据，返回其长度，src_addr不为空指针时写入发送方地址。flags被忽略
+pub fn sys_recvfrom(
+    sockfd: usize,
+    buf: *mut u8,
+    len: usize,
+    _flags: usize,
+    src_addr: *mut SockAddrIn,
+    addrlen: *mut u32,
+) -> isize {
+    let token = current_user_token();
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    let socket = file.downcast_ref::<Socket>().unwrap();
+    // 接收之前检查用户缓冲区，以免出错时数据丢失
+    let mut buffer = match UserSlice::new(token, buf, len).writable_buffer() {
+        Ok(buffer) => buffer,
+        Err(err) => return -err.errno(),
+    };
+    let mut data = vec![0u8; socket.recv_len(len)];
+    match socket.recv(&mut data) {
+        Ok((len, src)) => {
+            buffer.write_from(&data[..len]);
+            match write_sockaddr(token, src, src_addr, addrlen) {
+                Ok(()) => len as isize,
+                Err(err) => -err.errno(),
+            }
+        }
+        Err(err) => -err.errno(),
+    }
+}
+
+pub fn sys_shutdown(sockfd: usize, how: usize) -> isize {
+    let file = match socket_file(sockfd) {
+        Ok(file) => file,
+        Err(err) => return -err.errno(),
+    };
+    match file.downcast_ref::<Socket>().unwrap().shutdown(how) {
+        Ok(()) => 0,
+        Err(err) => -err.errno(),
+    }
+}
@@ -1,10 +1,13 @@
+use crate::cmdline::cmdline;
 use crate::loader::get_app_data_by_name;
-use crate::mm::{translated_refmut, translated_str};
+use crate::mm::{UserCStr, UserPtr};
 use crate::task::{
     add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    suspend_current_and_run_next, TaskNesting,
 };
-use crate::timer::get_time_ms;
+use super::errno::*;
+use super::trace::TraceMode;
+use crate::timer::{get_realtime_ns, get_time_ms, get_time_ns, TimeSpec};
 use alloc::sync::Arc;
 
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -21,13 +24,34 @@ pub fn sys_get_time() -> isize {
     get_time_ms() as isize
 }
 
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_BOOTTIME: usize = 7;
+
+/// 读取指定时钟的时间，精度为纳秒。
+/// CLOCK_REALTIME为墙上时间，CLOCK_MONOTONIC与CLOCK_BOOTTIME为开机以来的时间
+pub fn sys_clock_gettime(clockid: usize, tp: *mut TimeSpec) -> isize {
+    let ns = match clockid {
+        CLOCK_REALTIME => get_realtime_ns(),
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => get_time_ns(),
+        _ => return -EINVAL,
+    };
+    match UserPtr::new(current_user_token(), tp).write(TimeSpec::from_ns(ns)) {
+        Ok(()) => 0,
+        Err(err) => -err.errno(),
+    }
+}
+
 pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
 pub fn sys_fork() -> isize {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = match current_task.fork() {
+        Ok(new_task) => new_task,
+        Err(err) => return -err.errno(),
+    };
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -41,18 +65,26 @@ pub fn sys_fork() -> isize {
 
 pub fn sys_exec(path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match UserCStr::new(token, path).read() {
+        Ok(path) => path,
+        Err(err) => return -err.errno(),
+    };
     if let Some(data) = get_app_data_by_name(path.as_str()) {
         let task = current_task().unwrap();
-        task.exec(data);
+        if let Err(err) = task.exec(data) {
+            return -err.errno();
+        }
+        if cmdline().traced(path.as_str()) {
+            task.trace_mode.store(TraceMode::Console);
+        }
         0
     } else {
-        -1
+        -ENOENT
     }
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
+/// If there is not a child process whose pid is same as given, return -ECHILD.
+/// Else if there is a child process but it is still running, return -EAGAIN.
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     let task = current_task().unwrap();
     // find a child process
@@ -64,26 +96,35 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return -ECHILD;
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
         // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+        p.inner_exclusive_access_nested(TaskNesting::Child)
+            .is_zombie()
+            && (pid == -1 || pid as usize == p.getpid())
         // ++++ release child PCB
     });
     if let Some((idx, _)) = pair {
+        // 先写回退出码，出错时子进程留待下次回收
+        // ++++ temporarily access child TCB exclusively
+        let exit_code = inner.children[idx]
+            .inner_exclusive_access_nested(TaskNesting::Child)
+            .exit_code;
+        // ++++ release child PCB
+        let exit_code_ptr = UserPtr::new(inner.memory_set.token(), exit_code_ptr);
+        if !exit_code_ptr.is_null() {
+            if let Err(err) = exit_code_ptr.write(exit_code) {
+                return -err.errno();
+            }
+        }
         let child = inner.children.remove(idx);
         // confirm that child will be deallocated after removing from children list
         assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        child.getpid() as isize
     } else {
-        -2
+        -EAGAIN
     }
     // ---- release current PCB lock automatically
 }
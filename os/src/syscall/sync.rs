@@ -0,0 +1,53 @@
+use super::errno::*;
+use crate::mm::UserPtr;
+use crate::sync::{futex_wait, futex_wake};
+use crate::task::current_user_token;
+use crate::timer::TimeSpec;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+/// 仅在本进程内使用的futex，由于我们总以物理地址为键，可以直接忽略
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// 与Linux兼容的futex系统调用，仅支持FUTEX_WAIT和FUTEX_WAKE。
+/// FUTEX_WAIT: 若*uaddr == val则阻塞，timeout为相对时间，为空指针时无限等待；
+/// FUTEX_WAKE: 唤醒至多val个等待者，返回唤醒的数目
+pub fn sys_futex(uaddr: *mut u32, futex_op: usize, val: usize, timeout: *const TimeSpec) -> isize {
+    if uaddr as usize % core::mem::size_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let uaddr = UserPtr::new(token, uaddr);
+    // 以uaddr对应的物理地址作为futex的键，uaddr已对齐，不会跨越页边界
+    let key = match uaddr.paddr() {
+        Ok(key) => key,
+        Err(err) => return -err.errno(),
+    };
+    match futex_op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let value = match uaddr.read() {
+                Ok(value) => value,
+                Err(err) => return -err.errno(),
+            };
+            if value != val as u32 {
+                return -EAGAIN;
+            }
+            let timeout = UserPtr::new(token, timeout);
+            let expire_ms = if timeout.is_null() {
+                None
+            } else {
+                match timeout.read().and_then(|timeout| timeout.expire_ms()) {
+                    Ok(expire_ms) => Some(expire_ms),
+                    Err(err) => return -err.errno(),
+                }
+            };
+            if futex_wait(key, expire_ms) {
+                0
+            } else {
+                -ETIMEDOUT
+            }
+        }
+        FUTEX_WAKE => futex_wake(key, val) as isize,
+        _ => -ENOSYS,
+    }
+}
@@ -0,0 +1,38 @@
+use super::errno::*;
+use crate::console::{clear_log, log_buffer_size, read_log, take_log};
+use crate::mm::UserSlice;
+use crate::task::current_user_token;
+
+// syslog的操作类型，与Linux相同
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+/// 读取或清空内核日志缓冲区。READ_ALL把最后的至多len个字节复制到buf并返回字节数，
+/// READ_CLEAR取出最旧的至多len个字节并只移除这些字节，CLEAR清空全部，
+/// SIZE_BUFFER返回缓冲区的容量
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> isize {
+    match action {
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            let token = current_user_token();
+            // 先检查用户缓冲区，无效时不取出日志，READ_CLEAR也就不会丢失内容
+            let mut user_buf = match UserSlice::new(token, buf, len).writable_buffer() {
+                Ok(user_buf) => user_buf,
+                Err(err) => return -err.errno(),
+            };
+            let log = if action == SYSLOG_ACTION_READ_CLEAR {
+                take_log(len)
+            } else {
+                read_log(len)
+            };
+            user_buf.write_from(&log) as isize
+        }
+        SYSLOG_ACTION_CLEAR => {
+            clear_log();
+            0
+        }
+        SYSLOG_ACTION_SIZE_BUFFER => log_buffer_size() as isize,
+        _ => -EINVAL,
+    }
+}
@@ -0,0 +1,260 @@
+//! 类似strace的系统调用跟踪。被跟踪的进程每次系统调用都记录一行：
+//! 调用名、解码后的参数、返回值与耗时，写到串口或进程自己的跟踪缓冲区。
+//! 跟踪模式由strace系统调用设置，或在启动/exec命令行strace=中列出的程序时打开，
+//! fork出的子进程继承父进程的跟踪模式
+use super::errno::*;
+use super::*;
+use crate::mm::{UserCStr, UserSlice};
+use crate::task::{current_task, current_user_token, TaskControlBlock};
+use crate::timer::get_time_ns;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+// strace系统调用的操作类型
+const STRACE_OFF: usize = 0;
+const STRACE_CONSOLE: usize = 1;
+const STRACE_BUFFER: usize = 2;
+const STRACE_READ: usize = 3;
+
+/// 跟踪缓冲区的容量，写满后丢弃最旧的内容
+const TRACE_BUF_SIZE: usize = 16 * 1024;
+/// 字符串参数最多显示的字符数
+const MAX_STR_ARG: usize = 32;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceMode {
+    Off,
+    /// 每条记录直接打印到串口
+    Console,
+    /// 记录写入进程的跟踪缓冲区，由进程自己或其父进程读取
+    Buffer,
+}
+
+/// 任务控制块中的跟踪模式。每次系统调用都要读取，
+/// 放在原子变量中，不必借用任务控制块的inner
+pub struct AtomicTraceMode(AtomicU8);
+
+impl AtomicTraceMode {
+    pub fn new(mode: TraceMode) -> Self {
+        Self(AtomicU8::new(mode as u8))
+    }
+    pub fn load(&self) -> TraceMode {
+        match self.0.load(Ordering::Relaxed) {
+            0 => TraceMode::Off,
+            1 => TraceMode::Console,
+            _ => TraceMode::Buffer,
+        }
+    }
+    pub fn store(&self, mode: TraceMode) {
+        self.0.store(mode as u8, Ordering::Relaxed);
+    }
+}
+
+/// 进程的跟踪记录，保存在任务控制块的inner中。
+/// fork出的子进程沿用跟踪模式，缓冲区从空开始
+#[derive(Default)]
+pub struct TraceState {
+    buffer: VecDeque<u8>,
+}
+
+impl TraceState {
+    fn push(&mut self, line: &str) {
+        for &byte in line.as_bytes().iter().chain(b"\n") {
+            if self.buffer.len() == TRACE_BUF_SIZE {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back(byte);
+        }
+    }
+}
+
+/// 参数的显示方式
+#[derive(Copy, Clone)]
+enum Arg {
+    /// 有符号十进制数，如文件描述符与长度
+    Dec,
+    /// 十六进制数，如指针与标志位
+    Hex,
+    /// 用户内存中以'\0'结尾的字符串
+    Str,
+}
+
+use Arg::*;
+
+/// 系统调用的名字与各参数的显示方式
+fn signature(syscall_id: usize) -> Option<(&'static str, &'static [Arg])> {
+    let signature: (&str, &[Arg]) = match syscall_id {
+        SYSCALL_EVENTFD2 => ("eventfd2", &[Dec, Hex]),
+        SYSCALL_IOCTL => ("ioctl", &[Dec, Hex, Hex]),
+        SYSCALL_OPENAT => ("openat", &[Dec, Str, Hex, Hex]),
+        SYSCALL_CLOSE => ("close", &[Dec]),
+        SYSCALL_READ => ("read", &[Dec, Hex, Dec]),
+        SYSCALL_WRITE => ("write", &[Dec, Hex, Dec]),
+        SYSCALL_PPOLL => ("ppoll", &[Hex, Dec, Hex]),
+        SYSCALL_EXIT => ("exit", &[Dec]),
+        SYSCALL_FUTEX => ("futex", &[Hex, Dec, Dec, Hex]),
+        SYSCALL_CLOCK_GETTIME => ("clock_gettime", &[Dec, Hex]),
+        SYSCALL_SYSLOG => ("syslog", &[Dec, Hex, Dec]),
+        SYSCALL_YIELD => ("sched_yield", &[]),
+        SYSCALL_GET_TIME => ("get_time", &[]),
+        SYSCALL_GETPID => ("getpid", &[]),
+        SYSCALL_MQ_OPEN => ("mq_open", &[Str, Hex, Hex, Hex]),
+        SYSCALL_MQ_UNLINK => ("mq_unlink", &[Str]),
+        SYSCALL_MQ_TIMEDSEND => ("mq_timedsend", &[Dec, Hex, Dec, Dec, Hex]),
+        SYSCALL_MQ_TIMEDRECEIVE => ("mq_timedreceive", &[Dec, Hex, Dec, Hex, Hex]),
+        SYSCALL_MQ_GETSETATTR => ("mq_getsetattr", &[Dec, Hex, Hex]),
+        SYSCALL_SHMGET => ("shmget", &[Hex, Dec, Hex]),
+        SYSCALL_SHMAT => ("shmat", &[Dec, Hex, Hex]),
+        SYSCALL_SHMDT => ("shmdt", &[Hex]),
+        SYSCALL_SOCKET => ("socket", &[Dec, Dec, Dec]),
+        SYSCALL_BIND => ("bind", &[Dec, Hex, Dec]),
+        SYSCALL_LISTEN => ("listen", &[Dec, Dec]),
+        SYSCALL_ACCEPT => ("accept", &[Dec, Hex, Hex]),
+        SYSCALL_CONNECT => ("connect", &[Dec, Hex, Dec]),
+        SYSCALL_SENDTO => ("sendto", &[Dec, Hex, Dec, Hex, Hex, Dec]),
+        SYSCALL_RECVFROM => ("recvfrom", &[Dec, Hex, Dec, Hex, Hex, Hex]),
+        SYSCALL_SHUTDOWN => ("shutdown", &[Dec, Dec]),
+        SYSCALL_MUNMAP => ("munmap", &[Hex, Dec]),
+        SYSCALL_FORK => ("fork", &[]),
+        SYSCALL_EXEC => ("exec", &[Str]),
+        SYSCALL_MMAP => ("mmap", &[Hex, Dec, Hex, Hex, Dec, Hex]),
+        SYSCALL_WAITPID => ("waitpid", &[Dec, Hex]),
+        SYSCALL_GETRANDOM => ("getrandom", &[Hex, Dec, Hex]),
+        SYSCALL_STRACE => ("strace", &[Dec, Dec, Hex, Dec]),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+/// 按strace的格式写出调用，如openat(-100, "/dev/fb0", 0x2, 0x0)。
+/// 字符串参数须在调用之前读取，exec之后原来的地址空间就不存在了
+fn format_call(syscall_id: usize, args: &[usize; 6]) -> String {
+    let mut call = String::new();
+    let (name, kinds) = match signature(syscall_id) {
+        Some(signature) => signature,
+        None => {
+            let _ = write!(
+                call,
+                "syscall_{}({:#x}, {:#x}, {:#x})",
+                syscall_id, args[0], args[1], args[2]
+            );
+            return call;
+        }
+    };
+    call.push_str(name);
+    call.push('(');
+    for (idx, (kind, &arg)) in kinds.iter().zip(args.iter()).enumerate() {
+        if idx > 0 {
+            call.push_str(", ");
+        }
+        let _ = match kind {
+            Dec => write!(call, "{}", arg as isize),
+            Hex => write!(call, "{:#x}", arg),
+            Str => match UserCStr::new(current_user_token(), arg as *const u8).read() {
+                Ok(s) if s.chars().count() > MAX_STR_ARG => {
+                    let prefix: String = s.chars().take(MAX_STR_ARG).collect();
+                    write!(call, "\"{}\"...", prefix.escape_debug())
+                }
+                Ok(s) => write!(call, "\"{}\"", s.escape_debug()),
+                Err(_) => write!(call, "{:#x}", arg),
+            },
+        };
+    }
+    call.push(')');
+    call
+}
+
+/// 把一条记录交给当前进程的跟踪输出
+fn emit(task: &TaskControlBlock, line: &str) {
+    match task.trace_mode.load() {
+        TraceMode::Off => {}
+        TraceMode::Console => println!("[strace {}] {}", task.pid.0, line),
+        TraceMode::Buffer => task.inner_exclusive_access().trace.push(line),
+    }
+}
+
+/// 执行一次被跟踪的系统调用，dispatch完成实际的调用。
+/// exit不会返回，因此在调用之前记录
+pub fn trace_syscall(
+    syscall_id: usize,
+    args: [usize; 6],
+    dispatch: impl FnOnce() -> isize,
+) -> isize {
+    let task = current_task().unwrap();
+    let call = format_call(syscall_id, &args);
+    if syscall_id == SYSCALL_EXIT {
+        emit(&task, &format!("{} = ?", call));
+        drop(call);
+        drop(task);
+        return dispatch();
+    }
+    // 调用期间可能阻塞或切换，不持有任务的引用以外的任何借用
+    let start = get_time_ns();
+    let ret = dispatch();
+    let elapsed = get_time_ns() - start;
+    emit(
+        &task,
+        &format!(
+            "{} = {} <{}.{:06}>",
+            call,
+            ret,
+            elapsed / 1_000_000_000,
+            elapsed % 1_000_000_000 / 1000
+        ),
+    );
+    ret
+}
+
+/// pid为0时返回调用者自己，否则返回调用者pid相同的子进程
+fn strace_target(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    let task = current_task().unwrap();
+    if pid == 0 {
+        return Some(task);
+    }
+    let inner = task.inner_exclusive_access();
+    inner
+        .children
+        .iter()
+        .find(|child| child.getpid() == pid)
+        .cloned()
+}
+
+/// 设置或读取进程的系统调用跟踪，pid为0表示调用者自己，否则须是调用者的子进程。
+/// STRACE_OFF、STRACE_CONSOLE与STRACE_BUFFER设置跟踪模式，设置时清空跟踪缓冲区；
+/// STRACE_READ从跟踪缓冲区取出至多len个字节写入buf，返回字节数。
+/// 子进程退出后、被waitpid回收之前仍可以读取
+pub fn sys_strace(op: usize, pid: usize, buf: *mut u8, len: usize) -> isize {
+    let target = match strace_target(pid) {
+        Some(target) => target,
+        None => return -ESRCH,
+    };
+    let mode = match op {
+        STRACE_OFF => TraceMode::Off,
+        STRACE_CONSOLE => TraceMode::Console,
+        STRACE_BUFFER => TraceMode::Buffer,
+        STRACE_READ => {
+            // 取出之前检查用户缓冲区，以免出错时记录丢失
+            let user_buf = UserSlice::new(current_user_token(), buf, len);
+            let mut buffer = match user_buf.writable_buffer() {
+                Ok(buffer) => buffer,
+                Err(err) => return -err.errno(),
+            };
+            let data: Vec<u8> = {
+                let mut inner = target.inner_exclusive_access();
+                let count = len.min(inner.trace.buffer.len());
+                inner.trace.buffer.drain(..count).collect()
+            };
+            return buffer.write_from(&data) as isize;
+        }
+        _ => return -EINVAL,
+    };
+    target.trace_mode.store(mode);
+    target.inner_exclusive_access().trace.buffer.clear();
+    0
+}
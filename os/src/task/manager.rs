@@ -1,5 +1,5 @@
 use super::TaskControlBlock;
-use crate::sync::UPSafeCell;
+use crate::sync::{IrqSafeMutex, LockClass};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -27,17 +27,17 @@ impl TaskManager {
 }
 
 lazy_static! {
-    /// 任务管理器
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// 任务管理器，所有hart共享同一个就绪队列。中断处理中唤醒任务时也会访问它，因此持有时关中断
+    pub static ref TASK_MANAGER: IrqSafeMutex<TaskManager> =
+        IrqSafeMutex::new_with_class(TaskManager::new(), LockClass::TaskManager);
 }
 
 /// 增加一个任务，将任务增加到队尾
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    TASK_MANAGER.lock().add(task);
 }
 
 /// 从队头取出一个任务来执行
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.lock().fetch()
 }
@@ -6,18 +6,23 @@ mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
+use crate::cmdline::cmdline;
 use crate::loader::get_app_data_by_name;
+use crate::sbi::shutdown;
+use crate::syscall::TraceMode;
 use alloc::sync::Arc;
 use lazy_static::*;
 use manager::fetch_task;
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
+use task::TaskStatus;
 
 pub use context::TaskContext;
 pub use manager::add_task;
 pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use task::{TaskControlBlock, TaskNesting};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_task, current_trace_mode, current_trap_cx, current_user_token, run_tasks, schedule,
+    take_current_task,
 };
 
 pub fn suspend_current_and_run_next() {
@@ -38,9 +43,48 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// 将当前任务置为阻塞状态并切换到下一个任务，
+/// 调用者需要事先把当前任务挂到某个等待队列上，否则它将永远不会被唤醒
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+
+    // ---- access current TCB exclusively
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    // ---- release current PCB
+
+    // 等待队列持有该任务的引用，这里不再放回就绪队列
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+/// 唤醒一个阻塞中的任务，将其放回就绪队列，
+/// 若任务已经被唤醒过（例如超时与futex唤醒同时发生）则什么也不做
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
 pub fn exit_current_and_run_next(exit_code: i32) {
     // take from Processor
     let task = take_current_task().unwrap();
+    // 初始进程退出后没有进程可以收养孤儿，关机。由init=usertests运行测试时以此结束，
+    // 退出码不为0时以失败为原因关机，使QEMU以非零的退出码结束
+    if Arc::ptr_eq(&task, &INITPROC) {
+        if exit_code == 0 {
+            info!("init exited with code {}, shutting down", exit_code);
+        } else {
+            error!("init exited with code {}, shutting down", exit_code);
+        }
+        shutdown(exit_code != 0);
+    }
     // **** access current TCB exclusively
     let mut inner = task.inner_exclusive_access();
     // Change status to Zombie
@@ -52,9 +96,11 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     // 把当前进程的所有子进程挂到initproc下面，建立父子关系
     // ++++++ access initproc TCB exclusively
     {
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
+        let mut initproc_inner = INITPROC.inner_exclusive_access_nested(TaskNesting::Initproc);
         for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+            child
+                .inner_exclusive_access_nested(TaskNesting::Child)
+                .parent = Some(Arc::downgrade(&INITPROC));
             initproc_inner.children.push(child.clone());
         }
     }
@@ -63,6 +109,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.children.clear(); // vec中的Arc引用计数也会-1
     // deallocate user space
     inner.memory_set.recycle_data_pages();
+    // 关闭所有打开的文件
+    inner.fd_table.clear();
     drop(inner);
     // **** release current PCB
     // drop task manually to maintain rc correctly
@@ -73,10 +121,17 @@ pub fn exit_current_and_run_next(exit_code: i32) {
 }
 
 lazy_static! {
-    /// 初始化 初始进程initproc 的进程控制块
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
-        get_app_data_by_name("initproc").unwrap()
-    ));
+    /// 初始化 初始进程initproc 的进程控制块，它默认为initproc，可以由命令行中的init=指定
+    pub static ref INITPROC: Arc<TaskControlBlock> = {
+        let init = cmdline().init;
+        let data = get_app_data_by_name(init)
+            .unwrap_or_else(|| panic!("init program {} not found", init));
+        let task = Arc::new(TaskControlBlock::new(data));
+        if cmdline().traced(init) {
+            task.trace_mode.store(TraceMode::Console);
+        }
+        task
+    };
 }
 
 pub fn add_initproc() {
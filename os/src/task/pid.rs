@@ -1,6 +1,7 @@
 use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
 use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::{LockClass, SpinMutex};
+use crate::syscall::errno::SysResult;
 use alloc::vec::Vec;
 use lazy_static::*;
 
@@ -39,8 +40,8 @@ impl PidAllocator {
 
 lazy_static! {
     /// pid分配器
-    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
-        unsafe { UPSafeCell::new(PidAllocator::new()) };
+    static ref PID_ALLOCATOR: SpinMutex<PidAllocator> =
+        SpinMutex::new_with_class(PidAllocator::new(), LockClass::PidAllocator);
 }
 
 pub struct PidHandle(pub usize);
@@ -48,12 +49,12 @@ pub struct PidHandle(pub usize);
 impl Drop for PidHandle {
     fn drop(&mut self) {
         //println!("drop pid {}", self.0);
-        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        PID_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
 pub fn pid_alloc() -> PidHandle {
-    PID_ALLOCATOR.exclusive_access().alloc()
+    PID_ALLOCATOR.lock().alloc()
 }
 
 /// Return (bottom, top) of a kernel stack in kernel space.
@@ -68,16 +69,17 @@ pub struct KernelStack {
 }
 
 impl KernelStack {
-    /// 已分配的进程标识符中对应生成一个内核栈 `KernelStack`
-    pub fn new(pid_handle: &PidHandle) -> Self {
+    /// 已分配的进程标识符中对应生成一个内核栈 `KernelStack`，
+    /// 分配不到物理页帧时返回ENOMEM
+    pub fn new(pid_handle: &PidHandle) -> SysResult<Self> {
         let pid = pid_handle.0;
         let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
+        KERNEL_SPACE.lock().insert_framed_area(
             kernel_stack_bottom.into(),
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid: pid_handle.0 }
+        )?;
+        Ok(KernelStack { pid: pid_handle.0 })
     }
     #[allow(unused)]
     /// 在内核栈压入T类型数据
@@ -103,7 +105,7 @@ impl Drop for KernelStack {
         let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
         let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
         KERNEL_SPACE
-            .exclusive_access()
+            .lock()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
     }
 }
@@ -1,12 +1,20 @@
 use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
-use crate::sync::UPSafeCell;
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use crate::sync::{
+    kernel_lock, kernel_unlock, restore_preempt, save_preempt, LockClass, UPSafeCell,
+};
+use crate::syscall::TraceMode;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
 use lazy_static::*;
+use riscv::register::sstatus;
 
-/// 处理器管理结构，包含：
+/// 处理器管理结构，每个hart一个，包含：
 /// 指向当前处理器上正在运行的进程的任务控制块的指针和
 /// idle控制流的任务上下文
 pub struct Processor {
@@ -41,17 +49,24 @@ impl Processor {
     }
 }
 
-// 单核CPU，仅单个Processor的全局实例
 lazy_static! {
-    /// 全局的处理器管理器，由于目前只支持单核，所以只有一个实例
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// 各hart的处理器管理器，以hartid为下标
+    static ref PROCESSORS: Vec<UPSafeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPSafeCell::new_with_class(Processor::new(), LockClass::Processor) })
+        .collect();
+}
+
+/// 当前hart的处理器管理器
+fn processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 /// idle控制流，
-/// 内核初始化完毕之后，会通过调用 `run_tasks` 函数来进入 idle 控制流
+/// 内核初始化完毕之后，每个hart都会通过调用 `run_tasks` 函数来进入自己的 idle 控制流，
+/// 调用时须持有大内核锁
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = processor().exclusive_access();
         // fetch_task从队头取出下一个任务
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
@@ -65,19 +80,38 @@ pub fn run_tasks() {
             // release processor manually
             drop(processor);
             unsafe {
+                // 其他hart可能修改过内核地址空间（如回收后又分配的内核栈），清掉快表中过时的映射
+                asm!("sfence.vma");
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+        } else {
+            // 所有任务都在阻塞或在其他hart上运行，释放大内核锁，打开中断等待定时器或设备将它们唤醒。
+            // 其他hart放回就绪队列的任务要等到本hart的下一次时钟中断才会被取走
+            drop(processor);
+            kernel_unlock();
+            unsafe {
+                sstatus::set_sie();
+                asm!("wfi");
+                sstatus::clear_sie();
+            }
+            kernel_lock();
         }
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    processor().exclusive_access().take_current()
 }
 
 /// 获得指向当前正在运行的任务的任务控制块的Arc指针，用Option包裹
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    processor().exclusive_access().current()
+}
+
+/// 当前任务的系统调用跟踪模式，不复制任务的Arc指针，也不借用它的inner
+pub fn current_trace_mode() -> TraceMode {
+    let processor = processor().exclusive_access();
+    processor.current.as_ref().unwrap().trace_mode.load()
 }
 
 /// 得到当前应用的token值
@@ -96,12 +130,15 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
 }
 
 /// 当一个应用用尽了时间片或主动yield，本函数使CPU切换到idle控制流。
-/// 需要传入即将被切换出去的任务的 task_cx_ptr
+/// 需要传入即将被切换出去的任务的 task_cx_ptr。
+/// 任务的抢占状态随任务保存，再次运行时恢复，那时它可能已经在另一个hart上
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
+    let preempt = save_preempt();
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
+    restore_preempt(preempt);
 }
@@ -2,12 +2,15 @@
 use super::TaskContext;
 use super::{pid_alloc, KernelStack, PidHandle};
 use crate::config::TRAP_CONTEXT;
+use crate::fs::{open_file, File, OpenFlags};
 use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::{LockClass, UPRefMut, UPSafeCell};
+use crate::syscall::errno::SysResult;
+use crate::syscall::{AtomicTraceMode, TraceMode, TraceState};
 use crate::trap::{trap_handler, TrapContext};
 use alloc::sync::{Arc, Weak};
+use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefMut;
 
 /// 任务控制块=pid+内核栈+可变的任务控制块内部数据，
 /// 内部数据包括trap页面对应的物理页号，任务上下文，执行状态等
@@ -16,6 +19,8 @@ pub struct TaskControlBlock {
     // pid和kernel_stack的位置不可变
     pub pid: PidHandle,
     pub kernel_stack: KernelStack,
+    /// 系统调用跟踪的模式，每次系统调用都要读取，因此不放在inner中
+    pub trace_mode: AtomicTraceMode,
     // mutable
     /// 对于运行过程中可能发生变化的数据，将其用UPSafeCell包装
     inner: UPSafeCell<TaskControlBlockInner>,
@@ -38,6 +43,10 @@ pub struct TaskControlBlockInner {
     pub children: Vec<Arc<TaskControlBlock>>,
     /// 返回值
     pub exit_code: i32,
+    /// 文件描述符表，下标即文件描述符
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// 系统调用跟踪的记录
+    pub trace: TraceState,
 }
 
 impl TaskControlBlockInner {
@@ -59,17 +68,43 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
+    /// 分配一个最小的空闲文件描述符
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+}
+
+/// 同时借用多个任务的inner时，后借用者相对于当前任务的嵌套层次。
+/// 须按层次递增的顺序借用：当前任务、initproc、子进程
+#[derive(Copy, Clone)]
+pub enum TaskNesting {
+    Initproc = 1,
+    Child = 2,
 }
 
 impl TaskControlBlock {
     /// 得到一个内层的TaskControlBlockInner的可变引用
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    #[track_caller]
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
-    /// 创建一个新进程控制块
+    /// 在已借用当前任务的inner时借用另一个任务的inner
+    #[track_caller]
+    pub fn inner_exclusive_access_nested(
+        &self,
+        nesting: TaskNesting,
+    ) -> UPRefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access_nested(nesting as u8)
+    }
+    /// 创建一个新进程控制块，只用于启动时创建初始进程，内存不足时panic
     pub fn new(elf_data: &[u8]) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data).unwrap();
         // 得到trap上下文所在的物理页号
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
@@ -77,23 +112,36 @@ impl TaskControlBlock {
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle).unwrap();
         let kernel_stack_top = kernel_stack.get_top();
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid_handle,
             kernel_stack,
+            trace_mode: AtomicTraceMode::new(TraceMode::Off),
             inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),// 构造任务的初始上下文，当第一次切换到此任务时，从trap_return开始执行
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                })
+                UPSafeCell::new_with_class(
+                    TaskControlBlockInner {
+                        trap_cx_ppn,
+                        base_size: user_sp,
+                        task_cx: TaskContext::goto_trap_return(kernel_stack_top), // 构造任务的初始上下文，当第一次切换到此任务时，从trap_return开始执行
+                        task_status: TaskStatus::Ready,
+                        memory_set,
+                        parent: None,
+                        children: Vec::new(),
+                        exit_code: 0,
+                        fd_table: vec![
+                            // 0 -> stdin
+                            Some(open_file("/dev/console", OpenFlags::RDONLY).unwrap()),
+                            // 1 -> stdout
+                            Some(open_file("/dev/console", OpenFlags::WRONLY).unwrap()),
+                            // 2 -> stderr
+                            Some(open_file("/dev/console", OpenFlags::WRONLY).unwrap()),
+                        ],
+                        trace: TraceState::default(),
+                    },
+                    LockClass::TaskInner,
+                )
             },
         };
         // prepare TrapContext in user space
@@ -102,16 +150,17 @@ impl TaskControlBlock {
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
+            KERNEL_SPACE.lock().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
         task_control_block
     }
-    /// exec系统调用，加载执行另一个ELF可执行文件
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// exec系统调用，加载执行另一个ELF可执行文件。
+    /// 内存不足时返回ENOMEM，原来的地址空间保持不变
+    pub fn exec(&self, elf_data: &[u8]) -> SysResult<()> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
@@ -128,50 +177,64 @@ impl TaskControlBlock {
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
+            KERNEL_SPACE.lock().token(),
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        Ok(())
         // **** release inner automatically
     }
-    /// fork系统调用
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    /// fork系统调用，内存不足时返回ENOMEM
+    pub fn fork(self: &Arc<TaskControlBlock>) -> SysResult<Arc<TaskControlBlock>> {
+        // 持有inner的借用时记下地址空间的布局，释放借用后再分配页帧并复制内容，这一过程可以被抢占。
+        // 只有父进程自己会解除映射，而它正在执行fork，因此源页帧在复制期间不会被回收
+        let layout = self.inner_exclusive_access().memory_set.layout();
+        // copy user space(include trap context)
+        let memory_set = MemorySet::from_layout(layout)?;
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack = KernelStack::new(&pid_handle)?;
         let kernel_stack_top = kernel_stack.get_top();
+        // 子进程继承父进程打开的文件
+        let fd_table = parent_inner.fd_table.clone();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
+            trace_mode: AtomicTraceMode::new(self.trace_mode.load()),
             inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                })
+                UPSafeCell::new_with_class(
+                    TaskControlBlockInner {
+                        trap_cx_ppn,
+                        base_size: parent_inner.base_size,
+                        task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                        task_status: TaskStatus::Ready,
+                        memory_set,
+                        parent: Some(Arc::downgrade(self)),
+                        children: Vec::new(),
+                        exit_code: 0,
+                        fd_table,
+                        trace: TraceState::default(),
+                    },
+                    LockClass::TaskInner,
+                )
             },
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
         // modify kernel_sp in trap_cx
         // **** access children PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        let trap_cx = task_control_block
+            .inner_exclusive_access_nested(TaskNesting::Child)
+            .get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Ok(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
@@ -185,5 +248,7 @@ impl TaskControlBlock {
 pub enum TaskStatus {
     Ready,
     Running,
+    /// 等待某个事件（如futex、定时器）而被挂起，不在就绪队列中
+    Blocked,
     Zombie,
 }
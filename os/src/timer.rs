@@ -1,18 +1,208 @@
-use crate::config::CLOCK_FREQ;
+use crate::drivers::rtc::GoldfishRtc;
+use crate::fdt::machine;
 use crate::sbi::set_timer;
+use crate::sync::{LockClass, UPSafeCell};
+use crate::syscall::errno::{SysError, SysResult};
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use core::fmt;
+use core::sync::atomic::{self, AtomicU64, AtomicUsize};
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+const SECS_PER_DAY: u64 = 86400;
+
+/// 与Linux兼容的时间表示，用于超时等参数的传递
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as usize,
+            tv_nsec: (ns % NSEC_PER_SEC) as usize,
+        }
+    }
+    /// 换算为毫秒，tv_nsec不小于1秒或结果溢出时返回EINVAL
+    pub fn to_ms(&self) -> SysResult<usize> {
+        if self.tv_nsec >= NSEC_PER_SEC as usize {
+            return Err(SysError::EINVAL);
+        }
+        self.tv_sec
+            .checked_mul(MSEC_PER_SEC)
+            .and_then(|ms| ms.checked_add(self.tv_nsec / 1_000_000))
+            .ok_or(SysError::EINVAL)
+    }
+    /// 把相对的超时时长换算为get_time_ms时钟上的到期时刻，出错的情况同to_ms
+    pub fn expire_ms(&self) -> SysResult<usize> {
+        get_time_ms()
+            .checked_add(self.to_ms()?)
+            .ok_or(SysError::EINVAL)
+    }
+}
+
+/// time寄存器的频率，由设备树给出
+static CLOCK_FREQ: AtomicUsize = AtomicUsize::new(0);
+/// 开机（time寄存器为0）时刻的墙上时间，即自1970年起的纳秒数，没有RTC时为0
+static BOOT_REALTIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 记录时钟频率，并从RTC读出开机时刻的墙上时间
+pub fn init() {
+    CLOCK_FREQ.store(machine().timebase_frequency, atomic::Ordering::Relaxed);
+    if let Some(rtc) = machine().rtc {
+        let rtc = unsafe { GoldfishRtc::new(rtc.base) };
+        let boot_ns = rtc.read_time_ns().saturating_sub(get_time_ns());
+        BOOT_REALTIME_NS.store(boot_ns, atomic::Ordering::Relaxed);
+        info!("rtc: {}", DateTime::now());
+    }
+}
+
+fn clock_freq() -> usize {
+    CLOCK_FREQ.load(atomic::Ordering::Relaxed)
+}
 
 pub fn get_time() -> usize {
     time::read()
 }
 
 pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+    time::read() / (clock_freq() / MSEC_PER_SEC)
+}
+
+/// 开机以来的纳秒数，即CLOCK_MONOTONIC。时钟频率确定之前（启动早期的日志）返回0
+pub fn get_time_ns() -> u64 {
+    match clock_freq() {
+        0 => 0,
+        freq => (time::read() as u128 * NSEC_PER_SEC as u128 / freq as u128) as u64,
+    }
+}
+
+/// 自1970-01-01 00:00:00 UTC以来的纳秒数，即CLOCK_REALTIME
+pub fn get_realtime_ns() -> u64 {
+    BOOT_REALTIME_NS.load(atomic::Ordering::Relaxed) + get_time_ns()
+}
+
+/// UTC日期与时间，用于在日志与/proc/driver/rtc中显示墙上时间
+pub struct DateTime {
+    pub year: u64,
+    pub month: u64,
+    pub day: u64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl DateTime {
+    /// 当前的墙上时间
+    pub fn now() -> Self {
+        Self::from_unix_secs(get_realtime_ns() / NSEC_PER_SEC)
+    }
+    /// 由自1970年起的秒数换算出公历日期
+    pub fn from_unix_secs(secs: u64) -> Self {
+        let days = secs / SECS_PER_DAY;
+        let rem = secs % SECS_PER_DAY;
+        // 以3月1日为一年的开始，闰日恰好落在年末，400年为一个周期
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z % 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Self {
+            year,
+            month,
+            day,
+            hour: rem / 3600,
+            minute: rem % 3600 / 60,
+            second: rem % 60,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
 }
 
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
-}
\ No newline at end of file
+    set_timer(get_time() + clock_freq() / TICKS_PER_SEC);
+}
+
+/// 定时器：在expire_ms时刻唤醒阻塞中的task
+pub struct TimerCondVar {
+    pub expire_ms: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        // BinaryHeap是大根堆，这里反转比较结果，使最早到期的定时器位于堆顶。
+        // 直接比较无符号数，很远的到期时刻也不会因取负溢出而排到前面
+        Some(other.expire_ms.cmp(&self.expire_ms))
+    }
+}
+
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap()
+    }
+}
+
+lazy_static! {
+    /// 按到期时间排序的定时器集合
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new_with_class(BinaryHeap::<TimerCondVar>::new(), LockClass::Timers) };
+}
+
+/// 添加一个定时器，到期后唤醒task
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar { expire_ms, task });
+}
+
+/// 删除task的所有定时器，用于task被提前唤醒的情况
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    let remaining: BinaryHeap<TimerCondVar> = timers
+        .drain()
+        .filter(|timer| !Arc::ptr_eq(&timer.task, task))
+        .collect();
+    *timers = remaining;
+}
+
+/// 唤醒所有已到期定时器对应的task，在时钟中断中调用
+pub fn check_timer() {
+    let current_ms = get_time_ms();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms <= current_ms {
+            wakeup_task(Arc::clone(&timer.task));
+            timers.pop();
+        } else {
+            break;
+        }
+    }
+}
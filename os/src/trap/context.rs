@@ -17,6 +17,8 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// 内核中trap handler入口点的虚拟地址，在应用初始化时由内核写入
     pub trap_handler: usize,
+    /// 任务所在hart的编号，每次返回用户态前由trap_return写入，trap进入内核时恢复到tp
+    pub kernel_tp: usize,
 }
 
 impl TrapContext {
@@ -47,8 +49,19 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            kernel_tp: 0,
         };
         cx.set_sp(sp);
         cx
     }
 }
+
+/// 内核态trap时__kernel_trap保存在当前内核栈上的现场
+#[repr(C)]
+pub struct KernelTrapContext {
+    /// 32个通用寄存器，x[2]为trap发生前的sp
+    pub x: [usize; 32],
+    pub sstatus: usize,
+    /// 被打断的指令地址，返回时从这里继续执行
+    pub sepc: usize,
+}
@@ -1,11 +1,20 @@
 mod context;
 
+use crate::backtrace::{print_user_backtrace, Symbol};
+use crate::cmdline::{cmdline, SchedPolicy};
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::drivers::plic::handle_external_interrupt;
+use crate::net;
+use crate::smp::hart_id;
+use crate::sync::{
+    holds_kernel_lock, irq_enter, irq_exit, kernel_lock, kernel_unlock, preempt_check,
+    set_need_resched,
+};
 use crate::syscall::syscall;
 use crate::task::{
     current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
@@ -21,8 +30,11 @@ pub fn init() {
 
 /// 设置内核状态下的trap_entry
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kernel_trap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__kernel_trap as usize, TrapMode::Direct);
     }
 }
 
@@ -40,9 +52,18 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// 设置sie.seie为1使S特权级外部中断不会被屏蔽
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
 #[no_mangle]
 /// trap处理函数
 pub fn trap_handler() -> ! {
+    // 等待其他hart离开内核
+    kernel_lock();
     // 设置内核状态下的trap_entry，在内核下不允许trap，直接panic
     set_kernel_trap_entry();
     // trap的原因是什么？
@@ -57,7 +78,10 @@ pub fn trap_handler() -> ! {
             // Trap返回之后，应用程序控制流应从ecall的下一条指令开始执行，于是cx.sepc+=4
             cx.sepc += 4;
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -68,23 +92,34 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+            let cx = current_trap_cx();
+            warn!(
+                "{:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
                 scause.cause(),
                 stval,
-                current_trap_cx().sepc,
+                cx.sepc,
             );
+            print_user_backtrace(current_user_token(), cx.sepc, cx.x[8]);
             // page fault exit code
             exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, kernel killed it.");
+            let cx = current_trap_cx();
+            warn!("IllegalInstruction in application, kernel killed it.");
+            print_user_backtrace(current_user_token(), cx.sepc, cx.x[8]);
             // illegal instruction exit code
             exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            check_timer();
+            net::on_timer();
+            if cmdline().sched == SchedPolicy::RoundRobin {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
         }
         _ => {
             panic!(
@@ -107,12 +142,15 @@ pub fn trap_return() -> ! {
     let trap_cx_ptr = TRAP_CONTEXT;
     // 得到应用的token
     let user_satp = current_user_token();
+    // 下次trap进入内核时tp恢复为本hart的编号，任务可能已经换了hart
+    current_trap_cx().kernel_tp = hart_id();
     extern "C" {
         fn __alltraps();
         fn __restore();
     }
     // 计算 __restore的虚拟地址 = TRAMPOLINE + restore相对于alltraps的偏移量
     let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
+    kernel_unlock();
     // 使用 fence.i 指令清空指令缓存 i-cache
     // 在内核中进行的一些操作可能导致一些原先存放某个应用代码的物理页帧如今用来存放数据或者是其他应用的代码
     // i-cache 中可能还保存着该物理页帧的错误快照,导致jr跳到错误的物理页帧
@@ -129,8 +167,72 @@ pub fn trap_return() -> ! {
 }
 
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    panic!("a trap {:?} from kernel!", scause::read().cause());
+/// 内核态trap的处理函数，由__kernel_trap保存现场后调用，返回后回到被打断处继续执行。
+/// 内核在idle等待时以及preemptible之中打开中断。
+/// 打断了任务时，时钟中断请求重新调度，中断返回前若可以抢占就切换到其他任务
+pub fn trap_from_kernel(cx: &mut KernelTrapContext) {
+    let scause = scause::read();
+    let interrupt = match scause.cause() {
+        Trap::Interrupt(interrupt) => interrupt,
+        Trap::Exception(exception) => kernel_fault(cx, exception, stval::read()),
+    };
+    // idle等待时没有持有大内核锁
+    let locked = holds_kernel_lock();
+    if !locked {
+        kernel_lock();
+    }
+    irq_enter();
+    match interrupt {
+        Interrupt::SupervisorExternal => {
+            handle_external_interrupt();
+        }
+        Interrupt::SupervisorTimer => {
+            set_next_trigger();
+            check_timer();
+            net::on_timer();
+            if locked && cmdline().sched == SchedPolicy::RoundRobin {
+                set_need_resched();
+            }
+        }
+        _ => {
+            panic!(
+                "unsupported interrupt {:?} in kernel, sepc = {:#x}!",
+                interrupt, cx.sepc
+            );
+        }
+    }
+    irq_exit();
+    if locked {
+        preempt_check();
+    } else {
+        kernel_unlock();
+    }
+}
+
+/// 内核自身触发了异常，报告出错的位置后panic，由panic打印调用链。
+/// 调用链经过__kernel_trap回到被打断的函数的调用者
+fn kernel_fault(cx: &KernelTrapContext, exception: Exception, stval: usize) -> ! {
+    match exception {
+        Exception::LoadFault
+        | Exception::StoreFault
+        | Exception::InstructionFault
+        | Exception::LoadPageFault
+        | Exception::StorePageFault
+        | Exception::InstructionPageFault => println!(
+            "[kernel] {:?} in kernel, bad addr = {:#x}, sepc = {}",
+            exception,
+            stval,
+            Symbol(cx.sepc)
+        ),
+        _ => println!(
+            "[kernel] {:?} in kernel, stval = {:#x}, sepc = {}",
+            exception,
+            stval,
+            Symbol(cx.sepc)
+        ),
+    }
+    println!("[kernel] ra = {}, sp = {:#x}", Symbol(cx.x[1]), cx.x[2]);
+    panic!("fatal {:?} in kernel", exception);
 }
 
-pub use context::TrapContext;
\ No newline at end of file
+pub use context::{KernelTrapContext, TrapContext};
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{clock_gettime, close, open, read, TimeSpec, CLOCK_MONOTONIC, O_RDONLY};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// 取出/proc/driver/rtc中“name\t: a:b:c”一行的三个数，sep为数之间的分隔符
+fn field(content: &str, name: &str, sep: char) -> Option<[usize; 3]> {
+    let line = content.lines().find(|line| line.starts_with(name))?;
+    let mut parts = line.split(": ").nth(1)?.split(sep);
+    let mut values = [0; 3];
+    for value in values.iter_mut() {
+        *value = parts.next()?.parse().ok()?;
+    }
+    Some(values)
+}
+
+/// 公历日期是星期几，0为星期日
+fn weekday(year: usize, month: usize, day: usize) -> usize {
+    const OFFSETS: [usize; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let year = if month < 3 { year - 1 } else { year };
+    (year + year / 4 - year / 100 + year / 400 + OFFSETS[month - 1] + day) % 7
+}
+
+/// 以date命令的默认格式打印内核给出的当前UTC时间，以及开机以来的时间
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/proc/driver/rtc\0", O_RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 128];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    assert!(len > 0);
+    let content = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    let [hour, minute, second] = field(content, "rtc_time", ':').unwrap();
+    let [year, month, day] = field(content, "rtc_date", '-').unwrap();
+    println!(
+        "{} {} {:2} {:02}:{:02}:{:02} UTC {}",
+        WEEKDAYS[weekday(year, month, day)],
+        MONTHS[month - 1],
+        day,
+        hour,
+        minute,
+        second,
+        year
+    );
+    let mut uptime = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut uptime), 0);
+    println!("up {}.{:09} s", uptime.tv_sec, uptime.tv_nsec);
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ioctl, open, read, write, WinSize, EBADF, EEXIST, ENOENT, ENOTTY, O_CREAT, O_EXCL,
+    O_RDONLY, O_RDWR, O_WRONLY, TIOCGWINSZ,
+};
+
+const STDOUT: usize = 1;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // /dev/null丢弃写入的数据，读出时立即返回文件结尾
+    let null = open("/dev/null\0", O_RDWR);
+    assert!(null >= 0);
+    let null = null as usize;
+    assert_eq!(write(null, b"discarded"), 9);
+    let mut buf = [0xffu8; 32];
+    assert_eq!(read(null, &mut buf), 0);
+    assert_eq!(ioctl(null, TIOCGWINSZ, 0), -ENOTTY);
+    close(null);
+
+    let zero = open("/dev/zero\0", O_RDONLY) as usize;
+    assert_eq!(read(zero, &mut buf), buf.len() as isize);
+    assert!(buf.iter().all(|&b| b == 0));
+    // 只读打开的设备不能写
+    assert_eq!(write(zero, b"x"), -EBADF);
+    close(zero);
+
+    let urandom = open("dev/urandom\0", O_RDONLY) as usize;
+    assert_eq!(read(urandom, &mut buf), buf.len() as isize);
+    assert!(buf.iter().any(|&b| b != 0));
+    close(urandom);
+    println!("/dev/null, /dev/zero and /dev/urandom ok.");
+
+    assert_eq!(open("/dev/nonexistent\0", O_RDONLY), -ENOENT);
+    assert_eq!(open("/etc/passwd\0", O_RDONLY), -ENOENT);
+    assert_eq!(open("/dev/null\0", O_CREAT | O_EXCL | O_WRONLY), -EEXIST);
+
+    // 标准输出就是打开的/dev/console
+    let mut winsize = WinSize::default();
+    assert_eq!(
+        ioctl(STDOUT, TIOCGWINSZ, &mut winsize as *mut _ as usize),
+        0
+    );
+    assert!(winsize.ws_row > 0 && winsize.ws_col > 0);
+    let console = open("/dev/console\0", O_WRONLY);
+    assert!(console >= 0);
+    let msg = b"written through /dev/console\n";
+    assert_eq!(write(console as usize, msg), msg.len() as isize);
+    close(console as usize);
+    println!("dev_test passed!");
+    0
+}
@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec;
+use user_lib::{syslog, write, SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_SIZE_BUFFER};
+
+/// 打印内核日志缓冲区中的全部内容
+#[no_mangle]
+pub fn main() -> i32 {
+    let size = syslog(SYSLOG_ACTION_SIZE_BUFFER, &mut []);
+    assert!(size > 0);
+    let mut buf = vec![0u8; size as usize];
+    let len = syslog(SYSLOG_ACTION_READ_ALL, &mut buf);
+    assert!(len >= 0);
+    write(1, &buf[..len as usize]);
+    0
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use core::slice;
+use core::str;
+use user_lib::{
+    clock_gettime, exec, mmap, munmap, read, syslog, write, TimeSpec, CLOCK_MONOTONIC, EFAULT,
+    ENAMETOOLONG, ENOENT, ENOSYS, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+    SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_READ_CLEAR,
+};
+
+/// 用户程序从0x10000开始链接，更低的地址没有映射
+const UNMAPPED: usize = 0x10;
+const PAGE_SIZE: usize = 4096;
+/// 跳板所在的最高一页，已映射但用户态不可访问
+const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
+/// 内核不支持的系统调用号
+const SYSCALL_UNKNOWN: usize = 9999;
+
+fn syscall_unknown() -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") 0isize => ret,
+            in("x17") SYSCALL_UNKNOWN
+        );
+    }
+    ret
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 无效的用户指针应当得到-EFAULT，而不是让内核崩溃
+    let unmapped = unsafe { slice::from_raw_parts_mut(UNMAPPED as *mut u8, 8) };
+    assert_eq!(write(1, unmapped), -EFAULT);
+    assert_eq!(read(0, unmapped), -EFAULT);
+    let kernel = unsafe { slice::from_raw_parts_mut(TRAMPOLINE as *mut u8, 8) };
+    assert_eq!(write(1, kernel), -EFAULT);
+    let path = unsafe { str::from_utf8_unchecked(slice::from_raw_parts(UNMAPPED as *const u8, 1)) };
+    assert_eq!(exec(path), -EFAULT);
+    // 不存在的应用返回-ENOENT
+    assert_eq!(exec("no_such_app\0"), -ENOENT);
+    // 代码段不可写，内核不能替用户写入
+    let text = unsafe { slice::from_raw_parts_mut(main as usize as *mut u8, 8) };
+    assert_eq!(read(0, text), -EFAULT);
+    println!("bad pointers ok.");
+
+    // 跨越页边界的结构体也能正确读写
+    let len = 3 * PAGE_SIZE;
+    let addr = mmap(
+        0,
+        len,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let addr = addr as usize;
+    let straddle = unsafe { &mut *((addr + PAGE_SIZE - 8) as *mut TimeSpec) };
+    let mut aligned = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, straddle), 0);
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut aligned), 0);
+    assert!(straddle.tv_nsec < 1_000_000_000);
+    assert!(aligned.tv_sec - straddle.tv_sec <= 1);
+    println!("page crossing ok.");
+
+    // 过长的字符串
+    let buf = unsafe { slice::from_raw_parts_mut(addr as *mut u8, len) };
+    buf.fill(b'a');
+    buf[len - 1] = 0;
+    let path = unsafe { str::from_utf8_unchecked(buf) };
+    assert_eq!(exec(path), -ENAMETOOLONG);
+    assert_eq!(munmap(addr, len), 0);
+    println!("long string ok.");
+
+    // 未知的系统调用返回-ENOSYS
+    assert_eq!(syscall_unknown(), -ENOSYS);
+
+    // 内核为上面的未知系统调用记录了一条日志，读取失败时不应被清空
+    let unmapped = unsafe { slice::from_raw_parts_mut(UNMAPPED as *mut u8, 64) };
+    assert_eq!(syslog(SYSLOG_ACTION_READ_CLEAR, unmapped), -EFAULT);
+    let mut log = [0u8; 64];
+    assert!(syslog(SYSLOG_ACTION_READ_ALL, &mut log) > 0);
+    // READ_CLEAR只移除返回的字节，其余日志仍可读到
+    assert_eq!(syslog(SYSLOG_ACTION_READ_CLEAR, &mut log[..1]), 1);
+    assert!(syslog(SYSLOG_ACTION_READ_ALL, &mut log) > 0);
+    println!("errno_test passed!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::sync::Mutex;
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, get_time, shmat, shmdt, shmget, waitpid, yield_, EAGAIN,
+    ETIMEDOUT, IPC_CREAT, IPC_PRIVATE,
+};
+
+static MUTEX: Mutex = Mutex::new();
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 无竞争时加锁解锁不进入内核
+    MUTEX.lock();
+    assert!(!MUTEX.try_lock());
+    MUTEX.unlock();
+    assert!(MUTEX.try_lock());
+    MUTEX.unlock();
+    println!("uncontended lock/unlock ok.");
+
+    let futex = AtomicU32::new(1);
+    // 值不匹配时立即返回
+    assert_eq!(futex_wait(&futex, 0, None), -EAGAIN);
+    // 没有等待者时唤醒数目为0
+    assert_eq!(futex_wake(&futex, 1), 0);
+    // 超时等待
+    let start = get_time();
+    assert_eq!(futex_wait(&futex, 1, Some(100)), -ETIMEDOUT);
+    let elapsed = get_time() - start;
+    assert!(elapsed >= 100);
+    println!("futex wait timed out after {} msecs.", elapsed);
+    futex.store(0, Ordering::Relaxed);
+
+    // 阻塞在共享内存中futex上的子进程被父进程唤醒
+    let shmid = shmget(IPC_PRIVATE, 4096, IPC_CREAT);
+    assert!(shmid >= 0);
+    let addr = shmat(shmid as usize, 0, 0);
+    assert!(addr > 0);
+    // 共享内存段初始为全0，futex的值始终为0，子进程一定会阻塞
+    let futex = unsafe { &*(addr as *const AtomicU32) };
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(futex_wait(futex, 0, None), 0);
+        exit(0);
+    }
+    // 子进程进入FUTEX_WAIT之前唤醒不到任何任务，重试直到唤醒了它
+    while futex_wake(futex, 1) == 0 {
+        yield_();
+    }
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(shmdt(addr as usize), 0);
+    println!("futex wake ok.");
+    println!("futex_test passed!");
+    0
+}
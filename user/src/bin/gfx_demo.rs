@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::gfx::{
+    rgb, Framebuffer, BLACK, BLUE, CYAN, GLYPH_HEIGHT, GLYPH_WIDTH, GREEN, MAGENTA, RED, WHITE,
+    YELLOW,
+};
+
+const COLORS: [u32; 6] = [RED, YELLOW, GREEN, CYAN, BLUE, MAGENTA];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fb = match Framebuffer::open() {
+        Ok(fb) => fb,
+        Err(err) => {
+            println!("gfx_demo: cannot open /dev/fb0: {}, run with GPU=on", err);
+            return -1;
+        }
+    };
+    let (width, height) = (fb.width(), fb.height());
+
+    // 上半部分为纵向渐变的背景
+    for y in 0..height {
+        let level = (y * 255 / height) as u8;
+        fb.fill_rect(0, y, width, 1, rgb(0, level / 2, level));
+    }
+    // 下方一排色块
+    let block = width / COLORS.len();
+    for (i, &color) in COLORS.iter().enumerate() {
+        fb.fill_rect(i * block, height - block / 2, block, block / 2, color);
+    }
+    // 标题中的每个字母使用不同的颜色
+    let scale = (width / 240).max(1);
+    let title = "Hello RustCore!";
+    let mut x = width.saturating_sub(title.len() * GLYPH_WIDTH * scale) / 2;
+    let y = height / 3;
+    for (i, ch) in title.chars().enumerate() {
+        fb.draw_char(x + scale, y + scale, ch, BLACK, None, scale);
+        fb.draw_char(x, y, ch, COLORS[i % COLORS.len()], None, scale);
+        x += GLYPH_WIDTH * scale;
+    }
+    let info = format!("fb0 {}x{}", width, height);
+    fb.draw_text(8, 8, &info, WHITE, Some(BLACK), 1);
+    fb.draw_text(
+        8,
+        8 + GLYPH_HEIGHT * 2,
+        " !\"#$%&'()*+,-./0123456789:;<=>?@\nABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`\nabcdefghijklmnopqrstuvwxyz{|}~",
+        WHITE,
+        None,
+        1,
+    );
+    fb.flush();
+    println!("gfx_demo: drew a {}x{} frame", width, height);
+    0
+}
@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{exec, fork, wait, yield_, ECHILD};
 
 #[no_mangle]
 fn main() -> i32 {
@@ -16,7 +16,7 @@ fn main() -> i32 {
             let mut exit_code: i32 = 0;
             // 等待任意一个子进程退出
             let pid = wait(&mut exit_code);
-            if pid == -1 {
+            if pid == -ECHILD {
                 yield_();
                 continue;
             }
@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use core::mem::size_of;
+use core::slice;
+use core::str;
+use user_lib::{
+    close, eviocgname, ioctl, open, ppoll, read, InputEvent, PollFd, EV_ABS, EV_KEY, EV_REL,
+    EV_SYN, O_RDONLY, POLLIN,
+};
+
+const MAX_DEVICES: usize = 4;
+const KEY_ESC: u16 = 1;
+
+/// 按键码对应的字符，只列出美式键盘上的字母、数字与空格
+fn key_char(code: u16) -> Option<char> {
+    const ROWS: [(u16, &str); 4] = [
+        (2, "1234567890"),
+        (16, "qwertyuiop"),
+        (30, "asdfghjkl"),
+        (44, "zxcvbnm"),
+    ];
+    if code == 57 {
+        return Some(' ');
+    }
+    ROWS.iter().find_map(|&(first, keys)| {
+        let idx = code.checked_sub(first)? as usize;
+        keys.as_bytes().get(idx).map(|&byte| byte as char)
+    })
+}
+
+fn axis_name(event_type: u16, code: u16) -> &'static str {
+    match (event_type, code) {
+        (EV_REL, 0) | (EV_ABS, 0) => "x",
+        (EV_REL, 1) | (EV_ABS, 1) => "y",
+        (EV_REL, 8) => "wheel",
+        _ => "?",
+    }
+}
+
+fn print_event(device: usize, event: &InputEvent) {
+    let time = event.sec % 1000 * 1000 + event.usec / 1000;
+    match event.event_type {
+        EV_SYN => {}
+        EV_KEY => {
+            let action = match event.value {
+                0 => "up",
+                1 => "down",
+                _ => "repeat",
+            };
+            match key_char(event.code) {
+                Some(ch) => println!(
+                    "[{}ms] event{}: key {} '{}' {}",
+                    time, device, event.code, ch, action
+                ),
+                None => println!(
+                    "[{}ms] event{}: key {} {}",
+                    time, device, event.code, action
+                ),
+            }
+        }
+        EV_REL | EV_ABS => println!(
+            "[{}ms] event{}: {} {} {}",
+            time,
+            device,
+            if event.event_type == EV_REL {
+                "rel"
+            } else {
+                "abs"
+            },
+            axis_name(event.event_type, event.code),
+            event.value
+        ),
+        other => println!(
+            "[{}ms] event{}: type {} code {} value {}",
+            time, device, other, event.code, event.value
+        ),
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [PollFd::default(); MAX_DEVICES];
+    let mut count = 0;
+    for i in 0..MAX_DEVICES {
+        let path = format!("/dev/input/event{}\0", i);
+        let fd = open(&path, O_RDONLY);
+        if fd < 0 {
+            break;
+        }
+        let mut name = [0u8; 64];
+        let len = ioctl(
+            fd as usize,
+            eviocgname(name.len()),
+            name.as_mut_ptr() as usize,
+        );
+        let name = str::from_utf8(&name[..(len.max(1) - 1) as usize]).unwrap_or("?");
+        println!("event{}: {}", i, name);
+        fds[count] = PollFd::new(fd as usize, POLLIN);
+        count += 1;
+    }
+    if count == 0 {
+        println!("input_demo: no input devices, run with INPUT=on");
+        return -1;
+    }
+    println!("input_demo: press Esc to quit");
+
+    let mut events = [InputEvent::default(); 8];
+    'outer: loop {
+        ppoll(&mut fds[..count], None);
+        for (device, pollfd) in fds[..count].iter().enumerate() {
+            if pollfd.revents & POLLIN == 0 {
+                continue;
+            }
+            let buf = unsafe {
+                slice::from_raw_parts_mut(
+                    events.as_mut_ptr() as *mut u8,
+                    events.len() * size_of::<InputEvent>(),
+                )
+            };
+            let len = read(pollfd.fd as usize, buf);
+            if len < 0 {
+                println!("input_demo: read error {}", len);
+                break 'outer;
+            }
+            for event in &events[..len as usize / size_of::<InputEvent>()] {
+                print_event(device, event);
+                if event.event_type == EV_KEY && event.code == KEY_ESC && event.value == 0 {
+                    break 'outer;
+                }
+            }
+        }
+    }
+    for pollfd in &fds[..count] {
+        close(pollfd.fd as usize);
+    }
+    0
+}
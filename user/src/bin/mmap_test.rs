@@ -0,0 +1,171 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::slice;
+use user_lib::gfx::{rgb, Framebuffer, BLUE, WHITE};
+use user_lib::{
+    close, exec, exit, fork, mmap, munmap, open, waitpid, EINVAL, ENOENT, ENOMEM, MAP_ANONYMOUS,
+    MAP_PRIVATE, MAP_SHARED, O_RDWR, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = 3 * PAGE_SIZE;
+/// trap上下文所在的页，紧邻最高的跳板页
+const TRAP_CONTEXT: usize = usize::MAX - 2 * PAGE_SIZE + 1;
+/// 用户程序的代码段从0x10000开始
+const TEXT_START: usize = 0x10000;
+
+fn anonymous() {
+    let addr = mmap(
+        0,
+        LEN,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let buf = unsafe { slice::from_raw_parts_mut(addr as *mut u8, LEN) };
+    assert!(buf.iter().all(|&byte| byte == 0));
+    buf.fill(0x5a);
+
+    // 私有映射在fork后各自独立
+    let pid = fork();
+    if pid == 0 {
+        assert!(buf.iter().all(|&byte| byte == 0x5a));
+        buf.fill(0xa5);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert!(buf.iter().all(|&byte| byte == 0x5a));
+
+    assert_eq!(munmap(addr as usize, LEN - 4096), -EINVAL);
+    assert_eq!(munmap(addr as usize, LEN), 0);
+    assert_eq!(munmap(addr as usize, LEN), -EINVAL);
+    assert_eq!(
+        mmap(0, 0, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0),
+        -EINVAL
+    );
+    assert_eq!(
+        mmap(0, LEN, PROT_READ, MAP_SHARED | MAP_ANONYMOUS, 0, 0),
+        -EINVAL
+    );
+    println!("anonymous mmap ok.");
+}
+
+fn kernel_areas() {
+    // 内核建立的逻辑段不能被munmap解除，不论长度是否恰好相符
+    assert_eq!(munmap(TRAP_CONTEXT, PAGE_SIZE), -EINVAL);
+    for pages in 1..=64 {
+        assert_eq!(munmap(TEXT_START, pages * PAGE_SIZE), -EINVAL);
+    }
+    // 超过物理内存的映射失败，而不是耗尽内核的页帧
+    assert_eq!(
+        mmap(
+            0,
+            1 << 36,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            0,
+            0
+        ),
+        -ENOMEM
+    );
+    println!("kernel areas and oversized mmap ok.");
+}
+
+/// 用匿名映射占满所有空闲的物理页帧，返回各个映射的(地址, 长度)与映射的个数
+fn exhaust(areas: &mut [(usize, usize)]) -> usize {
+    let mut count = 0;
+    let mut len = 1 << 30;
+    while len >= PAGE_SIZE && count < areas.len() {
+        let addr = mmap(
+            0,
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            0,
+            0,
+        );
+        if addr < 0 {
+            assert_eq!(addr, -ENOMEM);
+            len /= 2;
+        } else {
+            areas[count] = (addr as usize, len);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn out_of_memory() {
+    let mut areas = [(0, 0); 64];
+    let count = exhaust(&mut areas);
+    // 没有空闲的页帧时fork与exec失败，而不是让内核panic
+    assert_eq!(fork(), -ENOMEM);
+    assert_eq!(exec("hello_world\0"), -ENOMEM);
+    for &(addr, len) in areas[..count].iter() {
+        assert_eq!(munmap(addr, len), 0);
+    }
+    // 释放之后恢复正常
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    println!("out of memory ok.");
+}
+
+fn framebuffer() {
+    let mut fb = match Framebuffer::open() {
+        Ok(fb) => fb,
+        Err(err) => {
+            assert_eq!(err, -ENOENT);
+            println!("no /dev/fb0, framebuffer test skipped.");
+            return;
+        }
+    };
+    let (width, height) = (fb.width(), fb.height());
+    println!("fb0: {}x{}", width, height);
+    fb.clear(BLUE);
+    fb.fill_rect(
+        width / 4,
+        height / 4,
+        width / 2,
+        height / 2,
+        rgb(255, 128, 0),
+    );
+    fb.draw_text(8, 8, "mmap_test", WHITE, None, 2);
+    assert_eq!(fb.get_pixel(0, height - 1), Some(BLUE));
+    assert_eq!(fb.get_pixel(width / 2, height / 2), Some(rgb(255, 128, 0)));
+    assert_eq!(fb.get_pixel(width, 0), None);
+    assert_eq!(fb.flush(), 0);
+    assert_eq!(fb.flush_rect(width - 10, height - 10, 100, 100), 0);
+
+    // 越过帧缓冲末尾的映射被拒绝
+    let fd = open("/dev/fb0\0", O_RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let len = width * height * 4;
+    let offset = (len + 4095) / 4096 * 4096;
+    assert_eq!(mmap(0, 4096, PROT_READ, MAP_SHARED, fd, offset), -EINVAL);
+    assert_eq!(mmap(0, 4096, PROT_READ, MAP_PRIVATE, fd, 0), -EINVAL);
+    close(fd);
+    println!("framebuffer mmap ok.");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    anonymous();
+    kernel_areas();
+    out_of_memory();
+    framebuffer();
+    println!("mmap_test passed!");
+    0
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, mq_close, mq_getattr, mq_open, mq_receive, mq_receive_msg, mq_send,
+    mq_send_msg, mq_timedreceive, mq_unlink, wait, MqAttr, EAGAIN, EEXIST, EFAULT, EMSGSIZE,
+    ENOENT, ETIMEDOUT, O_CREAT, O_EXCL, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY,
+};
+
+const QUEUE_NAME: &str = "/mq_demo\0";
+const MAXMSG: usize = 4;
+const JOBS: usize = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Job {
+    id: usize,
+    /// 为0时表示生产者已经发送完毕
+    value: usize,
+}
+
+fn producer() -> i32 {
+    let mq = mq_open(QUEUE_NAME, O_WRONLY, None);
+    assert!(mq >= 0);
+    let mq = mq as usize;
+    for id in 0..JOBS {
+        // 奇数号任务的优先级更高，队列满时阻塞等待消费者
+        let prio = (id % 2) as u32 + 1;
+        assert_eq!(mq_send_msg(mq, &Job { id, value: id + 1 }, prio), 0);
+    }
+    // 优先级最低的结束消息一定最后被取出
+    assert_eq!(mq_send_msg(mq, &Job { id: JOBS, value: 0 }, 0), 0);
+    mq_close(mq);
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mq_open(QUEUE_NAME, O_RDONLY, None), -ENOENT);
+    let attr = MqAttr::new(MAXMSG, core::mem::size_of::<Job>());
+    let mq = mq_open(QUEUE_NAME, O_RDWR | O_CREAT | O_EXCL, Some(&attr));
+    assert!(mq >= 0);
+    let mq = mq as usize;
+    assert_eq!(
+        mq_open(QUEUE_NAME, O_RDWR | O_CREAT | O_EXCL, Some(&attr)),
+        -EEXIST
+    );
+
+    // 空队列上的非阻塞接收与限时接收
+    let nb = mq_open(QUEUE_NAME, O_RDONLY | O_NONBLOCK, None);
+    assert!(nb >= 0);
+    let mut buf = [0u8; core::mem::size_of::<Job>()];
+    assert_eq!(mq_receive(nb as usize, &mut buf, None), -EAGAIN);
+    assert_eq!(mq_receive(nb as usize, &mut buf[..1], None), -EMSGSIZE);
+    mq_close(nb as usize);
+    let start = get_time();
+    assert_eq!(
+        mq_timedreceive(mq, &mut buf, None, start as usize + 100),
+        -ETIMEDOUT
+    );
+    assert!(get_time() - start >= 100);
+    println!("nonblocking and timed receive ok.");
+
+    // 超长的消息在读取之前就被拒绝
+    let long = [0u8; core::mem::size_of::<Job>() + 1];
+    assert_eq!(mq_send(mq, &long, 0), -EMSGSIZE);
+    let huge = unsafe { core::slice::from_raw_parts(0x10 as *const u8, 1 << 30) };
+    assert_eq!(mq_send(mq, huge, 0), -EMSGSIZE);
+    println!("oversized send ok.");
+
+    // msg_prio不可写时接收失败，消息仍留在队列中
+    assert_eq!(mq_send_msg(mq, &Job { id: 0, value: 1 }, 3), 0);
+    let bad_prio = unsafe { &mut *(0x10 as *mut u32) };
+    assert_eq!(mq_receive(mq, &mut buf, Some(bad_prio)), -EFAULT);
+    let mut prio = 0u32;
+    let job: Job = mq_receive_msg(mq, &mut buf, Some(&mut prio)).unwrap();
+    assert_eq!((job.id, job.value, prio), (0, 1, 3));
+    println!("bad msg_prio ok.");
+
+    if fork() == 0 {
+        exit(producer());
+    }
+    let mut received = 0;
+    let mut sum = 0;
+    loop {
+        let mut prio = 0u32;
+        let job: Job = mq_receive_msg(mq, &mut buf, Some(&mut prio)).unwrap();
+        let mut attr = MqAttr::default();
+        mq_getattr(mq, &mut attr);
+        assert!(attr.mq_curmsgs < MAXMSG);
+        if job.value == 0 {
+            assert_eq!(prio, 0);
+            break;
+        }
+        assert_eq!(prio as usize, job.id % 2 + 1);
+        received += 1;
+        sum += job.value;
+    }
+    assert_eq!(received, JOBS);
+    assert_eq!(sum, JOBS * (JOBS + 1) / 2);
+    let mut exit_code = 0;
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 0);
+    println!("producer/consumer ok, {} jobs received.", received);
+
+    mq_close(mq);
+    assert_eq!(mq_unlink(QUEUE_NAME), 0);
+    assert_eq!(mq_unlink(QUEUE_NAME), -ENOENT);
+    println!("mq_demo passed!");
+    0
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, eventfd, eventfd_read, eventfd_write, exit, fork, get_time, ppoll, sleep, wait, PollFd,
+    EAGAIN, EFD_NONBLOCK, EFD_SEMAPHORE, EINVAL, POLLIN, POLLNVAL, POLLOUT,
+};
+
+const STDIN: usize = 0;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 计数器的累加与读出
+    let efd = eventfd(0, EFD_NONBLOCK);
+    assert!(efd >= 0);
+    let efd = efd as usize;
+    let mut value = 0u64;
+    assert_eq!(eventfd_read(efd, &mut value), -EAGAIN);
+    assert_eq!(eventfd_write(efd, 3), 0);
+    assert_eq!(eventfd_write(efd, 4), 0);
+    assert_eq!(eventfd_read(efd, &mut value), 0);
+    assert_eq!(value, 7);
+    close(efd);
+
+    // 信号量模式每次只取出1
+    let sem = eventfd(2, EFD_SEMAPHORE | EFD_NONBLOCK) as usize;
+    assert_eq!(eventfd_read(sem, &mut value), 0);
+    assert_eq!(value, 1);
+    assert_eq!(eventfd_read(sem, &mut value), 0);
+    assert_eq!(eventfd_read(sem, &mut value), -EAGAIN);
+    close(sem);
+    println!("eventfd counter ok.");
+
+    // 超时与无效的描述符
+    let efd = eventfd(0, 0) as usize;
+    let mut fds = [PollFd::new(efd, POLLIN)];
+    let start = get_time();
+    assert_eq!(ppoll(&mut fds, Some(100)), 0);
+    assert!(get_time() - start >= 100);
+    assert_eq!(fds[0].revents, 0);
+    let mut fds = [PollFd::new(efd, POLLIN | POLLOUT), PollFd::new(99, POLLIN)];
+    assert_eq!(ppoll(&mut fds, Some(0)), 2);
+    assert_eq!(fds[0].revents, POLLOUT);
+    assert_eq!(fds[1].revents, POLLNVAL);
+    // 过多的描述符在访问用户内存之前就被拒绝
+    let huge = unsafe { core::slice::from_raw_parts_mut(0x10 as *mut PollFd, 1 << 20) };
+    assert_eq!(ppoll(huge, Some(0)), -EINVAL);
+    println!("ppoll timeout ok.");
+
+    // 同时等待控制台输入与子进程的通知
+    if fork() == 0 {
+        sleep(50);
+        assert_eq!(eventfd_write(efd, 1), 0);
+        exit(0);
+    }
+    let mut fds = [PollFd::new(STDIN, POLLIN), PollFd::new(efd, POLLIN)];
+    assert!(ppoll(&mut fds, None) >= 1);
+    assert_eq!(fds[1].revents, POLLIN);
+    assert_eq!(eventfd_read(efd, &mut value), 0);
+    assert_eq!(value, 1);
+    let mut exit_code = 0;
+    assert!(wait(&mut exit_code) > 0);
+    println!("woken by child through eventfd ok.");
+
+    // 阻塞的read由另一个进程的write唤醒
+    if fork() == 0 {
+        assert_eq!(eventfd_read(efd, &mut value), 0);
+        exit(value as i32);
+    }
+    sleep(20);
+    assert_eq!(eventfd_write(efd, 42), 0);
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 42);
+    close(efd);
+    println!("poll_test passed!");
+    0
+}
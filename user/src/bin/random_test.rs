@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, getrandom, open, read, EINVAL, GRND_NONBLOCK, GRND_RANDOM, O_RDONLY};
+
+const SAMPLE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut a = [0u8; 64];
+    let mut b = [0u8; 64];
+    assert_eq!(getrandom(&mut a, 0), 64);
+    assert_eq!(getrandom(&mut b, GRND_NONBLOCK | GRND_RANDOM), 64);
+    assert_ne!(a, b);
+    assert_eq!(getrandom(&mut a, 0x80), -EINVAL);
+    assert_eq!(getrandom(&mut a[..0], 0), 0);
+    println!("getrandom ok.");
+
+    // 随机设备与getrandom读取同一个生成器
+    let fd = open("/dev/random\0", O_RDONLY);
+    assert!(fd >= 0);
+    assert_eq!(read(fd as usize, &mut a), 64);
+    assert_ne!(a, b);
+    close(fd as usize);
+    println!("/dev/random ok.");
+
+    // 粗略检查输出的分布：每一位为1的比例应接近一半
+    let mut sample = [0u8; SAMPLE_SIZE];
+    assert_eq!(getrandom(&mut sample, 0), SAMPLE_SIZE as isize);
+    let ones: u32 = sample.iter().map(|byte| byte.count_ones()).sum();
+    let bits = (SAMPLE_SIZE * 8) as u32;
+    assert!(ones > bits * 48 / 100 && ones < bits * 52 / 100);
+    let zero_bytes = sample.iter().filter(|&&byte| byte == 0).count();
+    assert!(zero_bytes < SAMPLE_SIZE / 64);
+    println!("random_test passed!");
+    0
+}
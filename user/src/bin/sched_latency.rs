@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::slice;
+use user_lib::{
+    exit, fork, get_time, mmap, sleep, waitpid, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+
+/// 每次fork都要复制的内存大小
+const LEN: usize = 4 * 1024 * 1024;
+const FORKS: usize = 20;
+const SLEEP_MS: usize = 10;
+const ROUNDS: usize = 50;
+
+/// 不断fork一个占用大量内存的进程，每次fork都要在内核中复制整个地址空间
+fn forker() {
+    let addr = mmap(
+        0,
+        LEN,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let buf = unsafe { slice::from_raw_parts_mut(addr as *mut u8, LEN) };
+    buf.fill(0x5a);
+    for _ in 0..FORKS {
+        let pid = fork();
+        if pid == 0 {
+            exit(0);
+        }
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    }
+    exit(0);
+}
+
+/// 反复睡眠，统计每次醒来比预期晚了多少。
+/// 只打印结果而不设上限：QEMU下的实际延迟取决于宿主机的负载
+fn sleeper() {
+    let mut max = 0;
+    let mut total = 0;
+    for _ in 0..ROUNDS {
+        let start = get_time();
+        sleep(SLEEP_MS);
+        let late = ((get_time() - start) as usize).saturating_sub(SLEEP_MS);
+        max = max.max(late);
+        total += late;
+    }
+    println!(
+        "wakeup latency: max {} ms, avg {} ms over {} rounds",
+        max,
+        total / ROUNDS,
+        ROUNDS
+    );
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let forker_pid = fork();
+    if forker_pid == 0 {
+        forker();
+    }
+    let sleeper_pid = fork();
+    if sleeper_pid == 0 {
+        sleeper();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(sleeper_pid as usize, &mut exit_code), sleeper_pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(waitpid(forker_pid as usize, &mut exit_code), forker_pid);
+    assert_eq!(exit_code, 0);
+    println!("sched_latency passed!");
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::sync::Mutex;
+use user_lib::{exit, fork, shmat, shmdt, shmget, waitpid, EINVAL, IPC_CREAT, IPC_PRIVATE};
+
+const ROUNDS: usize = 10000;
+/// 跳板所在的最高一页，位于用户地址空间之外
+const TRAMPOLINE: usize = usize::MAX - 4096 + 1;
+
+/// 放在共享内存段中的数据，锁本身也位于共享内存中
+#[repr(C)]
+struct Shared {
+    lock: Mutex,
+    counter: usize,
+}
+
+fn add(shared: &mut Shared) {
+    for _ in 0..ROUNDS {
+        shared.lock.lock();
+        shared.counter += 1;
+        shared.lock.unlock();
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let shmid = shmget(IPC_PRIVATE, 4096, IPC_CREAT);
+    assert!(shmid >= 0);
+    let addr = shmat(shmid as usize, 0, 0);
+    assert!(addr > 0);
+    // 用户地址空间之外的地址不能挂接
+    assert_eq!(shmat(shmid as usize, TRAMPOLINE, 0), -EINVAL);
+    // 共享内存段初始为全0，即锁处于未上锁状态
+    let shared = unsafe { &mut *(addr as *mut Shared) };
+    assert_eq!(shared.counter, 0);
+    let pid = fork();
+    if pid == 0 {
+        // fork之后子进程仍然挂接着同一个共享内存段
+        add(shared);
+        exit(0);
+    }
+    add(shared);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("counter = {}", shared.counter);
+    assert_eq!(shared.counter, 2 * ROUNDS);
+    assert_eq!(shmdt(addr as usize), 0);
+    // 最后一个挂接者解除挂接后，共享内存段被释放
+    assert!(shmat(shmid as usize, 0, 0) < 0);
+    println!("shm_test passed!");
+    0
+}
@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, exit, fork, listen, recv, recvfrom, send, sendto, shutdown,
+    socket, waitpid, SockAddrIn, AF_INET, EAGAIN, ECONNREFUSED, ENOTCONN, INADDR_ANY,
+    INADDR_LOOPBACK, SHUT_WR, SOCK_DGRAM, SOCK_NONBLOCK, SOCK_STREAM,
+};
+
+const TCP_PORT: u16 = 5000;
+const UDP_PORT: u16 = 5001;
+const CLOSED_PORT: u16 = 5002;
+const ROUNDS: usize = 16;
+
+/// 子进程作为客户端，逐轮发送并检查回显，最后关闭写方向
+fn tcp_client() -> i32 {
+    let fd = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(connect(fd, &SockAddrIn::new(INADDR_LOOPBACK, TCP_PORT)), 0);
+    let mut buf = [0u8; 64];
+    for round in 0..ROUNDS {
+        let msg = [round as u8; 32];
+        assert_eq!(send(fd, &msg), msg.len() as isize);
+        let mut received = 0;
+        while received < msg.len() {
+            let len = recv(fd, &mut buf[received..msg.len()]);
+            assert!(len > 0);
+            received += len as usize;
+        }
+        assert_eq!(&buf[..msg.len()], &msg[..]);
+    }
+    assert_eq!(shutdown(fd, SHUT_WR), 0);
+    // 服务端看到EOF后关闭连接
+    assert_eq!(recv(fd, &mut buf), 0);
+    close(fd);
+    0
+}
+
+fn tcp_test() {
+    let listener = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    assert_eq!(bind(listener, &SockAddrIn::new(INADDR_ANY, TCP_PORT)), 0);
+    assert_eq!(listen(listener, 4), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(listener);
+        exit(tcp_client());
+    }
+    let mut peer = SockAddrIn::default();
+    let conn = accept(listener, Some(&mut peer));
+    assert!(conn >= 0);
+    let conn = conn as usize;
+    assert_eq!(peer.sin_addr, INADDR_LOOPBACK);
+    assert_ne!(peer.port(), TCP_PORT);
+    let mut buf = [0u8; 64];
+    let mut total = 0;
+    loop {
+        let len = recv(conn, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        assert_eq!(send(conn, &buf[..len as usize]), len);
+        total += len as usize;
+    }
+    assert_eq!(total, ROUNDS * 32);
+    close(conn);
+    close(listener);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("tcp echo over loopback ok.");
+}
+
+fn udp_test() {
+    let server = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(INADDR_LOOPBACK, UDP_PORT)), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(server);
+        let fd = socket(AF_INET, SOCK_DGRAM, 0) as usize;
+        let server_addr = SockAddrIn::new(INADDR_LOOPBACK, UDP_PORT);
+        assert_eq!(sendto(fd, b"ping", &server_addr), 4);
+        let mut buf = [0u8; 16];
+        let mut from = SockAddrIn::default();
+        assert_eq!(recvfrom(fd, &mut buf, Some(&mut from)), 4);
+        assert_eq!(&buf[..4], b"pong");
+        assert_eq!(from, server_addr);
+        close(fd);
+        exit(0);
+    }
+    let mut buf = [0u8; 16];
+    let mut from = SockAddrIn::default();
+    assert_eq!(recvfrom(server, &mut buf, Some(&mut from)), 4);
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(from.sin_addr, INADDR_LOOPBACK);
+    assert_eq!(sendto(server, b"pong", &from), 4);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(server);
+    println!("udp over loopback ok.");
+}
+
+fn error_test() {
+    let fd = socket(AF_INET, SOCK_STREAM, 0) as usize;
+    let mut buf = [0u8; 16];
+    assert_eq!(recv(fd, &mut buf), -ENOTCONN);
+    assert_eq!(
+        connect(fd, &SockAddrIn::new(INADDR_LOOPBACK, CLOSED_PORT)),
+        -ECONNREFUSED
+    );
+    close(fd);
+
+    let fd = socket(AF_INET, SOCK_DGRAM | SOCK_NONBLOCK, 0) as usize;
+    assert_eq!(bind(fd, &SockAddrIn::new(INADDR_LOOPBACK, 0)), 0);
+    assert_eq!(recv(fd, &mut buf), -EAGAIN);
+    close(fd);
+    println!("socket errors ok.");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    tcp_test();
+    udp_test();
+    error_test();
+    println!("socket_test passed!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use core::str;
+use user_lib::{
+    close, exit, fork, getpid, strace, strace_read, waitpid, yield_, EBADF, ESRCH, STRACE_BUFFER,
+    STRACE_OFF,
+};
+
+/// 不存在的文件描述符
+const BAD_FD: usize = 100;
+/// 不是本进程子进程的pid
+const NOT_CHILD: usize = 9999;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 跟踪自己，记录中应有调用名、参数与返回值
+    assert_eq!(strace(0, STRACE_BUFFER), 0);
+    let pid = getpid();
+    assert_eq!(close(BAD_FD), -EBADF);
+    let mut buf = [0u8; 1024];
+    let len = strace_read(0, &mut buf);
+    assert!(len > 0);
+    let trace = str::from_utf8(&buf[..len as usize]).unwrap();
+    print!("{}", trace);
+    let getpid_line = format!("getpid() = {} <", pid);
+    assert!(trace.contains(getpid_line.as_str()));
+    assert!(trace.contains("close(100) = -9 <"));
+    assert_eq!(strace(0, STRACE_OFF), 0);
+    println!("trace self ok.");
+
+    // 只能跟踪自己的子进程
+    assert_eq!(strace(NOT_CHILD, STRACE_BUFFER), -ESRCH);
+
+    // 子进程退出后、被回收之前，父进程仍能读出它的记录
+    let child = fork();
+    if child == 0 {
+        assert_eq!(strace(0, STRACE_BUFFER), 0);
+        getpid();
+        exit(7);
+    }
+    let mut len = 0;
+    loop {
+        let n = strace_read(child as usize, &mut buf[len..]);
+        assert!(n >= 0);
+        len += n as usize;
+        if str::from_utf8(&buf[..len]).unwrap().contains("exit(7) = ?") {
+            break;
+        }
+        yield_();
+    }
+    let trace = str::from_utf8(&buf[..len]).unwrap();
+    print!("{}", trace);
+    assert!(trace.contains("getpid() = "));
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 7);
+    assert_eq!(strace_read(child as usize, &mut buf), -ESRCH);
+    println!("strace_test passed!");
+    0
+}
@@ -38,7 +38,7 @@ pub fn main() -> i32 {
                     // pid = 0，说明是子进程
                     if pid == 0 {
                         // child process
-                        if exec(line.as_str()) == -1 {
+                        if exec(line.as_str()) < 0 {
                             println!("Error when executing!");
                             return -4;
                         }
@@ -3,41 +3,102 @@
 
 #[macro_use]
 extern crate user_lib;
+extern crate alloc;
 
 static TESTS: &[&str] = &[
+    "dev_test\0",
+    "errno_test\0",
     "exit\0",
     "fantastic_text\0",
     "forktest\0",
     "forktest2\0",
     "forktest_simple\0",
+    "futex_test\0",
     "hello_world\0",
     "matrix\0",
+    "mmap_test\0",
+    "mq_demo\0",
+    "poll_test\0",
+    "random_test\0",
+    "sched_latency\0",
+    "shm_test\0",
+    "socket_test\0",
     "sleep\0",
     "sleep_simple\0",
     "stack_overflow\0",
+    "strace_test\0",
     "yield\0",
 ];
 
-use user_lib::{exec, fork, waitpid};
+/// 预期以非0退出码结束的测例，stack_overflow因页错误被内核杀死
+static EXPECTED_EXIT_CODES: &[(&str, i32)] = &[("stack_overflow\0", -2)];
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exec, fork, open, read, waitpid, O_RDONLY};
+
+/// 内核命令行中tests=指定的测例，没有指定时返回None
+fn selected_tests() -> Option<Vec<String>> {
+    let fd = open("/proc/cmdline\0", O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 1024];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let cmdline = core::str::from_utf8(&buf[..len.max(0) as usize]).ok()?;
+    let tests = cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("tests="))?;
+    Some(
+        tests
+            .split(',')
+            .filter(|test| !test.is_empty())
+            .map(|test| {
+                let mut path = String::from(test);
+                path.push('\0');
+                path
+            })
+            .collect(),
+    )
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
-    for test in TESTS {
+    let tests =
+        selected_tests().unwrap_or_else(|| TESTS.iter().map(|&test| String::from(test)).collect());
+    let mut failed = 0;
+    for test in tests.iter() {
         println!("Usertests: Running {}", test);
         let pid = fork();
         if pid == 0 {
-            exec(*test);
+            exec(test);
             panic!("unreachable!");
         } else {
             let mut exit_code: i32 = Default::default();
             let wait_pid = waitpid(pid as usize, &mut exit_code);
             assert_eq!(pid, wait_pid);
+            let expected = EXPECTED_EXIT_CODES
+                .iter()
+                .find(|(name, _)| *name == test.as_str())
+                .map_or(0, |&(_, code)| code);
+            // 预期之内的退出码显示为绿色，否则显示为红色并记为失败
+            let color = if exit_code == expected {
+                32
+            } else {
+                failed += 1;
+                31
+            };
             println!(
-                "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-                test, pid, exit_code
+                "\x1b[{}mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
+                color, test, pid, exit_code
             );
         }
     }
+    if failed > 0 {
+        println!("Usertests: {} of {} tests failed!", failed, tests.len());
+        return 1;
+    }
     println!("Usertests passed!");
     0
 }
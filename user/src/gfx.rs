@@ -0,0 +1,287 @@
+//! 基于/dev/fb0的简单绘图库：把帧缓冲映射到地址空间中，
+//! 提供画点、填充矩形与8x8点阵字体的文字绘制，画完后调用flush刷新屏幕
+use crate::{close, ioctl, mmap, munmap, open, MAP_SHARED, O_RDWR, PROT_READ, PROT_WRITE};
+use core::slice;
+
+pub const FBIOGET_VSCREENINFO: usize = 0x4600;
+/// ApolloOS特有的命令：arg为0时刷新整个屏幕，否则指向一个FbRect，只刷新其中的区域
+pub const FBIOFLUSH: usize = 0x46f0;
+
+/// FBIOFLUSH的参数
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FbRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 像素格式为0x00RRGGBB
+pub const fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+pub const BLACK: u32 = rgb(0, 0, 0);
+pub const WHITE: u32 = rgb(255, 255, 255);
+pub const RED: u32 = rgb(255, 0, 0);
+pub const GREEN: u32 = rgb(0, 255, 0);
+pub const BLUE: u32 = rgb(0, 0, 255);
+pub const YELLOW: u32 = rgb(255, 255, 0);
+pub const CYAN: u32 = rgb(0, 255, 255);
+pub const MAGENTA: u32 = rgb(255, 0, 255);
+
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 8;
+
+/// 映射到当前进程地址空间中的帧缓冲
+pub struct Framebuffer {
+    fd: usize,
+    pixels: &'static mut [u32],
+    width: usize,
+    height: usize,
+}
+
+impl Framebuffer {
+    /// 打开/dev/fb0并映射整个帧缓冲，没有显示设备时返回-ENOENT
+    pub fn open() -> Result<Self, isize> {
+        let fd = open("/dev/fb0\0", O_RDWR);
+        if fd < 0 {
+            return Err(fd);
+        }
+        let fd = fd as usize;
+        // struct fb_var_screeninfo共40个u32，开头依次是横向与纵向的分辨率
+        let mut info = [0u32; 40];
+        let ret = ioctl(fd, FBIOGET_VSCREENINFO, info.as_mut_ptr() as usize);
+        if ret < 0 {
+            close(fd);
+            return Err(ret);
+        }
+        let (width, height) = (info[0] as usize, info[1] as usize);
+        let len = width * height * 4;
+        let addr = mmap(0, len, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+        if addr < 0 {
+            close(fd);
+            return Err(addr);
+        }
+        Ok(Self {
+            fd,
+            pixels: unsafe { slice::from_raw_parts_mut(addr as *mut u32, width * height) },
+            width,
+            height,
+        })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// 按行存放的全部像素
+    pub fn pixels(&mut self) -> &mut [u32] {
+        self.pixels
+    }
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+    /// 画一个点，超出屏幕的部分被忽略
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+    /// 填充左上角为(x, y)的矩形，超出屏幕的部分被裁剪
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+        if x >= x_end {
+            return;
+        }
+        for row in y..y_end {
+            self.pixels[row * self.width + x..row * self.width + x_end].fill(color);
+        }
+    }
+    pub fn clear(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+    /// 以scale倍的大小在(x, y)处画一个字符，bg为None时背景透明。不可显示的字符画成'?'
+    pub fn draw_char(
+        &mut self,
+        x: usize,
+        y: usize,
+        ch: char,
+        fg: u32,
+        bg: Option<u32>,
+        scale: usize,
+    ) {
+        let glyph = glyph(ch);
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                let color = if bits & (1 << col) != 0 {
+                    fg
+                } else if let Some(bg) = bg {
+                    bg
+                } else {
+                    continue;
+                };
+                self.fill_rect(x + col * scale, y + row * scale, scale, scale, color);
+            }
+        }
+    }
+    /// 从(x, y)开始画一行文字，'\n'换到下一行的开头。返回文字最后结束处的横坐标
+    pub fn draw_text(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        fg: u32,
+        bg: Option<u32>,
+        scale: usize,
+    ) -> usize {
+        let (mut cx, mut cy) = (x, y);
+        for ch in text.chars() {
+            if ch == '\n' {
+                cx = x;
+                cy += GLYPH_HEIGHT * scale;
+                continue;
+            }
+            self.draw_char(cx, cy, ch, fg, bg, scale);
+            cx += GLYPH_WIDTH * scale;
+        }
+        cx
+    }
+    /// 将整个帧缓冲刷新到屏幕上
+    pub fn flush(&self) -> isize {
+        ioctl(self.fd, FBIOFLUSH, 0)
+    }
+    /// 只刷新左上角为(x, y)的矩形区域
+    pub fn flush_rect(&self, x: usize, y: usize, width: usize, height: usize) -> isize {
+        let rect = FbRect {
+            x: x as u32,
+            y: y as u32,
+            width: width as u32,
+            height: height as u32,
+        };
+        ioctl(self.fd, FBIOFLUSH, &rect as *const FbRect as usize)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        munmap(self.pixels.as_ptr() as usize, self.pixels.len() * 4);
+        close(self.fd);
+    }
+}
+
+/// 字符的8x8点阵，每个字节为一行，最低位是最左边的像素
+pub fn glyph(ch: char) -> &'static [u8; 8] {
+    let code = ch as usize;
+    if (0x20..0x7f).contains(&code) {
+        &FONT[code - 0x20]
+    } else {
+        &FONT['?' as usize - 0x20]
+    }
+}
+
+/// 可显示的ASCII字符0x20..0x7f的点阵，取自公有领域的font8x8_basic
+static FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
@@ -5,10 +5,13 @@
 
 #[macro_use]
 pub mod console;
+pub mod gfx;
 mod lang_items;
+pub mod sync;
 mod syscall;
 
 use buddy_system_allocator::LockedHeap;
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
@@ -39,6 +42,246 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+/// 与内核中的定义一致，用于传递超时时间
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ms(ms: usize) -> Self {
+        Self {
+            tv_sec: ms / 1000,
+            tv_nsec: (ms % 1000) * 1_000_000,
+        }
+    }
+}
+
+/// 与内核中的定义一致，描述消息队列的属性
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MqAttr {
+    pub mq_flags: usize,
+    pub mq_maxmsg: usize,
+    pub mq_msgsize: usize,
+    pub mq_curmsgs: usize,
+    pub reserved: [usize; 4],
+}
+
+impl MqAttr {
+    pub fn new(maxmsg: usize, msgsize: usize) -> Self {
+        Self {
+            mq_maxmsg: maxmsg,
+            mq_msgsize: msgsize,
+            ..Default::default()
+        }
+    }
+}
+
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 1 << 0;
+pub const O_RDWR: u32 = 1 << 1;
+pub const O_CREAT: u32 = 1 << 6;
+pub const O_EXCL: u32 = 1 << 7;
+pub const O_NONBLOCK: u32 = 1 << 11;
+
+pub const AT_FDCWD: isize = -100;
+
+/// 与Linux的struct winsize布局相同
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+pub const TIOCGWINSZ: usize = 0x5413;
+
+/// 与Linux的struct input_event布局相同，从/dev/input/event*中读出
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InputEvent {
+    pub sec: u64,
+    pub usec: u64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+
+/// 读取输入设备名的ioctl命令，len为缓冲区长度
+pub const fn eviocgname(len: usize) -> usize {
+    0x8000_4506 | len << 16
+}
+
+/// 与Linux的struct pollfd布局相同
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: u16) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+pub const POLLIN: u16 = 0x001;
+pub const POLLOUT: u16 = 0x004;
+pub const POLLERR: u16 = 0x008;
+pub const POLLHUP: u16 = 0x010;
+pub const POLLNVAL: u16 = 0x020;
+
+/// 与Linux的struct sockaddr_in布局相同，端口与地址均为网络字节序
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SockAddrIn {
+    pub sin_family: u16,
+    pub sin_port: u16,
+    pub sin_addr: [u8; 4],
+    pub sin_zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            sin_family: AF_INET as u16,
+            sin_port: port.to_be(),
+            sin_addr: addr,
+            sin_zero: [0; 8],
+        }
+    }
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.sin_port)
+    }
+}
+
+pub const AF_INET: usize = 2;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOCK_NONBLOCK: usize = 0o4000;
+pub const SHUT_RD: usize = 0;
+pub const SHUT_WR: usize = 1;
+pub const SHUT_RDWR: usize = 2;
+pub const INADDR_ANY: [u8; 4] = [0, 0, 0, 0];
+pub const INADDR_LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+
+pub const EFD_SEMAPHORE: u32 = 1;
+pub const EFD_NONBLOCK: u32 = 0o4000;
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+pub const GRND_NONBLOCK: u32 = 1;
+pub const GRND_RANDOM: u32 = 2;
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const EAGAIN: isize = 11;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EEXIST: isize = 17;
+pub const EINVAL: isize = 22;
+pub const ENOTTY: isize = 25;
+pub const ENAMETOOLONG: isize = 36;
+pub const ENOSYS: isize = 38;
+pub const EMSGSIZE: isize = 90;
+pub const ENOTCONN: isize = 107;
+pub const ETIMEDOUT: isize = 110;
+pub const ECONNREFUSED: isize = 111;
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const SHM_RDONLY: usize = 0o10000;
+
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+pub const SYSLOG_ACTION_READ_ALL: usize = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+pub const SYSLOG_ACTION_CLEAR: usize = 5;
+pub const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+pub const STRACE_OFF: usize = 0;
+pub const STRACE_CONSOLE: usize = 1;
+pub const STRACE_BUFFER: usize = 2;
+const STRACE_READ: usize = 3;
+
+/// 新建一个计数器初值为initval的eventfd，返回其文件描述符
+pub fn eventfd(initval: u32, flags: u32) -> isize {
+    sys_eventfd2(initval, flags)
+}
+
+/// 从eventfd中读出计数器的值（信号量模式下为1），计数器为0时阻塞
+pub fn eventfd_read(fd: usize, value: &mut u64) -> isize {
+    let mut buf = [0u8; 8];
+    let ret = sys_read(fd, &mut buf);
+    if ret == 8 {
+        *value = u64::from_ne_bytes(buf);
+        0
+    } else {
+        ret
+    }
+}
+
+/// 将value加到eventfd的计数器上
+pub fn eventfd_write(fd: usize, value: u64) -> isize {
+    match sys_write(fd, &value.to_ne_bytes()) {
+        8 => 0,
+        ret => ret,
+    }
+}
+
+/// 等待fds中任意一个文件描述符就绪，timeout_ms为None时无限等待。
+/// 返回就绪的描述符数目，超时返回0
+pub fn ppoll(fds: &mut [PollFd], timeout_ms: Option<usize>) -> isize {
+    let timeout = timeout_ms.map(TimeSpec::from_ms);
+    let timeout_ptr = match timeout.as_ref() {
+        Some(timeout) => timeout as *const TimeSpec,
+        None => core::ptr::null(),
+    };
+    sys_ppoll(fds, timeout_ptr)
+}
+
+/// 打开path处的文件，path须以'\0'结尾，目前只能打开/dev下的设备与/proc下的只读文件。返回文件描述符
+pub fn open(path: &str, flags: u32) -> isize {
+    sys_openat(AT_FDCWD, path, flags, 0)
+}
+
+/// 对设备执行控制命令cmd，arg通常是指向参数结构的指针
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -59,6 +302,34 @@ pub fn get_time() -> isize {
     sys_get_time()
 }
 
+/// 读取时钟clockid的当前时间：CLOCK_REALTIME为自1970年起的墙上时间，
+/// CLOCK_MONOTONIC为开机以来的时间
+pub fn clock_gettime(clockid: usize, tp: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clockid, tp as *mut _)
+}
+
+/// 读取或清空内核日志缓冲区，action为SYSLOG_ACTION_*。
+/// 读取时把最后的至多buf.len()个字节复制到buf，返回复制的字节数
+pub fn syslog(action: usize, buf: &mut [u8]) -> isize {
+    sys_syslog(action, buf.as_mut_ptr(), buf.len())
+}
+
+/// 设置进程pid的系统调用跟踪，mode为STRACE_OFF、STRACE_CONSOLE或STRACE_BUFFER。
+/// pid为0表示自己，否则须是自己的子进程
+pub fn strace(pid: usize, mode: usize) -> isize {
+    sys_strace(mode, pid, core::ptr::null_mut(), 0)
+}
+
+/// 从进程pid的跟踪缓冲区取出记录，每条一行，返回复制的字节数
+pub fn strace_read(pid: usize, buf: &mut [u8]) -> isize {
+    sys_strace(STRACE_READ, pid, buf.as_mut_ptr(), buf.len())
+}
+
+/// 用内核CSPRNG产生的随机字节填满buf，返回填写的字节数
+pub fn getrandom(buf: &mut [u8], flags: u32) -> isize {
+    sys_getrandom(buf, flags)
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
@@ -72,30 +343,30 @@ pub fn exec(path: &str) -> isize {
 }
 
 /// 等待任意一个子进程结束
-/// 返回结束的子进程的pid或-1（没有要等待的子进程）
+/// 返回结束的子进程的pid或-ECHILD（没有要等待的子进程）
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
-            // -2表示都没有结束，还要再等等
-            -2 => {
+            // -EAGAIN表示都没有结束，还要再等等
+            ret if ret == -EAGAIN => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD or a real pid
             exit_pid => return exit_pid,
         }
     }
 }
 
 /// 等待一个进程标识符为pid的子进程结束
-/// 返回进程的pid或-1（表示进程不存在）
+/// 返回进程的pid或-ECHILD（表示进程不存在）
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(pid as isize, exit_code as *mut _) {
             // 要等待的子进程存在但它尚未退出，调用yield交出CPU使用权
-            -2 => {
+            ret if ret == -EAGAIN => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD or a real pid
             exit_pid => return exit_pid,
         }
     }
@@ -106,4 +377,198 @@ pub fn sleep(period_ms: usize) {
     while sys_get_time() < start + period_ms as isize {
         sys_yield();
     }
-}
\ No newline at end of file
+}
+
+/// 若*futex == val则阻塞，直到被唤醒或超时
+/// 返回0表示被唤醒，-EAGAIN表示值不匹配，-ETIMEDOUT表示超时
+pub fn futex_wait(futex: &AtomicU32, val: u32, timeout_ms: Option<usize>) -> isize {
+    let timeout = timeout_ms.map(TimeSpec::from_ms);
+    let timeout_ptr = match timeout.as_ref() {
+        Some(timeout) => timeout as *const TimeSpec,
+        None => core::ptr::null(),
+    };
+    sys_futex(futex as *const AtomicU32 as *const u32, FUTEX_WAIT, val, timeout_ptr)
+}
+
+/// 唤醒至多count个在futex上等待的进程，返回唤醒的数目
+pub fn futex_wake(futex: &AtomicU32, count: u32) -> isize {
+    sys_futex(futex as *const AtomicU32 as *const u32, FUTEX_WAKE, count, core::ptr::null())
+}
+
+/// 获取（或新建）key对应的共享内存段，返回共享内存段的id
+pub fn shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    sys_shmget(key, size, shmflg)
+}
+
+/// 将共享内存段挂接到地址空间中，shmaddr为0时由内核选择地址，返回挂接地址
+pub fn shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    sys_shmat(shmid, shmaddr, shmflg)
+}
+
+/// 解除shmaddr处共享内存段的挂接
+pub fn shmdt(shmaddr: usize) -> isize {
+    sys_shmdt(shmaddr)
+}
+
+/// 映射len字节的内存，返回映射的起始地址。匿名映射（MAP_PRIVATE | MAP_ANONYMOUS）得到清零的内存，
+/// 否则以MAP_SHARED映射fd从offset开始的内容，目前只有/dev/fb0这样的设备支持
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(addr, len, prot, flags, fd, offset)
+}
+
+/// 解除addr处由mmap建立的一整个映射
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+/// 打开（或在flags含O_CREAT时新建）名为name的消息队列，name须以'/'开头、以'\0'结尾，
+/// attr仅在新建时使用，为None时使用默认属性。返回队列描述符
+pub fn mq_open(name: &str, flags: u32, attr: Option<&MqAttr>) -> isize {
+    let attr_ptr = match attr {
+        Some(attr) => attr as *const MqAttr,
+        None => core::ptr::null(),
+    };
+    sys_mq_open(name, flags, attr_ptr)
+}
+
+/// 关闭消息队列描述符
+pub fn mq_close(mqdes: usize) -> isize {
+    sys_close(mqdes)
+}
+
+/// 删除名为name的消息队列，已打开的描述符仍可使用
+pub fn mq_unlink(name: &str) -> isize {
+    sys_mq_unlink(name)
+}
+
+/// 发送一条优先级为prio的消息，队列已满时阻塞
+pub fn mq_send(mqdes: usize, msg: &[u8], prio: u32) -> isize {
+    sys_mq_timedsend(mqdes, msg, prio, core::ptr::null())
+}
+
+/// 同mq_send，但最多阻塞到get_time()时钟上的deadline_ms时刻，超时返回-ETIMEDOUT
+pub fn mq_timedsend(mqdes: usize, msg: &[u8], prio: u32, deadline_ms: usize) -> isize {
+    let deadline = TimeSpec::from_ms(deadline_ms);
+    sys_mq_timedsend(mqdes, msg, prio, &deadline as *const _)
+}
+
+/// 接收优先级最高的消息，队列为空时阻塞，buf不能短于队列的mq_msgsize。
+/// 返回消息长度，prio不为None时写入消息的优先级
+pub fn mq_receive(mqdes: usize, buf: &mut [u8], prio: Option<&mut u32>) -> isize {
+    let prio_ptr = match prio {
+        Some(prio) => prio as *mut u32,
+        None => core::ptr::null_mut(),
+    };
+    sys_mq_timedreceive(mqdes, buf, prio_ptr, core::ptr::null())
+}
+
+/// 同mq_receive，但最多阻塞到get_time()时钟上的deadline_ms时刻，超时返回-ETIMEDOUT
+pub fn mq_timedreceive(
+    mqdes: usize,
+    buf: &mut [u8],
+    prio: Option<&mut u32>,
+    deadline_ms: usize,
+) -> isize {
+    let prio_ptr = match prio {
+        Some(prio) => prio as *mut u32,
+        None => core::ptr::null_mut(),
+    };
+    let deadline = TimeSpec::from_ms(deadline_ms);
+    sys_mq_timedreceive(mqdes, buf, prio_ptr, &deadline as *const _)
+}
+
+/// 读取消息队列的属性
+pub fn mq_getattr(mqdes: usize, attr: &mut MqAttr) -> isize {
+    sys_mq_getsetattr(mqdes, core::ptr::null(), attr as *mut _)
+}
+
+/// 设置消息队列描述符的mq_flags（仅O_NONBLOCK有效）
+pub fn mq_setattr(mqdes: usize, attr: &MqAttr) -> isize {
+    sys_mq_getsetattr(mqdes, attr as *const _, core::ptr::null_mut())
+}
+
+/// 以消息的形式发送一个Copy类型的值
+pub fn mq_send_msg<T: Copy>(mqdes: usize, msg: &T, prio: u32) -> isize {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(msg as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    mq_send(mqdes, bytes, prio)
+}
+
+/// 接收一条由mq_send_msg发送的T类型消息，buf用作接收缓冲区，
+/// 消息长度与T不符时返回Err(消息长度或错误码)
+pub fn mq_receive_msg<T: Copy>(
+    mqdes: usize,
+    buf: &mut [u8],
+    prio: Option<&mut u32>,
+) -> Result<T, isize> {
+    let len = mq_receive(mqdes, buf, prio);
+    if len != core::mem::size_of::<T>() as isize {
+        return Err(len);
+    }
+    Ok(unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const T) })
+}
+
+/// 新建一个IPv4套接字，socket_type为SOCK_STREAM或SOCK_DGRAM，可以或上SOCK_NONBLOCK
+pub fn socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    sys_socket(domain, socket_type, protocol)
+}
+
+/// 将套接字绑定到本地地址，端口为0时由内核分配
+pub fn bind(sockfd: usize, addr: &SockAddrIn) -> isize {
+    sys_bind(sockfd, addr as *const _, core::mem::size_of::<SockAddrIn>())
+}
+
+pub fn listen(sockfd: usize, backlog: usize) -> isize {
+    sys_listen(sockfd, backlog)
+}
+
+/// 等待一个连接，返回新连接的描述符，addr不为None时写入对端地址
+pub fn accept(sockfd: usize, addr: Option<&mut SockAddrIn>) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    let addr_ptr = match addr {
+        Some(addr) => addr as *mut SockAddrIn,
+        None => core::ptr::null_mut(),
+    };
+    sys_accept(sockfd, addr_ptr, &mut addrlen as *mut _)
+}
+
+pub fn connect(sockfd: usize, addr: &SockAddrIn) -> isize {
+    sys_connect(sockfd, addr as *const _, core::mem::size_of::<SockAddrIn>())
+}
+
+/// 向已连接的对端发送数据
+pub fn send(sockfd: usize, buf: &[u8]) -> isize {
+    sys_sendto(sockfd, buf, 0, core::ptr::null(), 0)
+}
+
+/// 向addr发送一个数据报
+pub fn sendto(sockfd: usize, buf: &[u8], addr: &SockAddrIn) -> isize {
+    sys_sendto(
+        sockfd,
+        buf,
+        0,
+        addr as *const _,
+        core::mem::size_of::<SockAddrIn>(),
+    )
+}
+
+/// 接收数据，流套接字在对方关闭后返回0
+pub fn recv(sockfd: usize, buf: &mut [u8]) -> isize {
+    sys_recvfrom(sockfd, buf, 0, core::ptr::null_mut(), core::ptr::null_mut())
+}
+
+/// 接收数据，addr不为None时写入发送方地址
+pub fn recvfrom(sockfd: usize, buf: &mut [u8], addr: Option<&mut SockAddrIn>) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    let addr_ptr = match addr {
+        Some(addr) => addr as *mut SockAddrIn,
+        None => core::ptr::null_mut(),
+    };
+    sys_recvfrom(sockfd, buf, 0, addr_ptr, &mut addrlen as *mut _)
+}
+
+/// 关闭连接的读方向（SHUT_RD）、写方向（SHUT_WR）或两者（SHUT_RDWR）
+pub fn shutdown(sockfd: usize, how: usize) -> isize {
+    sys_shutdown(sockfd, how)
+}
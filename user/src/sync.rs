@@ -0,0 +1,47 @@
+use crate::{futex_wait, futex_wake};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+const UNLOCKED: u32 = 0;
+/// 已上锁，没有等待者
+const LOCKED: u32 = 1;
+/// 已上锁，且可能有等待者，解锁时需要进入内核唤醒
+const CONTENDED: u32 = 2;
+
+/// 基于futex的互斥锁：无竞争时完全在用户态完成加锁解锁，
+/// 仅在发生竞争时才通过futex系统调用进入内核
+pub struct Mutex {
+    state: AtomicU32,
+}
+
+impl Mutex {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+        }
+    }
+    pub fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+    pub fn lock(&self) {
+        if self.try_lock() {
+            return;
+        }
+        // 标记为有竞争后在内核中等待，被唤醒后重新尝试
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex_wait(&self.state, CONTENDED, None);
+        }
+    }
+    pub fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl Default for Mutex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,15 +1,45 @@
 // user/src/syscall.rs
 use core::arch::asm;
 
+use crate::{MqAttr, PollFd, SockAddrIn, TimeSpec};
+
+const SYSCALL_EVENTFD2: usize = 19;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PPOLL: usize = 73;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_SYSLOG: usize = 116;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_MQ_OPEN: usize = 180;
+const SYSCALL_MQ_UNLINK: usize = 181;
+const SYSCALL_MQ_TIMEDSEND: usize = 182;
+const SYSCALL_MQ_TIMEDRECEIVE: usize = 183;
+const SYSCALL_MQ_GETSETATTR: usize = 185;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_SOCKET: usize = 198;
+const SYSCALL_BIND: usize = 200;
+const SYSCALL_LISTEN: usize = 201;
+const SYSCALL_ACCEPT: usize = 202;
+const SYSCALL_CONNECT: usize = 203;
+const SYSCALL_SENDTO: usize = 206;
+const SYSCALL_RECVFROM: usize = 207;
+const SYSCALL_SHUTDOWN: usize = 210;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_STRACE: usize = 1000;
 
 // RISC-V 寄存器编号从 0~31,表示为 x0~x31
 // x10~x17:对应 a0~a7
@@ -31,6 +61,43 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+/// 最多传递6个参数的系统调用，用于参数超过3个的情形
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+        "ecall",
+        inlateout("x10") args[0] => ret,
+        in("x11") args[1],
+        in("x12") args[2],
+        in("x13") args[3],
+        in("x14") args[4],
+        in("x15") args[5],
+        in("x17") id
+        );
+    }
+    ret
+}
+
+pub fn sys_eventfd2(initval: u32, flags: u32) -> isize {
+    syscall(SYSCALL_EVENTFD2, [initval as usize, flags as usize, 0])
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+pub fn sys_openat(dirfd: isize, path: &str, flags: u32, mode: usize) -> isize {
+    syscall6(
+        SYSCALL_OPENAT,
+        [dirfd as usize, path.as_ptr() as usize, flags as usize, mode, 0, 0],
+    )
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -42,6 +109,13 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_ppoll(fds: &mut [PollFd], timeout: *const TimeSpec) -> isize {
+    syscall6(
+        SYSCALL_PPOLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout as usize, 0, 0, 0],
+    )
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
@@ -70,3 +144,176 @@ pub fn sys_exec(path: &str) -> isize {
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
 }
+
+pub fn sys_getrandom(buf: &mut [u8], flags: u32) -> isize {
+    syscall(
+        SYSCALL_GETRANDOM,
+        [buf.as_mut_ptr() as usize, buf.len(), flags as usize],
+    )
+}
+
+pub fn sys_futex(uaddr: *const u32, futex_op: usize, val: u32, timeout: *const TimeSpec) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as usize, futex_op, val as usize, timeout as usize, 0, 0],
+    )
+}
+
+pub fn sys_clock_gettime(clockid: usize, tp: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clockid, tp as usize, 0])
+}
+
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> isize {
+    syscall(SYSCALL_SYSLOG, [action, buf as usize, len])
+}
+
+pub fn sys_strace(op: usize, pid: usize, buf: *mut u8, len: usize) -> isize {
+    syscall6(SYSCALL_STRACE, [op, pid, buf as usize, len, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, shmflg])
+}
+
+pub fn sys_shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, shmaddr, shmflg])
+}
+
+pub fn sys_shmdt(shmaddr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [shmaddr, 0, 0])
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0])
+}
+
+pub fn sys_mq_open(name: &str, oflag: u32, attr: *const MqAttr) -> isize {
+    syscall6(
+        SYSCALL_MQ_OPEN,
+        [name.as_ptr() as usize, oflag as usize, 0, attr as usize, 0, 0],
+    )
+}
+
+pub fn sys_mq_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MQ_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_timedsend(
+    mqdes: usize,
+    msg: &[u8],
+    prio: u32,
+    abs_timeout: *const TimeSpec,
+) -> isize {
+    syscall6(
+        SYSCALL_MQ_TIMEDSEND,
+        [
+            mqdes,
+            msg.as_ptr() as usize,
+            msg.len(),
+            prio as usize,
+            abs_timeout as usize,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_timedreceive(
+    mqdes: usize,
+    msg: &mut [u8],
+    prio: *mut u32,
+    abs_timeout: *const TimeSpec,
+) -> isize {
+    syscall6(
+        SYSCALL_MQ_TIMEDRECEIVE,
+        [
+            mqdes,
+            msg.as_mut_ptr() as usize,
+            msg.len(),
+            prio as usize,
+            abs_timeout as usize,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_getsetattr(mqdes: usize, newattr: *const MqAttr, oldattr: *mut MqAttr) -> isize {
+    syscall(
+        SYSCALL_MQ_GETSETATTR,
+        [mqdes, newattr as usize, oldattr as usize],
+    )
+}
+
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    syscall(SYSCALL_SOCKET, [domain, socket_type, protocol])
+}
+
+pub fn sys_bind(sockfd: usize, addr: *const SockAddrIn, addrlen: usize) -> isize {
+    syscall(SYSCALL_BIND, [sockfd, addr as usize, addrlen])
+}
+
+pub fn sys_listen(sockfd: usize, backlog: usize) -> isize {
+    syscall(SYSCALL_LISTEN, [sockfd, backlog, 0])
+}
+
+pub fn sys_accept(sockfd: usize, addr: *mut SockAddrIn, addrlen: *mut u32) -> isize {
+    syscall(SYSCALL_ACCEPT, [sockfd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_connect(sockfd: usize, addr: *const SockAddrIn, addrlen: usize) -> isize {
+    syscall(SYSCALL_CONNECT, [sockfd, addr as usize, addrlen])
+}
+
+pub fn sys_sendto(
+    sockfd: usize,
+    buf: &[u8],
+    flags: usize,
+    dest_addr: *const SockAddrIn,
+    addrlen: usize,
+) -> isize {
+    syscall6(
+        SYSCALL_SENDTO,
+        [
+            sockfd,
+            buf.as_ptr() as usize,
+            buf.len(),
+            flags,
+            dest_addr as usize,
+            addrlen,
+        ],
+    )
+}
+
+pub fn sys_recvfrom(
+    sockfd: usize,
+    buf: &mut [u8],
+    flags: usize,
+    src_addr: *mut SockAddrIn,
+    addrlen: *mut u32,
+) -> isize {
+    syscall6(
+        SYSCALL_RECVFROM,
+        [
+            sockfd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            flags,
+            src_addr as usize,
+            addrlen as usize,
+        ],
+    )
+}
+
+pub fn sys_shutdown(sockfd: usize, how: usize) -> isize {
+    syscall(SYSCALL_SHUTDOWN, [sockfd, how, 0])
+}